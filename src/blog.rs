@@ -1,255 +1,3582 @@
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
 use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
 use quick_xml::events::Event;
-use reqwest::Client;
-use reqwest::StatusCode;
+use reqwest::{Client, NoProxy, Proxy, StatusCode};
 use rusqlite::Connection;
 use scraper::{Html, Selector};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Instant;
 use thiserror::Error;
+use tracing::{info, warn};
 use url::Url;
 
-use crate::db;
+use crate::classify::{self, Boilerplate, TermMatcher, UrlFilter};
+use crate::config::{AuthConfig, BlogConfig, Config, DuplicateTitles, ScoringConfig};
+use crate::db::{self, RunCache};
+use crate::export;
+use crate::fulltext;
+use crate::robots::{self, Robots};
 
-const MAX_NEW_PER_SITE: usize = 5;
+const HTTPS_PROBE_INTERVAL_DAYS: i64 = 7;
+// Tries per request for transient failures, and the first retry's delay
+const MAX_ATTEMPTS: usize = 3;
+const RETRY_BASE_MS: u64 = 1000;
+// Backoff for a domain whose certificate fails verification
+const TLS_RETRY_DAYS: i64 = 30;
+// Newest sitemap entries checked every run before resuming at the cursor
+const SITEMAP_HEAD_ENTRIES: usize = 20;
+const MAX_SITE_TAGS: usize = 15;
+const MAX_TAG_CHARS: usize = 40;
+// Extraction drift: inserts needed to judge a run, and the drop below the trailing
+// average that is reported
+const DRIFT_MIN_SAMPLES: usize = 3;
+const DRIFT_DROP: f64 = 0.5;
+// Weight of the current run in the trailing average
+const DRIFT_SMOOTHING: f64 = 0.3;
+// Pages above this size are first judged on their <head> before a full parse
+const LARGE_PAGE_BYTES: usize = 256 * 1024;
+// Feed autodiscovery: <link rel="alternate"> types, then paths tried without one
+const FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/rdf+xml",
+];
+const FEED_PATHS: &[&str] = &["/feed", "/rss", "/atom.xml", "/index.rdf"];
+// Child sitemaps of a sitemap index fetched per run, newest lastmod first
+const MAX_CHILD_SITEMAPS: usize = 10;
 
 #[derive(Debug, Error)]
 pub enum CrawlError {
     #[error("HTTP status error: {status} {url}")]
     HttpStatus { status: StatusCode, url: String },
+    #[error("credentials rejected: {status} {url}")]
+    AuthRejected { status: StatusCode, url: String },
+    #[error("TLS error: {url}: {detail}")]
+    Tls { url: String, detail: String },
+    // The host answered 429 earlier in this run
+    #[error("rate limited: {host}")]
+    RateLimited { host: String },
 }
 
-pub async fn fetch_and_store(conn: &Connection, base_url: &str) -> Result<()> {
-    println!("Crawl blog; base_url: {}", base_url);
+// Machine-readable outcome of one source's run, written to summary.json
+#[derive(Debug, Default, Serialize)]
+pub struct SourceReport {
+    pub name: String,
+    pub kind: String,
+    // "sitemap", "feed", "html" or "refresh"; None when the source failed before crawling
+    pub path: Option<String>,
+    // Article fetches, meta-refresh hops included
+    pub attempted: usize,
+    pub inserted: usize,
+    // Stored articles fetched again and overwritten (refresh / recrawl)
+    pub refreshed: usize,
+    // Sitemap entries without / with a stored row (or error record) at the start
+    pub sitemap_new: usize,
+    pub sitemap_known: usize,
+    // Sitemap entries passed over because they are stored and unchanged
+    pub skipped_known: usize,
+    // Conditional requests (sitemap.xml, articles) answered 304 Not Modified
+    pub not_modified: usize,
+    // HTML-crawled listing pages only used for their links
+    pub listing_pages: usize,
+    // Entries or articles older than max_article_age_days (or undated, when strict)
+    pub too_old: usize,
+    // URLs skipped because of the takedowns list
+    pub taken_down: usize,
+    // URLs disallowed by robots.txt
+    pub robots_blocked: usize,
+    // URLs left out by the blog's include_patterns / exclude_patterns
+    pub filtered: usize,
+    // New articles not stored because their title matches a stored one
+    pub duplicate_titles: usize,
+    // Pages not stored because their unavailable_after date has passed
+    pub expired: usize,
+    // Failed fetches by kind (http_status / auth_rejected / tls / rate_limited / other)
+    pub errors: BTreeMap<String, usize>,
+    pub elapsed_ms: u128,
+    // A budget stopped the crawl; budget_limit names it (max_new_items / max_fetch_attempts)
+    pub budget_truncated: bool,
+    pub budget_limit: Option<String>,
+    // attempted / inserted; high values point at expensive discovery
+    pub fetches_per_insert: Option<f64>,
+    // Skipped this run because of the blog's quiet_hours
+    pub deferred: bool,
+    // This run's extraction rates over inserted pages, when there were enough of them
+    pub extraction: Option<db::ExtractionRates>,
+    // Rates that fell sharply against the source's trailing average (theme change?)
+    pub drift_warnings: Vec<String>,
+    // Each requested host's delay at the end of the crawl, after adapting to it
+    pub host_delays_ms: BTreeMap<String, u64>,
+    #[serde(skip)]
+    extracted: ExtractionCounts,
+    // Stored articles a refresh found under another URL
+    pub moved: Vec<Moved>,
+    // Why the source as a whole failed
+    pub failure: Option<String>,
+}
+
+// A stored permalink and the URL its page now lands on
+#[derive(Debug, Serialize)]
+pub struct Moved {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Default)]
+struct ExtractionCounts {
+    pages: usize,
+    title: usize,
+    description: usize,
+    date: usize,
+    thumbnail: usize,
+}
+
+impl SourceReport {
+    pub fn new(name: &str) -> Self {
+        SourceReport {
+            name: name.to_string(),
+            kind: "blog".to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Entries passed over without being stored: known and unchanged, too old, taken
+    // down, disallowed, expired, filtered out or answered 304
+    pub fn skipped(&self) -> usize {
+        self.skipped_known
+            + self.too_old
+            + self.taken_down
+            + self.robots_blocked
+            + self.expired
+            + self.filtered
+            + self.duplicate_titles
+            + self.not_modified
+    }
+
+    // Failed fetches, plus one when the source failed as a whole
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum::<usize>() + usize::from(self.failure.is_some())
+    }
+
+    fn record_extraction(
+        &mut self,
+        title: &str,
+        description: Option<&str>,
+        date: bool,
+        thumbnail: bool,
+    ) {
+        let counts = &mut self.extracted;
+        counts.pages += 1;
+        if !title.trim().is_empty() && title != "No Title" {
+            counts.title += 1;
+        }
+        if description.is_some_and(|d| !d.trim().is_empty()) {
+            counts.description += 1;
+        }
+        if date {
+            counts.date += 1;
+        }
+        if thumbnail {
+            counts.thumbnail += 1;
+        }
+    }
+
+    fn record_error(&mut self, error: &anyhow::Error) {
+        let kind = match error.downcast_ref::<CrawlError>() {
+            Some(CrawlError::HttpStatus { .. }) => "http_status",
+            Some(CrawlError::AuthRejected { .. }) => "auth_rejected",
+            Some(CrawlError::Tls { .. }) => "tls",
+            Some(CrawlError::RateLimited { .. }) => "rate_limited",
+            None => "other",
+        };
+        *self.errors.entry(kind.to_string()).or_default() += 1;
+    }
+}
+
+// The two per-source limits of the discovery loops. Seeds spend them only with
+// seeds_use_budget.
+struct Budget {
+    max_new: usize,
+    max_fetches: usize,
+    // report.attempted when the budgeted crawl started
+    fetch_base: usize,
+}
+
+impl Budget {
+    // Whether another article may be fetched after `inserted` inserts; records the
+    // limit hit on the report
+    fn allows(&self, site: &Site, inserted: usize) -> bool {
+        let mut report = site.report.borrow_mut();
+        let limit = if inserted >= self.max_new {
+            "max_new_items"
+        } else if report.attempted - self.fetch_base >= self.max_fetches {
+            "max_fetch_attempts"
+        } else {
+            return true;
+        };
+
+        if !report.budget_truncated {
+            info!("Reached {} limit, stopping this site.", limit);
+        }
+        report.budget_truncated = true;
+        report.budget_limit = Some(limit.to_string());
+        false
+    }
+}
+
+// Spacing of requests per host within one source's crawl. Keyed on the host being
+// fetched, so alt_urls and redirect targets are throttled separately. Each host's
+// delay adapts to how it answers (see HostPace); the state lasts for the run only.
+pub(crate) struct Throttle {
+    bounds: DelayBounds,
+    hosts: RefCell<HashMap<String, HostPace>>,
+    // Hosts that answered 429; not requested again this run
+    limited: RefCell<HashSet<String>>,
+}
+
+// Starting delay of a host and the range its adapted delay stays in
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DelayBounds {
+    start: std::time::Duration,
+    min: std::time::Duration,
+    max: std::time::Duration,
+}
+
+impl DelayBounds {
+    fn new(config: &Config, blog: &BlogConfig, crawl_delay: Option<std::time::Duration>) -> Self {
+        let ms = std::time::Duration::from_millis;
+        let delay = blog.request_delay_ms.unwrap_or(config.request_delay_ms);
+        let max = ms(config.max_request_delay_ms);
+        // Crawl-delay is a floor the site asked for, not something to adapt below
+        let floor = crawl_delay.unwrap_or_default();
+
+        let min = ms(config.min_request_delay_ms.unwrap_or(delay))
+            .max(floor)
+            .min(max);
+        DelayBounds {
+            start: ms(delay).max(floor).clamp(min, max),
+            min,
+            max,
+        }
+    }
+
+    fn fixed(delay: std::time::Duration) -> Self {
+        DelayBounds {
+            start: delay,
+            min: delay,
+            max: delay,
+        }
+    }
+}
+
+// Weight of the newest response in a host's rolling latency and error rate
+const PACE_WEIGHT: f64 = 0.3;
+// Rolling latency above which the delay widens, and below which it may tighten
+const SLOW_LATENCY_MS: f64 = 2000.0;
+const FAST_LATENCY_MS: f64 = 500.0;
+// Rolling error rate under which a host counts as clean
+const CLEAN_ERROR_RATE: f64 = 0.05;
+// Smallest widened delay, so a zero delay can still back off
+const MIN_BACKOFF_MS: u64 = 500;
+
+#[derive(Debug)]
+struct HostPace {
+    delay: std::time::Duration,
+    last: Option<Instant>,
+    // Rolling averages over this run's responses
+    latency_ms: f64,
+    error_rate: f64,
+    responses: usize,
+}
+
+impl HostPace {
+    fn new(bounds: &DelayBounds) -> Self {
+        HostPace {
+            delay: bounds.start,
+            last: None,
+            latency_ms: 0.0,
+            error_rate: 0.0,
+            responses: 0,
+        }
+    }
+
+    // Folds one response into the averages and moves the delay: doubled on a 5xx, 429
+    // or failed request, half as long again while responses are slow, and down by a
+    // fifth while they are fast and clean
+    fn observe(&mut self, bounds: &DelayBounds, latency: std::time::Duration, failed: bool) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let error = if failed { 1.0 } else { 0.0 };
+        if self.responses == 0 {
+            self.latency_ms = latency_ms;
+            self.error_rate = error;
+        } else {
+            self.latency_ms += PACE_WEIGHT * (latency_ms - self.latency_ms);
+            self.error_rate += PACE_WEIGHT * (error - self.error_rate);
+        }
+        self.responses += 1;
+
+        let backoff = self
+            .delay
+            .max(std::time::Duration::from_millis(MIN_BACKOFF_MS));
+        let delay = if failed {
+            backoff * 2
+        } else if self.latency_ms > SLOW_LATENCY_MS {
+            backoff.mul_f64(1.5)
+        } else if self.latency_ms < FAST_LATENCY_MS && self.error_rate < CLEAN_ERROR_RATE {
+            self.delay.mul_f64(0.8)
+        } else {
+            self.delay
+        };
+        self.delay = delay.clamp(bounds.min, bounds.max);
+    }
+}
+
+impl Throttle {
+    fn new(config: &Config, blog: &BlogConfig, crawl_delay: Option<std::time::Duration>) -> Self {
+        Throttle::with_bounds(DelayBounds::new(config, blog, crawl_delay))
+    }
+
+    // A delay that does not adapt
+    pub(crate) fn with_delay(delay: std::time::Duration) -> Self {
+        Throttle::with_bounds(DelayBounds::fixed(delay))
+    }
+
+    fn with_bounds(bounds: DelayBounds) -> Self {
+        Throttle {
+            bounds,
+            hosts: RefCell::new(HashMap::new()),
+            limited: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub(crate) async fn wait(&self, url: &str) -> Result<()> {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+        else {
+            return Ok(());
+        };
+
+        if self.limited.borrow().contains(&host) {
+            return Err(CrawlError::RateLimited { host }.into());
+        }
+
+        let ready_at = self
+            .hosts
+            .borrow()
+            .get(&host)
+            .and_then(|pace| Some(pace.last? + pace.delay));
+        if let Some(ready_at) = ready_at {
+            tokio::time::sleep_until(ready_at.into()).await;
+        }
+        self.hosts
+            .borrow_mut()
+            .entry(host)
+            .or_insert_with(|| HostPace::new(&self.bounds))
+            .last = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // Adapts the host's delay to how long a request took and whether it failed
+    pub(crate) fn observe(&self, url: &str, latency: std::time::Duration, failed: bool) {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+        else {
+            return;
+        };
+
+        self.hosts
+            .borrow_mut()
+            .entry(host)
+            .or_insert_with(|| HostPace::new(&self.bounds))
+            .observe(&self.bounds, latency, failed);
+    }
+
+    // Each requested host's current delay in milliseconds
+    pub(crate) fn delays_ms(&self) -> BTreeMap<String, u64> {
+        self.hosts
+            .borrow()
+            .iter()
+            .map(|(host, pace)| (host.clone(), pace.delay.as_millis() as u64))
+            .collect()
+    }
+
+    // Returns the host when it was newly marked
+    pub(crate) fn limit(&self, url: &str) -> Option<String> {
+        let host = Url::parse(url).ok()?.host_str()?.to_string();
+        self.limited
+            .borrow_mut()
+            .insert(host.clone())
+            .then_some(host)
+    }
+}
+
+// Why crawl_article passes over a URL without fetching it
+enum UrlSkip {
+    // Error backoff on the URL or its domain, until the given time
+    RecentError(DateTime<Utc>),
+    TakenDown,
+    // Disallowed by the site's robots.txt
+    Robots,
+    Boilerplate,
+    // Left out by include_patterns / exclude_patterns
+    Filtered,
+}
+
+// Why a fetched page is not stored
+enum PageSkip {
+    Boilerplate,
+    // The URL the page resolved to (meta refresh, canonical) is filtered out
+    Filtered,
+    // A copy in an unwanted language of `original`
+    Translation { lang: String, original: String },
+    TooOld,
+    // unavailable_after date that has passed
+    Expired(String),
+}
+
+// Crawl scope of one blog: the primary base URL plus mirror/alternate domains
+struct Site {
+    name: String,
+    base_url: String,
+    alt_hosts: Vec<String>,
+    // BlogConfig::allowed_hosts as http://host/ URLs, IDNs in their ASCII form
+    allowed_hosts: Vec<Url>,
+    boilerplate: Boilerplate,
+    boilerplate_skipped: Cell<usize>,
+    url_filter: UrlFilter,
+    duplicate_titles: DuplicateTitles,
+    // Dry run: new articles are scored with these rules and printed, not inserted
+    dry_run: bool,
+    scoring: ScoringConfig,
+    authenticated: bool,
+    // On http.danger_accept_invalid_certs: domain TLS backoffs don't apply
+    insecure: bool,
+    languages: Vec<String>,
+    meta_refresh_max_secs: u64,
+    store_fulltext: bool,
+    // Revisions kept per row when a refresh or recrawl changes it
+    max_revisions: usize,
+    takedowns: Vec<String>,
+    max_age: Option<chrono::Duration>,
+    max_age_strict: bool,
+    // Queue depth up to which crawl_html extracts links
+    max_depth: i64,
+    robots: Robots,
+    throttle: Throttle,
+    cache: Rc<RunCache>,
+    report: RefCell<SourceReport>,
+}
+
+impl Site {
+    fn new(
+        blog: &BlogConfig,
+        base_url: String,
+        boilerplate: Boilerplate,
+        url_filter: UrlFilter,
+        config: &Config,
+        robots: Robots,
+        cache: Rc<RunCache>,
+    ) -> Self {
+        let alt_hosts = blog
+            .alt_urls
+            .iter()
+            .filter_map(|u| Url::parse(u).ok())
+            .filter_map(|u| u.host_str().map(|h| h.to_string()))
+            .collect();
+
+        Site {
+            name: blog.name.clone(),
+            base_url,
+            alt_hosts,
+            allowed_hosts: blog
+                .allowed_hosts
+                .iter()
+                .filter_map(|h| Url::parse(&format!("http://{}/", h)).ok())
+                .collect(),
+            boilerplate,
+            boilerplate_skipped: Cell::new(0),
+            url_filter,
+            duplicate_titles: config.duplicate_titles,
+            dry_run: config.dry_run,
+            scoring: config.scoring.clone(),
+            authenticated: blog.auth.is_some(),
+            insecure: config.http.accepts_invalid_certs(&blog.url),
+            languages: config
+                .languages
+                .iter()
+                .map(|l| primary_language(l))
+                .collect(),
+            meta_refresh_max_secs: config.meta_refresh_max_secs,
+            store_fulltext: blog.store_fulltext.unwrap_or(config.store_fulltext),
+            max_revisions: config.max_revisions,
+            takedowns: config.takedowns.clone(),
+            max_age: blog
+                .max_article_age_days
+                .or(config.max_article_age_days)
+                .map(chrono::Duration::days),
+            max_age_strict: blog.max_article_age_strict,
+            max_depth: blog.max_depth.unwrap_or(config.max_depth),
+            throttle: Throttle::new(config, blog, robots.crawl_delay),
+            robots,
+            cache,
+            report: RefCell::new(SourceReport::new(&blog.name)),
+        }
+    }
+
+    // Whether an article dated `date` is past max_article_age_days
+    fn is_too_old(&self, date: Option<DateTime<Utc>>) -> bool {
+        match (self.max_age, date) {
+            (None, _) => false,
+            (Some(max_age), Some(date)) => date < Utc::now() - max_age,
+            (Some(_), None) => self.max_age_strict,
+        }
+    }
+
+    // The checks crawl_article makes before fetching a URL, in order
+    fn url_skip(&self, url: &str) -> Option<UrlSkip> {
+        if let Some(retry_after) = self.cache.retry_after(url) {
+            return Some(UrlSkip::RecentError(retry_after));
+        }
+        if !self.insecure
+            && let Some(retry_after) = domain_key(url).and_then(|key| self.cache.retry_after(&key))
+        {
+            return Some(UrlSkip::RecentError(retry_after));
+        }
+
+        if classify::is_taken_down(&self.takedowns, url) {
+            return Some(UrlSkip::TakenDown);
+        }
+
+        if !self.robots.allows(url) {
+            return Some(UrlSkip::Robots);
+        }
+
+        if self.boilerplate.matches_url(url) {
+            return Some(UrlSkip::Boilerplate);
+        }
+
+        if !self.url_filter.allows(url) {
+            return Some(UrlSkip::Filtered);
+        }
+
+        None
+    }
+
+    async fn send(&self, client: &Client, url: &str) -> Result<reqwest::Response> {
+        self.send_request(url, client.get(url)).await
+    }
+
+    // GET carrying the validators of an earlier response, so an unchanged resource
+    // can answer 304 Not Modified
+    async fn send_conditional(
+        &self,
+        client: &Client,
+        url: &str,
+        validators: Option<&db::Validators>,
+    ) -> Result<reqwest::Response> {
+        let mut request = client.get(url);
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        self.send_request(url, request).await
+    }
+
+    // Throttled request; a 429 takes the host out of this run
+    async fn send_request(
+        &self,
+        url: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.throttle.wait(url).await?;
+        let started = Instant::now();
+        let outcome = send_request(url, request).await;
+        let failed = outcome.as_ref().map_or(true, |response| {
+            response.status().is_server_error()
+                || response.status() == StatusCode::TOO_MANY_REQUESTS
+        });
+        self.throttle.observe(url, started.elapsed(), failed);
+        let response = outcome?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS
+            && let Some(host) = self.throttle.limit(url)
+        {
+            warn!(
+                "Rate limited by {}; backing off for the rest of the run",
+                host
+            );
+        }
+
+        Ok(response)
+    }
+
+    async fn fetch_html(&self, client: &Client, url: &str) -> Result<String> {
+        read_html(url, self.send(client, url).await?).await
+    }
+
+    // fetch_html plus the URL the client's redirects ended at
+    async fn fetch_page(&self, client: &Client, url: &str) -> Result<(String, String)> {
+        let response = self.send(client, url).await?;
+        let final_url = response.url().to_string();
+        Ok((final_url, read_html(url, response).await?))
+    }
+
+    // fetch_page with a conditional GET: None when the page is unchanged since the
+    // stored validators were taken. Validators are replaced after every full fetch.
+    async fn fetch_page_if_modified(
+        &self,
+        conn: &Connection,
+        client: &Client,
+        url: &str,
+    ) -> Result<Option<(String, String)>> {
+        let validators = db::http_validators(conn, url)?;
+        let response = self
+            .send_conditional(client, url, validators.as_ref())
+            .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let fresh = response_validators(response.headers());
+        let final_url = response.url().to_string();
+        let body = read_html(url, response).await?;
+        db::store_validators(conn, url, fresh.as_ref())?;
+
+        Ok(Some((final_url, body)))
+    }
+
+    // The report with the hosts' final delays
+    fn finish(self) -> SourceReport {
+        let mut report = self.report.into_inner();
+        report.host_delays_ms = self.throttle.delays_ms();
+        for (host, delay) in &report.host_delays_ms {
+            if *delay != self.throttle.bounds.start.as_millis() as u64 {
+                info!("{}: request delay adapted to {} ms", host, delay);
+            }
+        }
+        report
+    }
+
+    fn count_takedown(&self, url: &str) {
+        info!("Skipping taken-down {}", url);
+        self.report.borrow_mut().taken_down += 1;
+    }
+
+    // A meta refresh on the page: Ok(target) when it is followed (same site, short
+    // delay), Err(target) when the page is a stub that isn't
+    fn refresh_target(
+        &self,
+        document: &Html,
+        url: &str,
+    ) -> Option<std::result::Result<String, String>> {
+        let (delay, target) = meta_refresh(document, url)?;
+
+        Some(
+            self.to_primary(&target)
+                .filter(|_| delay <= self.meta_refresh_max_secs)
+                .ok_or(target),
+        )
+    }
+
+    // The checks crawl_article makes on a fetched page before storing it, in order
+    fn page_skip(&self, document: &Html, article: &ExtractedArticle) -> Option<PageSkip> {
+        let url = article.url.as_str();
+
+        if !self.url_filter.allows(url) {
+            return Some(PageSkip::Filtered);
+        }
+
+        if self.boilerplate.matches_title(&article.title) {
+            return Some(PageSkip::Boilerplate);
+        }
+
+        // A translated copy is dropped in favour of its declared preferred-language
+        // original on the same site
+        let alternates: Vec<(String, String)> = hreflang_alternates(document, url)
+            .into_iter()
+            .filter_map(|(lang, alternate)| Some((lang, self.to_primary(&alternate)?)))
+            .collect();
+        let page_language = alternates
+            .iter()
+            .find(|(_, alternate)| alternate == url)
+            .map(|(lang, _)| lang.clone())
+            .or_else(|| article.language.clone());
+
+        if let Some(lang) = page_language
+            && !self.is_preferred_language(&lang)
+            && let Some((_, original)) = alternates
+                .iter()
+                .find(|(l, alternate)| self.is_preferred_language(l) && alternate != url)
+        {
+            return Some(PageSkip::Translation {
+                lang,
+                original: original.clone(),
+            });
+        }
+
+        // The page's own date decides, whatever its sitemap lastmod said
+        let published = article.published_at.as_deref().and_then(parse_lastmod);
+        if self.is_too_old(published) {
+            return Some(PageSkip::TooOld);
+        }
+
+        if let Some(expiry) = &article.unavailable_after
+            && DateTime::parse_from_rfc3339(expiry).is_ok_and(|d| d <= Utc::now())
+        {
+            return Some(PageSkip::Expired(expiry.clone()));
+        }
+
+        None
+    }
+
+    // Preferred article language(s): the configured ones, else Japanese
+    fn is_preferred_language(&self, lang: &str) -> bool {
+        if self.languages.is_empty() {
+            return lang == "ja";
+        }
+
+        self.languages.iter().any(|l| l == lang)
+    }
+
+    // Map a URL on the primary or an alternate domain onto the primary domain; URLs
+    // on an allowed host keep their host. Returns None for URLs outside the site.
+    fn to_primary(&self, url: &str) -> Option<String> {
+        let mut target = Url::parse(url).ok()?;
+        let host = target.host_str()?.to_string();
+        let base = Url::parse(&self.base_url).ok()?;
+
+        // www. and bare host are one site, stored under the configured host
+        if same_domain(&self.base_url, url) {
+            target.set_host(base.host_str()).ok()?;
+            return Some(db::canonical_url(&upgrade_scheme(
+                &self.base_url,
+                target.as_str(),
+            )));
+        }
+
+        if let Some(allowed) = self
+            .allowed_hosts
+            .iter()
+            .find(|u| u.host_str().is_some_and(|h| same_host(h, &host)))
+        {
+            target.set_host(allowed.host_str()).ok()?;
+            return Some(db::canonical_url(target.as_str()));
+        }
+
+        if !self.alt_hosts.contains(&host) {
+            return None;
+        }
+
+        target.set_scheme(base.scheme()).ok()?;
+        target.set_host(base.host_str()).ok()?;
+        target.set_port(base.port()).ok()?;
+
+        Some(db::canonical_url(target.as_str()))
+    }
+}
+
+pub async fn fetch_and_store(
+    conn: &Connection,
+    config: &Config,
+    blog: &BlogConfig,
+    cache: Rc<RunCache>,
+) -> Result<SourceReport> {
+    if let Some(report) = deferred(blog)? {
+        return Ok(report);
+    }
+
+    let (client, site) = open_site(conn, config, blog, cache).await?;
+    let base_url = &site.base_url;
+    info!("Crawl blog; base_url: {}", base_url);
+
+    let seeded = crawl_seeds(conn, &client, &site, &blog.seed_urls).await?;
+    let max_new = blog.max_new.unwrap_or(config.max_new_items);
+    let budget = if blog.seeds_use_budget {
+        Budget {
+            max_new: max_new.saturating_sub(seeded),
+            max_fetches: config.max_fetch_attempts_per_source,
+            fetch_base: 0,
+        }
+    } else {
+        Budget {
+            max_new,
+            max_fetches: config.max_fetch_attempts_per_source,
+            fetch_base: site.report.borrow().attempted,
+        }
+    };
+
+    // Try sitemap first: the configured ones, else those robots.txt declares, then
+    // the conventional path. Declared sitemaps on other hosts are left out: they
+    // would be fetched with this blog's client and credentials.
+    let default_sitemap = sitemap_url(base_url);
+    let declared = if blog.sitemaps.is_empty() {
+        site.robots
+            .sitemaps
+            .iter()
+            .filter(|url| {
+                let on_site = site.to_primary(url).is_some();
+                if !on_site {
+                    warn!("Sitemap {} (robots.txt): outside the site, skipped", url);
+                }
+                on_site
+            })
+            .cloned()
+            .collect()
+    } else {
+        configured_sitemaps(&site, blog)
+    };
+    let mut sitemap = Err(anyhow::anyhow!("No sitemap"));
+    if !declared.is_empty() {
+        sitemap = fetch_sitemap(conn, &client, &site, &declared).await;
+    }
+    if sitemap.is_err() && blog.sitemaps.is_empty() && !declared.contains(&default_sitemap) {
+        sitemap = fetch_sitemap(conn, &client, &site, &[default_sitemap]).await;
+    }
+    let result = if let Ok(None) = sitemap {
+        info!("Sitemap not modified since the last full pass");
+        let mut report = site.report.borrow_mut();
+        report.path = Some("sitemap".to_string());
+        report.not_modified += 1;
+        Ok(())
+    } else if let Ok(Some(entries)) = sitemap {
+        info!("Crawl sitemap");
+        site.report.borrow_mut().path = Some("sitemap".to_string());
+        crawl_sitemap(conn, &client, &site, &entries, &budget).await
+    } else if let Some((feed_url, entries)) = discover_feed(&client, &site, base_url).await {
+        // Feed entries go through the sitemap pass: same budget, age and lastmod rules
+        info!("Crawl feed {}", feed_url);
+        site.report.borrow_mut().path = Some("feed".to_string());
+        let entries = unwrap_redirectors(conn, config, &site, entries).await?;
+        crawl_sitemap(conn, &client, &site, &entries, &budget).await
+    } else {
+        // Fallback to HTML link scraping
+        info!("Crawl via HTML link scraping");
+        site.report.borrow_mut().path = Some("html".to_string());
+        crawl_html(conn, &client, &site, &budget).await
+    };
+
+    if site.boilerplate_skipped.get() > 0 {
+        info!(
+            "Skipped {} boilerplate pages on {}",
+            site.boilerplate_skipped.get(),
+            site.name
+        );
+    }
+
+    result?;
+    check_drift(conn, &site)?;
+
+    {
+        let mut report = site.report.borrow_mut();
+        if report.inserted > 0 {
+            report.fetches_per_insert = Some(report.attempted as f64 / report.inserted as f64);
+        }
+    }
+
+    Ok(site.finish())
+}
+
+// Entry point for: crawler refresh <config.json>. Fetches this blog's articles stored
+// (or last refreshed) before `stale_before` again and overwrites their fields, oldest
+// first, within the per-source fetch budget. Failed fetches leave the row as it is.
+pub async fn refresh(
+    conn: &Connection,
+    config: &Config,
+    blog: &BlogConfig,
+    cache: Rc<RunCache>,
+    stale_before: DateTime<Utc>,
+) -> Result<SourceReport> {
+    if let Some(report) = deferred(blog)? {
+        return Ok(report);
+    }
+
+    let (client, site) = open_site(conn, config, blog, cache).await?;
+    site.report.borrow_mut().path = Some("refresh".to_string());
+
+    let stale = db::stale_contents(
+        conn,
+        &blog.name,
+        &stale_before.to_rfc3339(),
+        config.max_fetch_attempts_per_source,
+    )?;
+    info!("Refresh blog {}: {} stale articles", blog.name, stale.len());
+
+    let now = Utc::now().to_rfc3339();
+    for url in &stale {
+        if site.report.borrow().attempted >= config.max_fetch_attempts_per_source {
+            site.report.borrow_mut().budget_truncated = true;
+            break;
+        }
+
+        match crawl_article(conn, &client, &site, url, None, &now, FetchMode::Refresh).await {
+            // Skipped pages (boilerplate, too old, ...) are not retried every run, nor
+            // is a row whose page now redirects to another URL
+            Ok(_) => db::mark_refreshed(conn, url, &now)?,
+            Err(e) => {
+                warn!("{}", e);
+                site.report.borrow_mut().record_error(&e);
+                if is_rate_limited(&e) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(site.finish())
+}
+
+// A report for a blog inside its quiet hours, which is not crawled now
+fn deferred(blog: &BlogConfig) -> Result<Option<SourceReport>> {
+    let Some(quiet_hours) = &blog.quiet_hours else {
+        return Ok(None);
+    };
+    if !quiet_hours.contains(Utc::now())? {
+        return Ok(None);
+    }
+
+    info!(
+        "Deferring {}: quiet hours {}-{} ({})",
+        blog.name, quiet_hours.start, quiet_hours.end, quiet_hours.tz
+    );
+    let mut report = SourceReport::new(&blog.name);
+    report.deferred = true;
+    Ok(Some(report))
+}
+
+// Client and Site of a blog about to be crawled: TLS backoff, source registration,
+// base URL, robots.txt and the credentials check
+async fn open_site(
+    conn: &Connection,
+    config: &Config,
+    blog: &BlogConfig,
+    cache: Rc<RunCache>,
+) -> Result<(Client, Site)> {
+    // Opting out of verification also lifts the TLS backoff
+    if !config.http.accepts_invalid_certs(&blog.url)
+        && let Some(key) = domain_key(&blog.url)
+        && cache.should_skip(&key)
+    {
+        anyhow::bail!("skipped: recent TLS failures on {}", key);
+    }
+
+    let client = build_client(config, blog)?;
+
+    db::register_source(conn, &blog.name, &blog.url, blog.category.as_deref())?;
+
+    let boilerplate = Boilerplate::new(&config.boilerplate, blog)?;
+    let url_filter = UrlFilter::new(blog)?;
+    let base_url = resolve_base_url(conn, &client, blog).await?;
+    let robots = robots::fetch(&client, &base_url)
+        .await
+        .map_err(|e| anyhow::anyhow!("skipped: {}", e))?;
+    let site = Site::new(
+        blog,
+        base_url,
+        boilerplate,
+        url_filter,
+        config,
+        robots,
+        cache,
+    );
+
+    if site.authenticated {
+        check_auth(&client, &site.base_url).await?;
+    }
+
+    Ok((client, site))
+}
+
+// Compare this run's extraction rates with the source's trailing average and fold them in
+fn check_drift(conn: &Connection, site: &Site) -> Result<()> {
+    let mut report = site.report.borrow_mut();
+    let counts = &report.extracted;
+    if counts.pages < DRIFT_MIN_SAMPLES {
+        return Ok(());
+    }
+
+    let pages = counts.pages as f64;
+    let current = db::ExtractionRates {
+        title: counts.title as f64 / pages,
+        description: counts.description as f64 / pages,
+        date: counts.date as f64 / pages,
+        thumbnail: counts.thumbnail as f64 / pages,
+    };
+
+    let average = match db::extraction_rates(conn, &site.name)? {
+        Some(previous) => {
+            let metrics = [
+                ("title", previous.title, current.title),
+                ("description", previous.description, current.description),
+                ("date", previous.date, current.date),
+                ("thumbnail", previous.thumbnail, current.thumbnail),
+            ];
+
+            for (metric, before, now) in metrics {
+                if before - now >= DRIFT_DROP {
+                    let warning = format!(
+                        "{} rate fell from {:.0}% to {:.0}%",
+                        metric,
+                        before * 100.0,
+                        now * 100.0
+                    );
+                    warn!("{}: {}; check its selectors", site.name, warning);
+                    report.drift_warnings.push(warning);
+                }
+            }
+
+            let blend = |before: f64, now: f64| before + (now - before) * DRIFT_SMOOTHING;
+            db::ExtractionRates {
+                title: blend(previous.title, current.title),
+                description: blend(previous.description, current.description),
+                date: blend(previous.date, current.date),
+                thumbnail: blend(previous.thumbnail, current.thumbnail),
+            }
+        }
+        None => current,
+    };
+
+    db::set_extraction_rates(conn, &site.name, &average)?;
+    report.extraction = Some(current);
+
+    Ok(())
+}
+
+// Fetch the configured seed URLs not stored yet. Returns how many were inserted.
+async fn crawl_seeds(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    seeds: &[String],
+) -> Result<usize> {
+    let now = Utc::now().to_rfc3339();
+    let mut inserted = 0;
+
+    for seed in seeds {
+        let Some(url) = site.to_primary(seed) else {
+            continue;
+        };
+
+        if site.cache.fetched_at(&url).is_some() {
+            continue;
+        }
+
+        let added = crawl_article(conn, client, site, &url, None, &now, FetchMode::New)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Seed {}: {}", url, e);
+                site.report.borrow_mut().record_error(&e);
+                false
+            });
+
+        if added {
+            inserted += 1;
+        }
+    }
+
+    Ok(inserted)
+}
+
+// Settings of every client: the User-Agent, timeouts so a server that accepts the
+// connection and never answers can't stall the run, and the configured proxy
+fn client_builder(config: &Config) -> Result<reqwest::ClientBuilder> {
+    let mut builder = Client::builder()
+        .user_agent(config.user_agent())
+        .connect_timeout(std::time::Duration::from_secs(config.connect_timeout_secs))
+        .timeout(std::time::Duration::from_secs(config.request_timeout_secs));
+
+    // An explicit proxy replaces reqwest's environment lookup
+    if let Some(proxy) = &config.http.proxy {
+        let no_proxy = match &config.http.no_proxy {
+            Some(hosts) => NoProxy::from_string(hosts),
+            None => NoProxy::from_env(),
+        };
+        builder = builder.proxy(Proxy::all(proxy)?.no_proxy(no_proxy));
+    }
+
+    Ok(builder)
+}
+
+// For requests without per-blog settings (YouTube feeds); one per run
+pub fn shared_client(config: &Config) -> Result<Client> {
+    Ok(client_builder(config)?.build()?)
+}
+
+// Each blog gets its own client so configured credentials and headers never reach
+// other sources. reqwest drops Authorization/Cookie when a redirect leaves the host.
+pub fn build_client(config: &Config, blog: &BlogConfig) -> Result<Client> {
+    let mut builder = client_builder(config)?;
+
+    // reqwest can't scope this to hosts: a redirect off the blog is unverified too
+    if config.http.accepts_invalid_certs(&blog.url) {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    let mut headers = blog.header_map()?;
+    let Some(auth) = &blog.auth else {
+        return Ok(builder.default_headers(headers).build()?);
+    };
+
+    let secret = |var: &str| {
+        std::env::var(var)
+            .map_err(|_| anyhow::anyhow!("{}: auth variable {} is not set", blog.name, var))
+    };
+
+    let (name, value) = match auth {
+        AuthConfig::Basic {
+            username,
+            password_env,
+        } => {
+            let credentials = format!("{}:{}", username, secret(password_env)?);
+            (
+                AUTHORIZATION,
+                format!("Basic {}", STANDARD.encode(credentials)),
+            )
+        }
+        AuthConfig::Cookie { cookie_env } => (COOKIE, secret(cookie_env)?),
+    };
+
+    let mut value = HeaderValue::from_str(&value)?;
+    value.set_sensitive(true);
+    headers.insert(name, value);
+
+    Ok(builder.default_headers(headers).build()?)
+}
+
+// Fail the whole source up front instead of logging a status error per page
+async fn check_auth(client: &Client, base_url: &str) -> Result<()> {
+    let status = client.get(base_url).send().await?.status();
+
+    if is_auth_failure(status) {
+        return Err(CrawlError::AuthRejected {
+            status,
+            url: base_url.to_string(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+async fn crawl_sitemap(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    entries: &[SitemapEntry],
+    budget: &Budget,
+) -> Result<()> {
+    let mut counter = 0;
+    let now = Utc::now().to_rfc3339();
+
+    // Entries outside the site are dropped before anything indexes into the list:
+    // their URLs are never fetched with this blog's client and its credentials
+    let listed = entries.len();
+    let entries: Vec<SitemapEntry> = entries
+        .iter()
+        .filter_map(|entry| {
+            Some(SitemapEntry {
+                url: site.to_primary(&entry.url)?,
+                ..entry.clone()
+            })
+        })
+        .collect();
+    if entries.len() < listed {
+        warn!(
+            "Sitemap: skipped {} entries outside the site",
+            listed - entries.len()
+        );
+        site.report.borrow_mut().filtered += listed - entries.len();
+    }
+
+    let signature = SitemapSignature::of(&entries);
+    let cursor = match db::sitemap_cursor(conn, &site.name)? {
+        Some((cursor, previous)) if !signature.differs_materially(&previous) => cursor,
+        _ => 0,
+    };
+
+    let head = SITEMAP_HEAD_ENTRIES.min(entries.len());
+    let cursor = if cursor < head || cursor >= entries.len() {
+        head
+    } else {
+        cursor
+    };
+    let mut next_cursor = cursor;
+
+    let urls: Vec<String> = entries.iter().map(|entry| entry.url.clone()).collect();
+    let unknown = site.cache.filter_unknown(&urls);
+
+    {
+        let mut report = site.report.borrow_mut();
+        report.sitemap_new = unknown.len();
+        report.sitemap_known = urls.len() - unknown.len();
+    }
+
+    // Never-seen entries spend the budget first; stored ones are only revisited for
+    // lastmod changes, which never count against it
+    let order = sitemap_order(entries.len(), head, cursor);
+    let (fresh, seen): (Vec<usize>, Vec<usize>) = order
+        .into_iter()
+        .partition(|&index| unknown.contains(&urls[index]));
+    let mut stopped_early = false;
+
+    for index in fresh.into_iter().chain(seen) {
+        if !budget.allows(site, counter) {
+            stopped_early = true;
+            break;
+        }
+
+        let entry = &entries[index];
+        let url = &urls[index];
+        let is_fresh = unknown.contains(url);
+
+        if is_fresh && index >= head {
+            next_cursor = index + 1;
+        }
+
+        // Sitemaps list tag and archive pages too
+        if !site.url_filter.allows(url) {
+            site.report.borrow_mut().filtered += 1;
+            continue;
+        }
+
+        if site.is_too_old(entry.lastmod) {
+            site.report.borrow_mut().too_old += 1;
+            continue;
+        }
+
+        // Known URLs are only refetched when the sitemap says they changed since;
+        // rarely-changing pages never are. A changed one overwrites its row, as in
+        // a refresh pass.
+        let mut mode = FetchMode::New;
+        if !is_fresh && let Some(stored_at) = site.cache.fetched_at(url) {
+            let changed = entry.lastmod.is_some_and(|lastmod| lastmod > stored_at);
+            if entry.is_static() || !changed {
+                site.report.borrow_mut().skipped_known += 1;
+                continue;
+            }
+            mode = FetchMode::Refresh;
+        }
+
+        let stored =
+            match crawl_article(conn, client, site, url, Some(&entry.sitemap), &now, mode).await {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("{}", e);
+                    site.report.borrow_mut().record_error(&e);
+                    // The rest of the sitemap is on the same host
+                    if is_rate_limited(&e) {
+                        stopped_early = true;
+                        break;
+                    }
+                    false
+                }
+            };
+
+        if stored {
+            // Only new rows spend the budget
+            if mode == FetchMode::New {
+                counter += 1;
+            }
+            // Pages without a date of their own fall back to the sitemap's lastmod
+            if let Some(lastmod) = entry.lastmod {
+                db::fill_published_at(conn, url, &lastmod.to_rfc3339())?;
+            }
+        }
+    }
+
+    // A finished pass starts from the top of the archive again next run. An
+    // unfinished one needs the sitemap again even if it doesn't change.
+    if !stopped_early || next_cursor >= entries.len() {
+        next_cursor = head;
+    } else {
+        let files: HashSet<&str> = entries.iter().map(|e| e.sitemap.as_str()).collect();
+        for file in files {
+            db::store_validators(conn, file, None)?;
+        }
+    }
+
+    db::set_sitemap_cursor(conn, &site.name, next_cursor, &signature.encode())?;
+
+    Ok(())
+}
+
+// Visit order: the newest `head` entries first, then the archive starting at the
+// cursor and wrapping around to just after the head.
+fn sitemap_order(len: usize, head: usize, cursor: usize) -> Vec<usize> {
+    (0..head).chain(cursor..len).chain(head..cursor).collect()
+}
+
+// Entry count + newest lastmod, used to notice a restructured sitemap
+struct SitemapSignature {
+    count: usize,
+    newest: Option<DateTime<Utc>>,
+}
+
+impl SitemapSignature {
+    fn of(entries: &[SitemapEntry]) -> Self {
+        SitemapSignature {
+            count: entries.len(),
+            newest: entries.iter().filter_map(|e| e.lastmod).max(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{}|{}",
+            self.count,
+            self.newest.map(|d| d.to_rfc3339()).unwrap_or_default()
+        )
+    }
+
+    // New posts are expected; shrinking, >10% growth, or an older newest entry are not
+    fn differs_materially(&self, previous: &str) -> bool {
+        let Some((count, newest)) = previous.split_once('|') else {
+            return true;
+        };
+        let Ok(count) = count.parse::<usize>() else {
+            return true;
+        };
+
+        if self.count < count || self.count > count + count / 10 {
+            return true;
+        }
+
+        let previous_newest = DateTime::parse_from_rfc3339(newest)
+            .ok()
+            .map(|d| d.with_timezone(&Utc));
+
+        matches!((self.newest, previous_newest), (Some(now), Some(before)) if now < before)
+    }
+}
+
+// Use https for http-configured blogs when the https variant serves the same site.
+// https entries are never downgraded; the probe result is cached per source.
+async fn resolve_base_url(conn: &Connection, client: &Client, blog: &BlogConfig) -> Result<String> {
+    let https_url = match blog.url.strip_prefix("http://") {
+        Some(rest) => format!("https://{}", rest),
+        None => return Ok(blog.url.clone()),
+    };
+
+    let cached = db::https_probe(conn, &blog.name)?.filter(|probe| {
+        Utc::now() - probe.checked_at < chrono::Duration::days(HTTPS_PROBE_INTERVAL_DAYS)
+    });
+
+    let (ok, note) = match cached {
+        Some(probe) => (probe.ok, probe.note),
+        None => {
+            let (status, note) = match probe_https(client, &blog.url, &https_url).await {
+                Ok(()) => ("upgradeable", None),
+                Err((status, note)) => (status, Some(note)),
+            };
+            db::record_https_probe(conn, &blog.name, &blog.url, status, note.as_deref())?;
+            (note.is_none(), note)
+        }
+    };
+
+    if ok {
+        return Ok(https_url);
+    }
+
+    info!(
+        "Keeping http for {}: {}",
+        blog.name,
+        note.as_deref().unwrap_or("https unavailable")
+    );
+
+    Ok(blog.url.clone())
+}
+
+// Returns Err with the probe status (db::HttpsProbe) and a short reason when the
+// https variant can't replace http
+async fn probe_https(
+    client: &Client,
+    http_url: &str,
+    https_url: &str,
+) -> Result<(), (&'static str, String)> {
+    let https_body = match fetch_html(client, https_url).await {
+        Ok(body) => body,
+        Err(e) => {
+            return Err((
+                https_failure_status(&e),
+                format!("https fetch failed: {}", e),
+            ));
+        }
+    };
+
+    // Without a working http side there is nothing to compare against
+    let Ok(http_body) = fetch_html(client, http_url).await else {
+        return Ok(());
+    };
+
+    let https_title = page_title(&https_body);
+    let http_title = page_title(&http_body);
+
+    if https_title != http_title {
+        return Err((
+            "https_broken",
+            format!(
+                "https content differs (title {:?} vs {:?})",
+                https_title, http_title
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// A refused or unreachable https port means the site has none; anything that answers
+// (a bad certificate, an error status, a reset handshake) has a broken one
+fn https_failure_status(error: &anyhow::Error) -> &'static str {
+    let unreachable = error.downcast_ref::<CrawlError>().is_none()
+        && error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect() && !is_reset(e));
+    if unreachable {
+        "http_only"
+    } else {
+        "https_broken"
+    }
+}
+
+fn page_title(body: &str) -> Option<String> {
+    let document = Html::parse_document(body);
+    let selector = Selector::parse("title").unwrap();
+
+    document
+        .select(&selector)
+        .next()
+        .map(|t| t.text().collect::<String>().trim().to_string())
+}
+
+// One <url> entry of a sitemap with its optional annotations
+#[derive(Clone)]
+struct SitemapEntry {
+    url: String,
+    // The sitemap file that listed this entry
+    sitemap: String,
+    lastmod: Option<DateTime<Utc>>,
+    priority: Option<f32>,
+    changefreq: Option<String>,
+}
+
+impl SitemapEntry {
+    // Pages the sitemap says rarely or never change
+    fn is_static(&self) -> bool {
+        matches!(self.changefreq.as_deref(), Some("yearly") | Some("never"))
+    }
+}
+
+fn sitemap_url(base_url: &str) -> String {
+    format!("{}/sitemap.xml", base_url.trim_end_matches('/'))
+}
+
+// BlogConfig::sitemaps as absolute URLs; unusable entries are logged and left out
+fn configured_sitemaps(site: &Site, blog: &BlogConfig) -> Vec<String> {
+    let base = Url::parse(&format!("{}/", site.base_url.trim_end_matches('/')));
+
+    blog.sitemaps
+        .iter()
+        .filter_map(|entry| match base.as_ref().map(|base| base.join(entry)) {
+            Ok(Ok(url)) if matches!(url.scheme(), "http" | "https") => Some(url.to_string()),
+            _ => {
+                warn!("Sitemap {:?}: not a usable URL", entry);
+                None
+            }
+        })
+        .collect()
+}
+
+// Entries of the given sitemaps combined, or None when every one answered 304 to the
+// validators kept from a finished pass (crawl_sitemap drops them when a pass stops
+// early). A failing sitemap is skipped, with a warning unless it's the default guess.
+async fn fetch_sitemap(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    sitemap_urls: &[String],
+) -> Result<Option<Vec<SitemapEntry>>> {
+    let default_sitemap = sitemap_url(&site.base_url);
+    let mut entries = Vec::new();
+    let mut not_modified = Vec::new();
+    let mut failed = 0;
+
+    for sitemap_url in sitemap_urls {
+        match fetch_sitemap_entries(conn, client, site, sitemap_url, true).await {
+            Ok(Some(found)) => entries.extend(found),
+            Ok(None) => not_modified.push(sitemap_url),
+            Err(e) => {
+                if *sitemap_url != default_sitemap {
+                    warn!("Sitemap {}: {}", sitemap_url, e);
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 && not_modified.len() == sitemap_urls.len() {
+        return Ok(None);
+    }
+
+    // Unchanged files still belong to the entry list when another one changed
+    for sitemap_url in not_modified {
+        match fetch_sitemap_entries(conn, client, site, sitemap_url, false).await {
+            Ok(found) => entries.extend(found.unwrap_or_default()),
+            Err(e) => warn!("Sitemap {}: {}", sitemap_url, e),
+        }
+    }
+
+    if entries.is_empty() {
+        anyhow::bail!("No URLs in sitemap");
+    }
+
+    // Newest lastmod first, then higher priority; undated entries keep their
+    // relative order after the dated ones. 0.5 is the sitemap default priority.
+    entries.sort_by(|a, b| {
+        b.lastmod.cmp(&a.lastmod).then_with(|| {
+            let pa = a.priority.unwrap_or(0.5);
+            let pb = b.priority.unwrap_or(0.5);
+            pb.partial_cmp(&pa).unwrap_or(std::cmp::Ordering::Equal)
+        })
+    });
+
+    // Pages listed by more than one sitemap keep their newest entry
+    let mut seen = HashSet::new();
+    entries.retain(|entry| seen.insert(entry.url.clone()));
+
+    Ok(Some(entries))
+}
+
+// One top-level sitemap with a sitemap index expanded, or None on a 304 to the
+// stored validators (only sent when `conditional`)
+async fn fetch_sitemap_entries(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    sitemap_url: &str,
+    conditional: bool,
+) -> Result<Option<Vec<SitemapEntry>>> {
+    let validators = if conditional {
+        db::http_validators(conn, sitemap_url)?
+    } else {
+        None
+    };
+    let response = site
+        .send_conditional(client, sitemap_url, validators.as_ref())
+        .await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let fresh = response_validators(response.headers());
+    let (is_index, mut entries) = read_sitemap(sitemap_url, response).await?;
+
+    // An index's children can change while the index itself doesn't
+    db::store_validators(conn, sitemap_url, fresh.as_ref().filter(|_| !is_index))?;
+
+    // A sitemap index lists child sitemaps, not articles: expand the newest ones
+    if is_index {
+        let mut pending = std::mem::take(&mut entries);
+        pending.sort_by_key(|child| std::cmp::Reverse(child.lastmod));
+        let mut fetched = 0;
+
+        while !pending.is_empty() && fetched < MAX_CHILD_SITEMAPS {
+            let child = pending.remove(0);
+            // The index's own host only, like the entries crawl_sitemap keeps
+            if site.to_primary(&child.url).is_none() {
+                warn!("Sitemap {}: outside the site, skipped", child.url);
+                continue;
+            }
+            fetched += 1;
+
+            match fetch_sitemap_file(client, site, &child.url).await {
+                Ok((false, child_entries)) => entries.extend(child_entries),
+                Ok((true, mut nested)) => {
+                    nested.sort_by_key(|child| std::cmp::Reverse(child.lastmod));
+                    pending.extend(nested);
+                }
+                Err(e) => warn!("Sitemap {}: {}", child.url, e),
+            }
+        }
+
+        if !pending.is_empty() {
+            info!(
+                "Sitemap index: fetched the newest {} child sitemaps, skipped {}",
+                fetched,
+                pending.len()
+            );
+        }
+    }
+
+    Ok(Some(entries))
+}
+
+// One sitemap file: (whether it is a <sitemapindex>, its <url> or <sitemap> entries).
+// Gzipped files (.xml.gz or a gzip Content-Type/-Encoding) are unpacked first.
+async fn fetch_sitemap_file(
+    client: &Client,
+    site: &Site,
+    sitemap_url: &str,
+) -> Result<(bool, Vec<SitemapEntry>)> {
+    read_sitemap(sitemap_url, site.send(client, sitemap_url).await?).await
+}
+
+async fn read_sitemap(
+    sitemap_url: &str,
+    response: reqwest::Response,
+) -> Result<(bool, Vec<SitemapEntry>)> {
+    if !response.status().is_success() {
+        return Err(CrawlError::HttpStatus {
+            status: response.status(),
+            url: sitemap_url.to_string(),
+        }
+        .into());
+    }
+
+    let headers = response.headers().clone();
+    let bytes = response.bytes().await?;
+
+    let header_says_gzip = [CONTENT_TYPE, CONTENT_ENCODING].iter().any(|name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.to_ascii_lowercase().contains("gzip"))
+    });
+    let looks_gzipped = bytes.starts_with(&[0x1f, 0x8b]);
+
+    let body = if looks_gzipped && (header_says_gzip || sitemap_url.ends_with(".gz")) {
+        let mut unpacked = Vec::new();
+        GzDecoder::new(&bytes[..]).read_to_end(&mut unpacked)?;
+        decode_body(&HeaderMap::new(), &unpacked)?
+    } else {
+        decode_body(&headers, &bytes)?
+    };
+
+    let mut reader = Reader::from_str(&body);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    let mut text = String::new();
+    let mut is_index = None;
+
+    let mut loc = None;
+    let mut lastmod = None;
+    let mut priority = None;
+    let mut changefreq = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                text.clear();
+                if is_index.is_none() {
+                    is_index = Some(e.local_name().as_ref() == b"sitemapindex");
+                }
+            }
+            Ok(Event::Text(e)) => {
+                text.push_str(&String::from_utf8_lossy(e.as_ref()));
+            }
+            // Entities such as &amp; in query strings arrive as separate events
+            Ok(Event::GeneralRef(e)) => {
+                if let Ok(Some(ch)) = e.resolve_char_ref() {
+                    text.push(ch);
+                } else if let Some(resolved) =
+                    resolve_predefined_entity(&String::from_utf8_lossy(e.as_ref()))
+                {
+                    text.push_str(resolved);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let value = text.trim().to_string();
+                text.clear();
+
+                match e.local_name().as_ref() {
+                    b"loc" => loc = Some(value),
+                    b"lastmod" => lastmod = parse_lastmod(&value),
+                    b"priority" => priority = value.parse().ok(),
+                    b"changefreq" => changefreq = Some(value.to_lowercase()),
+                    b"url" | b"sitemap" => {
+                        if let Some(url) = loc.take() {
+                            entries.push(SitemapEntry {
+                                url,
+                                sitemap: sitemap_url.to_string(),
+                                lastmod: lastmod.take(),
+                                priority: priority.take(),
+                                changefreq: changefreq.take(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok((is_index.unwrap_or(false), entries))
+}
+
+// Feeds advertised in the homepage <head>, then the usual feed paths. The first one
+// with entries wins.
+async fn discover_feed(
+    client: &Client,
+    site: &Site,
+    base_url: &str,
+) -> Option<(String, Vec<SitemapEntry>)> {
+    let mut candidates = Vec::new();
+
+    if let Ok(body) = site.fetch_html(client, base_url).await {
+        let document = Html::parse_document(&body);
+        let selector = Selector::parse("link[rel~=alternate][href]").unwrap();
+
+        for link in document.select(&selector) {
+            let kind = link.value().attr("type").unwrap_or("").to_lowercase();
+            if FEED_TYPES.contains(&kind.trim())
+                && let Some(href) = link.value().attr("href")
+            {
+                candidates.push(normalize_url(base_url, href));
+            }
+        }
+    }
+
+    let root = base_url.trim_end_matches('/');
+    candidates.extend(FEED_PATHS.iter().map(|path| format!("{}{}", root, path)));
+
+    let mut tried = Vec::new();
+    for feed_url in candidates {
+        if tried.contains(&feed_url) {
+            continue;
+        }
+        tried.push(feed_url.clone());
+
+        let Ok(body) = site.fetch_html(client, &feed_url).await else {
+            continue;
+        };
+        let entries = parse_feed(&body, &feed_url);
+        if !entries.is_empty() {
+            return Some((feed_url, entries));
+        }
+    }
+
+    None
+}
+
+// RSS 2.0 <item>, RSS 1.0 (RDF) <item> and Atom <entry> as sitemap entries, newest
+// first. The entry date stands in for lastmod.
+fn parse_feed(body: &str, feed_url: &str) -> Vec<SitemapEntry> {
+    let mut reader = Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    let mut text = String::new();
+
+    let mut in_entry = false;
+    let mut link = None;
+    let mut date = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                text.clear();
+                match e.local_name().as_ref() {
+                    b"item" | b"entry" => {
+                        in_entry = true;
+                        link = None;
+                        date = None;
+                    }
+                    // Atom: <link href="..."/>, the alternate (or unqualified) one
+                    b"link" if in_entry => {
+                        let attr = |name: &[u8]| {
+                            e.try_get_attribute(name)
+                                .ok()
+                                .flatten()
+                                .and_then(|a| a.unescape_value().ok())
+                                .map(|v| v.into_owned())
+                        };
+                        if let Some(href) = attr(b"href")
+                            && attr(b"rel").is_none_or(|rel| rel == "alternate")
+                        {
+                            link = Some(href);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(e)) => {
+                text.push_str(&String::from_utf8_lossy(e.as_ref()));
+            }
+            Ok(Event::CData(e)) => {
+                text.push_str(&String::from_utf8_lossy(e.as_ref()));
+            }
+            Ok(Event::GeneralRef(e)) => {
+                if let Ok(Some(ch)) = e.resolve_char_ref() {
+                    text.push(ch);
+                } else if let Some(resolved) =
+                    resolve_predefined_entity(&String::from_utf8_lossy(e.as_ref()))
+                {
+                    text.push_str(resolved);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let value = text.trim().to_string();
+                text.clear();
+
+                if !in_entry {
+                    buf.clear();
+                    continue;
+                }
+
+                match e.local_name().as_ref() {
+                    // RSS: <link>url</link>
+                    b"link" if !value.is_empty() => link = Some(value),
+                    // Publication date wins over Atom's <updated>
+                    b"pubDate" | b"published" | b"date" => date = parse_loose_date(&value),
+                    b"updated" if date.is_none() => date = parse_loose_date(&value),
+                    b"item" | b"entry" => {
+                        in_entry = false;
+                        if let Some(url) = link.take() {
+                            entries.push(SitemapEntry {
+                                url: normalize_url(feed_url, &url),
+                                sitemap: feed_url.to_string(),
+                                lastmod: date.take(),
+                                priority: None,
+                                changefreq: None,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.lastmod));
+    entries
+}
+
+// Wrappers followed per link, as many as reqwest's default redirect policy follows
+const MAX_WRAPPER_HOPS: usize = 10;
+
+// Feed entries linking through a redirector (config redirectors) replaced by where
+// the wrapper leads, so crawl_sitemap's scope checks apply to the destination.
+// Resolutions are kept in the redirects table; links that can't be resolved are
+// dropped with a warning.
+async fn unwrap_redirectors(
+    conn: &Connection,
+    config: &Config,
+    site: &Site,
+    entries: Vec<SitemapEntry>,
+) -> Result<Vec<SitemapEntry>> {
+    let redirectors = &config.redirectors;
+    if !entries.iter().any(|e| is_redirector(redirectors, &e.url)) {
+        return Ok(entries);
+    }
+
+    // Not the blog's client: its headers and credentials stay on the blog, and each
+    // hop is looked at before it is followed
+    let client = client_builder(config)?
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let mut unwrapped = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if !is_redirector(redirectors, &entry.url) {
+            unwrapped.push(entry);
+            continue;
+        }
+
+        let destination = match db::redirect_target(conn, &entry.url)? {
+            Some(target) => target,
+            None => match resolve_wrapper(&client, site, redirectors, &entry.url).await {
+                Ok(target) => {
+                    db::record_redirect(conn, &entry.url, &target, "wrapper")?;
+                    target
+                }
+                Err(e) => {
+                    warn!("Feed link {} not followed: {}", entry.url, e);
+                    continue;
+                }
+            },
+        };
+        info!("Feed link {} -> {}", entry.url, destination);
+        unwrapped.push(SitemapEntry {
+            url: destination,
+            ..entry
+        });
+    }
+
+    Ok(unwrapped)
+}
+
+// Follow a wrapper one hop at a time (Location, meta refresh, or the URL carried in
+// the wrapper's query) until the link leaves the redirectors
+async fn resolve_wrapper(
+    client: &Client,
+    site: &Site,
+    redirectors: &[String],
+    url: &str,
+) -> Result<String> {
+    let mut current = url.to_string();
+    let mut hops = 0;
+
+    while is_redirector(redirectors, &current) {
+        if hops == MAX_WRAPPER_HOPS {
+            anyhow::bail!("more than {} redirects", MAX_WRAPPER_HOPS);
+        }
+        hops += 1;
+
+        if let Some(target) = wrapped_url(redirectors, &current) {
+            current = target;
+            continue;
+        }
+
+        site.throttle.wait(&current).await?;
+        let response = client.get(&current).send().await?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| anyhow::anyhow!("{} without a Location", status))?;
+            current = normalize_url(&current, location);
+            continue;
+        }
+
+        let body = read_html(&current, response).await?;
+        match meta_refresh(&Html::parse_document(&body), &current) {
+            Some((delay, target)) if delay <= site.meta_refresh_max_secs => current = target,
+            _ => anyhow::bail!("{} {} leads nowhere", status, current),
+        }
+    }
+
+    Ok(current)
+}
+
+// A redirector is a host ("t.co", www. or not) or a path with the query parameter
+// holding the destination ("/outbound?url=")
+fn is_redirector(redirectors: &[String], url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+
+    redirectors.iter().any(|redirector| {
+        if !redirector.starts_with('/') {
+            return url
+                .host_str()
+                .is_some_and(|host| same_host(host, &redirector.to_ascii_lowercase()));
+        }
+        let (path, param) = redirector.split_once('?').unwrap_or((redirector, ""));
+        let param = param.trim_end_matches('=');
+        url.path() == path && (param.is_empty() || url.query_pairs().any(|(k, _)| k == param))
+    })
+}
+
+// The absolute http(s) destination in a path redirector's query parameter
+fn wrapped_url(redirectors: &[String], url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+
+    redirectors.iter().find_map(|redirector| {
+        let (path, param) = redirector.split_once('?')?;
+        if url.path() != path {
+            return None;
+        }
+        let param = param.trim_end_matches('=');
+        let (_, value) = url.query_pairs().find(|(k, _)| k == param)?;
+        let target = Url::parse(&value).ok()?;
+        matches!(target.scheme(), "http" | "https").then(|| db::canonical_url(target.as_str()))
+    })
+}
+
+// W3C datetime as used by sitemaps: full timestamp or a bare date
+fn parse_lastmod(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+async fn crawl_html(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    budget: &Budget,
+) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+
+    // Insert root if not exists (depth 0)
+    db::enqueue(conn, &site.base_url, None)?;
+
+    let mut new_count = 0;
+
+    // Resume after the page an interrupted run stopped at. Once the queue's end is
+    // reached, pages before the checkpoint get their turn, up to the checkpoint.
+    let mut scopes = vec![Url::parse(&site.base_url)?.origin().ascii_serialization() + "/"];
+    for allowed in &site.allowed_hosts {
+        let authority = &allowed[url::Position::BeforeHost..url::Position::AfterPort];
+        scopes.push(format!("http://{}/", authority));
+        scopes.push(format!("https://{}/", authority));
+    }
+    let start = db::html_cursor(conn, &site.name)?;
+    let mut cursor = start;
+    let mut wrapped = start == 0;
+
+    loop {
+        // Stop if limit reached
+        if !budget.allows(site, new_count) {
+            break;
+        }
+
+        let mut targets = db::next_pending(conn, &scopes, site.max_depth, cursor, 10)?;
+        if wrapped && start > 0 {
+            targets.retain(|(rowid, _, _)| *rowid <= start);
+        }
+        if targets.is_empty() {
+            if wrapped {
+                // Drained: the next run starts from the front of the queue
+                db::set_html_cursor(conn, &site.name, 0)?;
+                break;
+            }
+            wrapped = true;
+            cursor = 0;
+            continue;
+        }
+
+        for (rowid, url, depth) in targets {
+            if !budget.allows(site, new_count) {
+                break;
+            }
+
+            // Not even fetched for its links
+            if classify::is_taken_down(&site.takedowns, &url) {
+                site.count_takedown(&url);
+                db::mark_done(conn, &url)?;
+                cursor = rowid;
+                db::set_html_cursor(conn, &site.name, cursor)?;
+                continue;
+            }
+            if !site.robots.allows(&url) {
+                site.report.borrow_mut().robots_blocked += 1;
+                db::mark_robots_blocked(conn, &url, None)?;
+                cursor = rowid;
+                db::set_html_cursor(conn, &site.name, cursor)?;
+                continue;
+            }
+
+            // At max_depth the page is only a candidate article; its links aren't followed
+            let expanded = if depth < site.max_depth {
+                crawl_page(conn, client, site, &url).await
+            } else {
+                Ok(0)
+            };
+
+            match expanded {
+                Ok(_) => {
+                    let parent = db::queue_parent(conn, &url)?;
+
+                    // Listing pages are only worth their links
+                    let inserted = if classify::is_listing_url(&url) {
+                        site.report.borrow_mut().listing_pages += 1;
+                        false
+                    } else {
+                        match crawl_article(
+                            conn,
+                            client,
+                            site,
+                            &url,
+                            parent.as_deref(),
+                            &now,
+                            FetchMode::New,
+                        )
+                        .await
+                        {
+                            Ok(inserted) => inserted,
+                            Err(e) if is_rate_limited(&e) => {
+                                site.report.borrow_mut().record_error(&e);
+                                return Ok(());
+                            }
+                            Err(e) => {
+                                warn!("{}", e);
+                                site.report.borrow_mut().record_error(&e);
+                                false
+                            }
+                        }
+                    };
+
+                    if inserted {
+                        new_count += 1;
+                    }
+
+                    db::mark_done(conn, &url)?;
+                }
+                Err(e) => {
+                    warn!("{}: {}", url, e);
+                    site.report.borrow_mut().record_error(&e);
+
+                    // Stays pending; the next run resumes here
+                    if is_rate_limited(&e) {
+                        return Ok(());
+                    }
+
+                    // The rest of the host would fail the same way
+                    if let Some(CrawlError::Tls { .. }) = e.downcast_ref::<CrawlError>() {
+                        if let Some(key) = domain_key(&url) {
+                            site.cache
+                                .register_error(conn, &key, "tls", TLS_RETRY_DAYS)?;
+                        }
+                        return Err(e);
+                    }
+
+                    if db::mark_retry(conn, &url, &e.to_string())? {
+                        warn!("Giving up on {} after repeated errors", url);
+                    }
+                }
+            }
+
+            cursor = rowid;
+            db::set_html_cursor(conn, &site.name, cursor)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn crawl_page(conn: &Connection, client: &Client, site: &Site, url: &str) -> Result<usize> {
+    let response = site.send(client, url).await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Status error {}", response.status());
+    }
+
+    // HTML only
+    if let Some(ct) = response.headers().get(reqwest::header::CONTENT_TYPE) {
+        if !ct.to_str()?.contains("text/html") {
+            return Ok(0);
+        }
+    } else if !is_article_link(url) {
+        return Ok(0);
+    }
+
+    let body = response.text().await?;
+    let document = Html::parse_document(&body);
+    let selector = Selector::parse("a").unwrap();
+
+    // Declared translations in unwanted languages are not followed
+    let unwanted: Vec<String> = if site.languages.is_empty() {
+        Vec::new()
+    } else {
+        hreflang_alternates(&document, url)
+            .into_iter()
+            .filter(|(lang, _)| !site.is_preferred_language(lang))
+            .filter_map(|(_, alternate)| site.to_primary(&alternate))
+            .collect()
+    };
+
+    let mut added = 0;
+
+    for element in document.select(&selector) {
+        if let Some(href) = element.value().attr("href") {
+            let Some(next_url) = site.to_primary(&normalize_url(url, href)) else {
+                continue;
+            };
+
+            if unwanted.contains(&next_url) {
+                continue;
+            }
+
+            // Recorded so the URL shows up as blocked rather than never found
+            if !site.robots.allows(&next_url) {
+                db::mark_robots_blocked(conn, &next_url, Some(url))?;
+                continue;
+            }
+
+            if !site.url_filter.allows(&next_url) {
+                continue;
+            }
+
+            if db::enqueue(conn, &next_url, Some(url))? {
+                added += 1;
+            }
+        }
+    }
+
+    Ok(added)
+}
+
+// What crawl_article does with an article that is already stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FetchMode {
+    // Discovery: stored rows are left as they are
+    New,
+    // Refresh pass: stored fields are overwritten
+    Refresh,
+    // One URL on request: overwritten, and the recent-error backoff is ignored
+    Recrawl,
+}
+
+// Ok(true) when a row was inserted, or updated outside FetchMode::New
+#[tracing::instrument(name = "article", skip_all, fields(url = %url))]
+async fn crawl_article(
+    conn: &Connection,
+    client: &Client,
+    site: &Site,
+    url: &str,
+    discovered_from: Option<&str>,
+    fetched_at: &str,
+    mode: FetchMode,
+) -> Result<bool> {
+    match site.url_skip(url) {
+        Some(UrlSkip::RecentError(_)) if mode == FetchMode::Recrawl => {}
+        Some(UrlSkip::RecentError(_)) => {
+            info!("Skipping {} due to recent error", url);
+            return Ok(false);
+        }
+        Some(UrlSkip::TakenDown) => {
+            site.count_takedown(url);
+            return Ok(false);
+        }
+        Some(UrlSkip::Robots) => {
+            site.report.borrow_mut().robots_blocked += 1;
+            db::mark_robots_blocked(conn, url, None)?;
+            return Ok(false);
+        }
+        Some(UrlSkip::Boilerplate) => {
+            site.boilerplate_skipped
+                .set(site.boilerplate_skipped.get() + 1);
+            return Ok(false);
+        }
+        Some(UrlSkip::Filtered) => {
+            site.report.borrow_mut().filtered += 1;
+            return Ok(false);
+        }
+        None => {}
+    }
+
+    // A recrawl is asked for explicitly and always takes the whole page
+    site.report.borrow_mut().attempted += 1;
+    let fetch_result = match mode {
+        FetchMode::Recrawl => site.fetch_page(client, url).await.map(Some),
+        FetchMode::New | FetchMode::Refresh => site.fetch_page_if_modified(conn, client, url).await,
+    };
+
+    if let Err(ref e) = fetch_result
+        && let Some(crawl_err) = e.downcast_ref::<CrawlError>()
+    {
+        match crawl_err {
+            CrawlError::HttpStatus { status, url }
+                if site.authenticated && is_auth_failure(*status) =>
+            {
+                warn!("Credentials rejected for {}: {} {}", site.name, status, url);
+            }
+            CrawlError::HttpStatus { status, url } => {
+                warn!("Status error: {} {}", status, url);
+
+                if *status == StatusCode::NOT_FOUND {
+                    site.cache.register_error(conn, url, "404", 7)?;
+                }
+            }
+            CrawlError::AuthRejected { .. } | CrawlError::RateLimited { .. } => {}
+            CrawlError::Tls { url, detail } => {
+                warn!("TLS error: {} {}", url, detail);
+
+                if let Some(key) = domain_key(url) {
+                    site.cache
+                        .register_error(conn, &key, "tls", TLS_RETRY_DAYS)?;
+                }
+            }
+        }
+    }
+
+    let Some((final_url, body)) = fetch_result? else {
+        site.report.borrow_mut().not_modified += 1;
+        return Ok(false);
+    };
+
+    let mut record_redirect = |from: &str, to: &str, kind: &str| -> Result<()> {
+        db::record_redirect(conn, from, to, kind)?;
+        site.cache.record_redirect(from);
+        Ok(())
+    };
+    let requested = url;
+    let resolved = resolve_article(
+        site,
+        client,
+        url,
+        final_url,
+        body,
+        mode == FetchMode::New,
+        &mut record_redirect,
+    )
+    .await?;
+    let (url, document) = match resolved {
+        Ok(page) => page,
+        Err(Unresolved::Boilerplate) => {
+            site.boilerplate_skipped
+                .set(site.boilerplate_skipped.get() + 1);
+            return Ok(false);
+        }
+        Err(Unresolved::Stored) => return Ok(false),
+        Err(skip) => {
+            info!("Skipping {}: {}", url, skip);
+            return Ok(false);
+        }
+    };
+    let url = url.as_str();
+
+    let mut article = extract_article(&document, url);
+    // A suffix that is the blog's configured name, or the site name another page
+    // of this source already showed
+    if let Some(title) = strip_site_suffix(&article.title, |suffix| {
+        same_name(suffix, &site.name)
+            || db::site_name_seen(conn, &site.name, suffix, url).unwrap_or(false)
+    }) {
+        article.title = title;
+    }
+    let title = &article.title;
+    let description = article.description.as_deref();
+
+    match site.page_skip(&document, &article) {
+        Some(PageSkip::Boilerplate) => {
+            site.boilerplate_skipped
+                .set(site.boilerplate_skipped.get() + 1);
+            return Ok(false);
+        }
+        Some(PageSkip::Filtered) => {
+            site.report.borrow_mut().filtered += 1;
+            return Ok(false);
+        }
+        Some(PageSkip::Translation { lang, original }) => {
+            info!("Skipping {} copy {} of {}", lang, url, original);
+            db::enqueue(conn, &original, Some(url))?;
+            return Ok(false);
+        }
+        Some(PageSkip::TooOld) => {
+            site.report.borrow_mut().too_old += 1;
+            return Ok(false);
+        }
+        Some(PageSkip::Expired(expiry)) => {
+            info!("Skipping {}: unavailable after {}", url, expiry);
+            site.report.borrow_mut().expired += 1;
+            return Ok(false);
+        }
+        None => {}
+    }
+
+    let site_name = article.site_name.as_deref().unwrap_or(&site.name);
+
+    // The same entry under another permalink or on a mirror
+    if mode == FetchMode::New
+        && site.duplicate_titles != DuplicateTitles::Keep
+        && let Some((existing, existing_published)) = db::title_duplicate(conn, url, "blog", title)?
+    {
+        let earlier = match (
+            article.published_at.as_deref().and_then(parse_lastmod),
+            existing_published.as_deref().and_then(parse_lastmod),
+        ) {
+            (Some(new), Some(old)) => new < old,
+            _ => false,
+        };
+
+        if site.duplicate_titles == DuplicateTitles::KeepEarliest && earlier {
+            info!("Replacing {} with its earlier copy {}", existing, url);
+            db::supersede(conn, &existing, url)?;
+        } else {
+            info!("Skipping {}: same title as {}", url, existing);
+            site.report.borrow_mut().duplicate_titles += 1;
+            return Ok(false);
+        }
+    }
+
+    if site.dry_run && mode == FetchMode::New {
+        if site.cache.fetched_at(url).is_some() {
+            return Ok(false);
+        }
+        info!(
+            "Would insert article: {} ({}) score {}",
+            title,
+            url,
+            score_article(&article, &site.scoring, site.store_fulltext)?
+        );
+        if let Some(description) = description {
+            info!("    {}", description);
+        }
+        site.report.borrow_mut().inserted += 1;
+        site.cache.record_content(url, fetched_at);
+        return Ok(true);
+    }
+
+    // A stored permalink that now lands on another URL, as after a platform
+    // migration. The article goes on under the destination's row, which keeps the
+    // first-seen time of the old one when it is new, so it isn't announced again.
+    let moved_from = match mode {
+        FetchMode::Refresh | FetchMode::Recrawl
+            if db::canonical_url(url) != db::canonical_url(requested) =>
+        {
+            db::live_fetched_at(conn, requested)?
+        }
+        _ => None,
+    };
+    let first_seen = match &moved_from {
+        Some(first_seen) if !db::content_exists(conn, url)? => first_seen,
+        _ => fetched_at,
+    };
+
+    let content = db::NewContent {
+        id: url, // URL as unique ID
+        content_type: "blog",
+        title,
+        url,
+        description,
+        thumbnail: article.thumbnail.as_deref(),
+        published_at: article.published_at.as_deref(),
+        fetched_at: first_seen,
+        source: Some(&site.name),
+        discovered_from,
+    };
+
+    // Refresh and recrawl overwrite what an earlier run stored
+    let result = if mode == FetchMode::New {
+        db::insert(conn, &content)
+    } else {
+        db::upsert(conn, &content, site.max_revisions)
+    };
+
+    let moved = result.is_ok() && moved_from.is_some();
+    if moved {
+        info!("Article moved: {} -> {}", requested, url);
+        db::record_move(conn, requested, url)?;
+        db::mark_refreshed(conn, url, fetched_at)?;
+        site.cache.record_content(url, first_seen);
+        site.report.borrow_mut().moved.push(Moved {
+            from: db::canonical_url(requested),
+            to: url.to_string(),
+        });
+    }
+    let refreshed = mode != FetchMode::New && (matches!(result, Ok(false)) || moved);
+
+    if refreshed {
+        info!("Refreshed article: {}", url);
+        site.report.borrow_mut().refreshed += 1;
+        db::set_content_stats(conn, url, article.text_length, article.image_count)?;
+        db::set_site_name(conn, url, site_name)?;
+    }
+
+    if let Ok(true) = result
+        && !moved
+    {
+        info!("Crawl and insert article: {}", url);
+        {
+            let mut report = site.report.borrow_mut();
+            report.inserted += 1;
+            report.record_extraction(
+                title,
+                description,
+                article.published_at.is_some(),
+                article.thumbnail.is_some(),
+            );
+        }
+        site.cache.record_content(url, fetched_at);
+        db::set_content_stats(conn, url, article.text_length, article.image_count)?;
+        db::set_site_name(conn, url, site_name)?;
+    }
+
+    if result.is_ok() {
+        for video_id in &article.embedded_videos {
+            db::record_embed(conn, url, video_id)?;
+        }
+        db::record_tags(conn, url, "site_tag", &article.tags)?;
+        db::set_expires_at(conn, url, article.unavailable_after.as_deref())?;
+        db::set_excerpt(conn, url, article.excerpt.as_deref())?;
+
+        if site.store_fulltext
+            && let Some(text) = &article.main_text
+        {
+            db::set_content_text(conn, url, text)?;
+        }
+    }
+
+    result.map(|inserted| inserted || refreshed)
+}
+
+// Why a fetched page doesn't stand for an article of the site
+enum Unresolved {
+    // Redirected to a URL outside the site
+    OffSite(String),
+    // Redirected to an article stored already (only checked for new URLs)
+    Stored,
+    // A large page whose <title> is boilerplate
+    Boilerplate,
+    // A meta refresh that isn't followed: off the site or too slow
+    RefreshStub(String),
+    // A followed meta refresh landing on another one
+    RefreshChain(String),
+}
+
+impl std::fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Unresolved::OffSite(target) => write!(f, "redirected off the site to {}", target),
+            Unresolved::Stored => write!(f, "redirected to a stored article"),
+            Unresolved::Boilerplate => write!(f, "boilerplate page"),
+            Unresolved::RefreshStub(target) => {
+                write!(
+                    f,
+                    "meta-refresh stub for {} (not followed: off the site or slower than meta_refresh_max_secs)",
+                    target
+                )
+            }
+            Unresolved::RefreshChain(target) => {
+                write!(f, "meta-refresh chain via {}", target)
+            }
+        }
+    }
+}
+
+// The article a fetched page stands for, shared by crawls and crawl_single: HTTP
+// redirects (http -> https, moved permalinks) are followed by the client, one short
+// same-site meta refresh here, and <link rel="canonical"> names the article itself
+// (share links, paginated comments). Every hop stays within site.to_primary; each one
+// taken is passed to `on_redirect` as (from, to, kind), which crawls record and trace
+// reports. Returns the article's URL and document.
+async fn resolve_article(
+    site: &Site,
+    client: &Client,
+    url: &str,
+    final_url: String,
+    body: String,
+    skip_stored: bool,
+    on_redirect: &mut impl FnMut(&str, &str, &str) -> Result<()>,
+) -> Result<std::result::Result<(String, Html), Unresolved>> {
+    // The article is stored under where the redirects ended, once
+    let url = if final_url == url {
+        url.to_string()
+    } else {
+        match site.to_primary(&final_url) {
+            None => return Ok(Err(Unresolved::OffSite(final_url))),
+            Some(target) if target != db::canonical_url(url) => {
+                on_redirect(url, &target, "http")?;
+                info!("Following redirect {} -> {}", url, target);
+
+                if skip_stored && site.cache.fetched_at(&target).is_some() {
+                    return Ok(Err(Unresolved::Stored));
+                }
+                target
+            }
+            Some(_) => url.to_string(),
+        }
+    };
+
+    if body.len() > LARGE_PAGE_BYTES
+        && let Some(head_end) = body.find("</head>")
+        && page_title(&body[..head_end]).is_some_and(|t| site.boilerplate.matches_title(&t))
+    {
+        return Ok(Err(Unresolved::Boilerplate));
+    }
+
+    let document = Html::parse_document(&body);
+
+    // A stub that only forwards to the migrated article is indexed as its target
+    let (url, document) = match site.refresh_target(&document, &url) {
+        None => (url, document),
+        Some(Err(target)) => return Ok(Err(Unresolved::RefreshStub(target))),
+        Some(Ok(target)) => {
+            site.report.borrow_mut().attempted += 1;
+            let body = site.fetch_html(client, &target).await?;
+            let target_document = Html::parse_document(&body);
+            if meta_refresh(&target_document, &target).is_some() {
+                return Ok(Err(Unresolved::RefreshChain(target)));
+            }
+
+            on_redirect(&url, &target, "meta_refresh")?;
+            info!("Following meta refresh {} -> {}", url, target);
+            (target, target_document)
+        }
+    };
+
+    // Only followed within the site. Themes that point every page at the homepage
+    // are ignored.
+    let canonical = canonical_link(&document, &url)
+        .and_then(|c| site.to_primary(&c))
+        .filter(|c| Url::parse(c).is_ok_and(|u| u.path() != "/"));
+    let url = match canonical {
+        Some(canonical) if canonical != url => {
+            on_redirect(&url, &canonical, "canonical")?;
+            canonical
+        }
+        _ => url,
+    };
+
+    Ok(Ok((url, document)))
+}
+
+// Everything the extraction pipeline reads from one article page
+#[derive(Debug, Serialize)]
+pub struct ExtractedArticle {
+    pub url: String,
+    // The requested URL, when a meta refresh was followed from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirected_from: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub published_at: Option<String>,
+    // og:image / twitter:image / first article image, absolute
+    pub thumbnail: Option<String>,
+    pub language: Option<String>,
+    pub site_name: Option<String>,
+    // robots meta unavailable_after, as RFC 3339 UTC
+    pub unavailable_after: Option<String>,
+    pub tags: Vec<String>,
+    pub embedded_videos: Vec<String>,
+    pub text_length: i64,
+    pub image_count: i64,
+    // Start of the main text
+    pub excerpt: Option<String>,
+    #[serde(skip)]
+    pub main_text: Option<String>,
+    // Export score; only computed by crawl_single
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i32>,
+}
+
+// The database-free part of crawl_article: what gets stored for a parsed page
+fn extract_article(document: &Html, url: &str) -> ExtractedArticle {
+    let title_selector = Selector::parse("title").unwrap();
+
+    let page_title = document
+        .select(&title_selector)
+        .next()
+        .map(|t| t.text().collect::<String>());
+    let raw_title = meta_content(document, &[r#"meta[property="og:title"]"#])
+        .or(page_title)
+        .unwrap_or_else(|| "No Title".to_string());
+
+    // The page's own og:site_name is enough to strip it; other suffixes need
+    // the site context (crawl_article)
+    let og_site_name = meta_content(document, &[r#"meta[property="og:site_name"]"#]);
+    let title = og_site_name
+        .as_deref()
+        .and_then(|name| strip_site_suffix(&raw_title, |suffix| same_name(suffix, name)))
+        .unwrap_or_else(|| raw_title.clone());
+
+    let description = meta_content(
+        document,
+        &[
+            "meta[name=description]",
+            r#"meta[property="og:description"]"#,
+            r#"meta[name="twitter:description"], meta[property="twitter:description"]"#,
+        ],
+    );
+
+    let (text_length, image_count) = content_stats(document);
+    let main_text = fulltext::main_text(document);
+
+    ExtractedArticle {
+        url: url.to_string(),
+        redirected_from: None,
+        site_name: site_name(document, &raw_title),
+        title,
+        description,
+        published_at: published_time(document).map(|d| d.to_rfc3339()),
+        thumbnail: thumbnail(document, url),
+        language: page_language(document),
+        unavailable_after: unavailable_after(document).map(|d| d.to_rfc3339()),
+        tags: site_tags(document),
+        embedded_videos: embedded_video_ids(document),
+        text_length,
+        image_count,
+        excerpt: main_text.as_deref().map(fulltext::excerpt),
+        main_text,
+        score: None,
+    }
+}
+
+// Fetch one URL and run the full extraction on it, without touching the database:
+// the pipeline of a crawl (resolve_article, extract_article, the score) under the
+// settings of the configured blog covering the URL. Without one, the URL's origin is
+// the site, with the config's settings, or the defaults when there is no config.
+pub async fn crawl_single(
+    client: &Client,
+    url: &str,
+    config: Option<&Config>,
+) -> Result<ExtractedArticle> {
+    let defaults;
+    let config = match config {
+        Some(config) => config,
+        None => {
+            defaults = Config::default();
+            &defaults
+        }
+    };
+
+    let cache = Rc::new(RunCache::default());
+    let (site, canonical) = match site_for_url(config, url, &cache)? {
+        Some((_, site, canonical)) => (site, canonical),
+        None => {
+            let blog = BlogConfig::for_url(url)?;
+            let site = Site::new(
+                &blog,
+                blog.url.clone(),
+                Boilerplate::new(&config.boilerplate, &blog)?,
+                UrlFilter::new(&blog)?,
+                config,
+                Robots::allow_all(),
+                cache,
+            );
+            (site, db::canonical_url(url))
+        }
+    };
+
+    let (final_url, body) = site.fetch_page(client, &canonical).await?;
+    let resolved = resolve_article(
+        &site,
+        client,
+        &canonical,
+        final_url,
+        body,
+        false,
+        &mut |_, _, _| Ok(()),
+    )
+    .await?;
+    let (page_url, document) = match resolved {
+        Ok(page) => page,
+        Err(skip) => anyhow::bail!("{}: {}", url, skip),
+    };
+
+    let mut article = extract_article(&document, &page_url);
+    if page_url != url {
+        article.redirected_from = Some(url.to_string());
+    }
+    if let Some(title) = strip_site_suffix(&article.title, |suffix| same_name(suffix, &site.name)) {
+        article.title = title;
+    }
+
+    article.score = Some(score_article(&article, &site.scoring, site.store_fulltext)?);
+
+    Ok(article)
+}
+
+// Export score of an extracted, not yet stored article; its main text counts when it
+// would be stored
+fn score_article(
+    article: &ExtractedArticle,
+    scoring: &ScoringConfig,
+    store_fulltext: bool,
+) -> Result<i32> {
+    let content = db::Content {
+        id: article.url.clone(),
+        content_type: "blog".to_string(),
+        title: article.title.clone(),
+        url: article.url.clone(),
+        description: article.description.clone(),
+        thumbnail: article.thumbnail.clone(),
+        published_at: article.published_at.clone(),
+        category: None,
+        fingerprint: db::fingerprint(&article.title, article.description.as_deref()),
+        source: None,
+        fetched_at: Utc::now().to_rfc3339(),
+        discovered_from: None,
+        text_length: Some(article.text_length),
+        image_count: Some(article.image_count),
+        site_name: article.site_name.clone(),
+        expires_at: article.unavailable_after.clone(),
+        excerpt: article.excerpt.clone(),
+        title_hash: db::title_hash(&article.title),
+        main_text: article.main_text.clone().filter(|_| store_fulltext),
+        dead_at: None,
+        archived_url: None,
+        superseded_by: None,
+        refreshed_at: None,
+    };
+    let terms = TermMatcher::new(scoring)?;
+
+    Ok(export::calculate_score(&content, scoring, &terms))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Block,
+    // Informational; doesn't stop the URL on its own
+    Note,
+}
+
+// One decision point crawl_article (or the discovery loops) would apply to a URL
+#[derive(Debug)]
+pub struct TraceStep {
+    pub gate: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+#[derive(Default)]
+struct TraceLog(Vec<TraceStep>);
+
+impl TraceLog {
+    fn add(&mut self, gate: &'static str, verdict: Verdict, detail: impl Into<String>) {
+        self.0.push(TraceStep {
+            gate,
+            verdict,
+            detail: detail.into(),
+        });
+    }
+
+    fn blocked(&self) -> bool {
+        self.0.iter().any(|step| step.verdict == Verdict::Block)
+    }
+}
+
+// The configured blog covering `url`, with the URL in its primary form
+fn site_for_url<'a>(
+    config: &'a Config,
+    url: &str,
+    cache: &Rc<RunCache>,
+) -> Result<Option<(&'a BlogConfig, Site, String)>> {
+    for blog in &config.blogs {
+        let boilerplate = Boilerplate::new(&config.boilerplate, blog)?;
+        let site = Site::new(
+            blog,
+            blog.url.clone(),
+            boilerplate,
+            UrlFilter::new(blog)?,
+            config,
+            Robots::allow_all(),
+            Rc::clone(cache),
+        );
+        if let Some(canonical) = site.to_primary(url) {
+            return Ok(Some((blog, site, canonical)));
+        }
+    }
+    Ok(None)
+}
+
+// Entry point for: crawler recrawl <config.json> <url>. Fetches one article again and
+// overwrites its stored fields, ignoring the recent-error backoff; robots.txt still applies.
+pub async fn recrawl(conn: &Connection, config: &Config, url: &str) -> Result<bool> {
+    let cache = Rc::new(RunCache::load(conn)?);
+    let Some((blog, mut site, canonical)) = site_for_url(config, url, &cache)? else {
+        anyhow::bail!("No configured blog covers {}", url);
+    };
+
+    let client = build_client(config, blog)?;
+    site.robots = robots::fetch(&client, &blog.url).await?;
+
+    let fetched_at = Utc::now().to_rfc3339();
+    crawl_article(
+        conn,
+        &client,
+        &site,
+        &canonical,
+        None,
+        &fetched_at,
+        FetchMode::Recrawl,
+    )
+    .await
+}
+
+// Walk the pipeline for one URL using the production checks, read-only: the page is
+// only fetched when no pre-fetch gate blocks it, and nothing is written. The https
+// probe is not run; each blog's configured URL is its base.
+pub async fn trace(conn: &Connection, config: &Config, url: &str) -> Result<Vec<TraceStep>> {
+    let mut steps = TraceLog::default();
+
+    let cache = Rc::new(RunCache::load(conn)?);
+    let scope = site_for_url(config, url, &cache)?;
+
+    let Some((blog, mut site, canonical)) = scope else {
+        steps.add(
+            "scope",
+            Verdict::Block,
+            "no configured blog covers this URL",
+        );
+        return Ok(steps.0);
+    };
+    steps.add(
+        "scope",
+        Verdict::Pass,
+        format!(
+            "source {} ({}); canonical form {}",
+            blog.name, blog.url, canonical
+        ),
+    );
+    let url = canonical.as_str();
+
+    if let Some(quiet_hours) = &blog.quiet_hours
+        && quiet_hours.contains(Utc::now())?
+    {
+        steps.add(
+            "quiet_hours",
+            Verdict::Block,
+            format!(
+                "source deferred until {} ({})",
+                quiet_hours.end, quiet_hours.tz
+            ),
+        );
+    }
+
+    let client = build_client(config, blog)?;
+    match robots::fetch(&client, &blog.url).await {
+        Ok(robots) => {
+            site.robots = robots;
+            if site.robots.allows(url) {
+                steps.add("robots", Verdict::Pass, "allowed by robots.txt");
+            } else {
+                steps.add("robots", Verdict::Block, "disallowed by robots.txt");
+            }
+        }
+        Err(e) => steps.add(
+            "robots",
+            Verdict::Block,
+            format!("{}; the source is skipped this run", e),
+        ),
+    }
 
-    let client = Client::new();
+    match site.url_skip(url) {
+        Some(UrlSkip::RecentError(retry_after)) => steps.add(
+            "error_backoff",
+            Verdict::Block,
+            format!(
+                "recent error on the URL or its domain; retried after {}",
+                retry_after.to_rfc3339()
+            ),
+        ),
+        Some(UrlSkip::TakenDown) => {
+            steps.add("takedown", Verdict::Block, "matches config takedowns")
+        }
+        // Reported by the robots step above
+        Some(UrlSkip::Robots) => {}
+        Some(UrlSkip::Boilerplate) => steps.add(
+            "boilerplate",
+            Verdict::Block,
+            "URL matches a boilerplate path",
+        ),
+        Some(UrlSkip::Filtered) => steps.add(
+            "url_patterns",
+            Verdict::Block,
+            "left out by the blog's include_patterns / exclude_patterns",
+        ),
+        None => steps.add(
+            "url_filters",
+            Verdict::Pass,
+            "no error backoff, takedown, boilerplate path or URL pattern",
+        ),
+    }
 
-    // Try sitemap first
-    if let Ok(urls) = fetch_sitemap(&client, base_url).await {
-        println!("Crawl sitemap");
-        let mut counter = 0;
-        let now = Utc::now().to_rfc3339();
+    if classify::is_listing_url(url) {
+        steps.add(
+            "listing",
+            Verdict::Note,
+            "listing page: HTML crawls follow its links but never store it",
+        );
+    }
+    if !is_article_link(url) {
+        steps.add(
+            "link_filter",
+            Verdict::Note,
+            "not article-like: HTML crawls skip it when the server sends no Content-Type",
+        );
+    }
 
-        for url in urls {
-            let inserted = crawl_article(conn, &client, &url, &now, false)
-                .await
-                .unwrap_or_else(|e| {
-                    eprintln!("Blog warn: {}", e);
-                    false
-                });
+    match cache.fetched_at(url) {
+        Some(fetched_at) => steps.add(
+            "stored",
+            Verdict::Note,
+            format!(
+                "already in contents (fetched {}); refetched only for a newer lastmod",
+                fetched_at.to_rfc3339()
+            ),
+        ),
+        None => steps.add("stored", Verdict::Pass, "not in contents"),
+    }
+    if cache.is_redirect(url) {
+        steps.add(
+            "redirect",
+            Verdict::Note,
+            "recorded as a redirect stub; stored under its target",
+        );
+    }
+    match db::queue_entry(conn, url)? {
+        Some((status, retries, next_retry_at)) => steps.add(
+            "queue",
+            Verdict::Note,
+            format!(
+                "{} ({} retries{})",
+                status,
+                retries,
+                next_retry_at
+                    .map(|t| format!(", next at {}", t))
+                    .unwrap_or_default()
+            ),
+        ),
+        None => steps.add("queue", Verdict::Note, "not queued (sitemap or seed only?)"),
+    }
 
-            if inserted {
-                counter += 1;
-            }
+    if steps.blocked() {
+        return Ok(steps.0);
+    }
 
-            if counter >= MAX_NEW_PER_SITE {
-                println!("Reached limit, stopping this site.");
-                break;
+    let (final_url, body) = match fetch_page(&client, url).await {
+        Ok(page) => page,
+        Err(e) => {
+            steps.add("fetch", Verdict::Block, e.to_string());
+            return Ok(steps.0);
+        }
+    };
+    steps.add("fetch", Verdict::Pass, format!("{} bytes", body.len()));
+
+    // The same redirect, meta-refresh and canonical handling as a crawl; each hop it
+    // takes becomes a step, nothing is recorded
+    let mut hops = Vec::new();
+    let resolved = resolve_article(
+        &site,
+        &client,
+        url,
+        final_url,
+        body,
+        true,
+        &mut |_: &str, to: &str, kind: &str| {
+            hops.push((kind.to_string(), to.to_string()));
+            Ok(())
+        },
+    )
+    .await;
+    for (kind, to) in hops {
+        match kind.as_str() {
+            "http" => steps.add(
+                "http_redirect",
+                Verdict::Note,
+                format!("redirected to {}; stored under the target", to),
+            ),
+            "meta_refresh" => {
+                steps.add("meta_refresh", Verdict::Pass, format!("followed to {}", to))
             }
+            _ => steps.add(
+                "canonical",
+                Verdict::Note,
+                format!("rel=canonical names {}; stored under it", to),
+            ),
+        }
+    }
+    let (page_url, document) = match resolved? {
+        Ok(page) => page,
+        Err(unresolved) => {
+            let gate = match unresolved {
+                Unresolved::OffSite(_) | Unresolved::Stored => "http_redirect",
+                Unresolved::Boilerplate => "boilerplate",
+                Unresolved::RefreshStub(_) | Unresolved::RefreshChain(_) => "meta_refresh",
+            };
+            steps.add(gate, Verdict::Block, unresolved.to_string());
+            return Ok(steps.0);
         }
+    };
 
-        return Ok(());
+    let mut article = extract_article(&document, &page_url);
+    match site.page_skip(&document, &article) {
+        Some(PageSkip::Boilerplate) => steps.add(
+            "boilerplate",
+            Verdict::Block,
+            format!("title {:?} is boilerplate", article.title),
+        ),
+        Some(PageSkip::Filtered) => steps.add(
+            "url_patterns",
+            Verdict::Block,
+            format!(
+                "resolved URL {} is left out by include_patterns / exclude_patterns",
+                article.url
+            ),
+        ),
+        Some(PageSkip::Translation { lang, original }) => steps.add(
+            "language",
+            Verdict::Block,
+            format!("{} copy; the original {} is queued instead", lang, original),
+        ),
+        Some(PageSkip::TooOld) => steps.add(
+            "age",
+            Verdict::Block,
+            format!(
+                "published {:?}, past max_article_age_days",
+                article.published_at
+            ),
+        ),
+        Some(PageSkip::Expired(expiry)) => steps.add(
+            "unavailable_after",
+            Verdict::Block,
+            format!("expired {}", expiry),
+        ),
+        None => steps.add("page_filters", Verdict::Pass, "stored by a crawl"),
     }
 
-    // Fallback to HTML link scraping
-    println!("Crawl via HTML link scraping");
-    crawl_html(conn, base_url, MAX_NEW_PER_SITE).await
+    article.score = Some(score_article(
+        &article,
+        &config.scoring,
+        site.store_fulltext,
+    )?);
+    steps.add(
+        "extraction",
+        Verdict::Note,
+        serde_json::to_string_pretty(&article)?,
+    );
+
+    Ok(steps.0)
 }
 
-async fn fetch_sitemap(client: &Client, base_url: &str) -> Result<Vec<String>> {
-    let sitemap_url = format!("{}/sitemap.xml", base_url.trim_end_matches('/'));
+// Visible body text length (non-whitespace characters) and number of images
+fn content_stats(document: &Html) -> (i64, i64) {
+    let body_selector = Selector::parse("body").unwrap();
+    let img_selector = Selector::parse("img").unwrap();
 
-    let body = client.get(&sitemap_url).send().await?.text().await?;
+    let Some(body) = document.select(&body_selector).next() else {
+        return (0, 0);
+    };
 
-    let mut reader = Reader::from_str(&body);
-    reader.config_mut().trim_text(true);
+    let mut text_length = 0;
+    for node in body.descendants() {
+        let Some(text) = node.value().as_text() else {
+            continue;
+        };
 
-    let mut buf = Vec::new();
-    let mut urls = Vec::new();
-    let mut inside_loc = false;
+        let hidden = node.ancestors().any(|a| {
+            a.value()
+                .as_element()
+                .is_some_and(|e| matches!(e.name(), "script" | "style" | "noscript" | "template"))
+        });
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"loc" => {
-                inside_loc = true;
-            }
-            Ok(Event::Text(e)) if inside_loc => {
-                let text = String::from_utf8_lossy(e.as_ref()).to_string();
-                urls.push(text);
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"loc" => {
-                inside_loc = false;
-            }
-            Ok(Event::Eof) => break,
-            Err(_) => break,
-            _ => {}
+        if !hidden {
+            text_length += text.chars().filter(|c| !c.is_whitespace()).count() as i64;
         }
-        buf.clear();
     }
 
-    if urls.is_empty() {
-        anyhow::bail!("No URLs in sitemap");
-    }
+    let image_count = body
+        .select(&img_selector)
+        .filter(|img| {
+            let value = img.value();
+            let src = value.attr("src").or_else(|| value.attr("data-src"));
+            src.is_some_and(|s| !s.is_empty() && !s.starts_with("data:"))
+        })
+        .count() as i64;
 
-    Ok(urls)
+    (text_length, image_count)
 }
 
-pub async fn crawl_html(conn: &Connection, base_url: &str, max_new: usize) -> Result<()> {
-    let client = Client::new();
+// The blog's own labels: meta keywords plus rel=tag / category links
+// (WordPress rel="tag", Hatena .entry-categories). Case-insensitively deduped.
+fn site_tags(document: &Html) -> Vec<String> {
+    let keywords_selector = Selector::parse("meta[name=keywords]").unwrap();
+    let link_selector =
+        Selector::parse(r#"a[rel~="tag"], .entry-categories a, .entry-tags a, .cat-links a"#)
+            .unwrap();
 
-    let now = Utc::now().to_rfc3339();
+    let keywords = document
+        .select(&keywords_selector)
+        .filter_map(|m| m.value().attr("content"))
+        .flat_map(|content| content.split([',', '、', '，']))
+        .map(|s| s.to_string());
 
-    // Insert root if not exists
-    db::enqueue(conn, base_url, None)?;
+    let links = document
+        .select(&link_selector)
+        .map(|a| a.text().collect::<String>());
 
-    let mut new_count = 0;
+    let mut tags: Vec<String> = Vec::new();
 
-    loop {
-        // Stop if limit reached
-        if new_count >= max_new {
-            break;
+    for raw in keywords.chain(links) {
+        let tag = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+            continue;
         }
 
-        let targets = db::next_pending(conn, 10)?;
-        if targets.is_empty() {
+        if tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+            continue;
+        }
+
+        tags.push(tag);
+        if tags.len() >= MAX_SITE_TAGS {
             break;
         }
+    }
 
-        for url in targets {
-            if new_count >= max_new {
-                break;
-            }
+    tags
+}
 
-            match crawl_page(conn, &client, &url).await {
-                Ok(added) => {
-                    let inserted = crawl_article(conn, &client, &url, &now, false)
-                        .await
-                        .unwrap_or_else(|e| {
-                            eprintln!("Blog warn: {}", e);
-                            false
-                        });
+// (delay seconds, absolute target) of <meta http-equiv="refresh" content="0;url=...">.
+// A refresh without a URL or pointing at the page itself is not a redirect.
+fn meta_refresh(document: &Html, page_url: &str) -> Option<(u64, String)> {
+    let selector = Selector::parse("meta[http-equiv][content]").unwrap();
 
-                    if inserted {
-                        new_count += added
-                    }
+    let content = document
+        .select(&selector)
+        .find(|m| {
+            m.value()
+                .attr("http-equiv")
+                .is_some_and(|v| v.eq_ignore_ascii_case("refresh"))
+        })?
+        .value()
+        .attr("content")?;
 
-                    db::mark_done(conn, &url)?;
-                }
-                Err(e) => {
-                    eprintln!("Crawl html warn: {}, {}", e, url);
-                }
-            }
-        }
+    let (delay, rest) = content.split_once([';', ','])?;
+    let delay = delay.trim().parse::<f64>().ok()?.max(0.0) as u64;
+
+    let rest = rest.trim();
+    let target = match rest.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &rest[4..],
+        _ => rest,
+    };
+    let target = target.trim().trim_matches(['\'', '"']);
+    if target.is_empty() {
+        return None;
     }
 
-    Ok(())
+    let target = normalize_url(page_url, target);
+    (target != page_url).then_some((delay, target))
 }
 
-async fn crawl_page(conn: &Connection, client: &Client, url: &str) -> Result<usize> {
-    let response = client.get(url).send().await?;
+// Absolute href of <link rel="canonical">
+fn canonical_link(document: &Html, page_url: &str) -> Option<String> {
+    let selector = Selector::parse(r#"link[rel~="canonical"][href]"#).unwrap();
 
-    if !response.status().is_success() {
-        anyhow::bail!("Status error {}", response.status());
+    let href = document
+        .select(&selector)
+        .next()?
+        .value()
+        .attr("href")?
+        .trim();
+    if href.is_empty() {
+        return None;
     }
 
-    // HTML only
-    if let Some(ct) = response.headers().get(reqwest::header::CONTENT_TYPE) {
-        if !ct.to_str()?.contains("text/html") {
-            return Ok(0);
-        }
-    } else if !is_article_link(&url) {
-        return Ok(0);
-    }
+    Some(normalize_url(page_url, href))
+}
 
-    let body = response.text().await?;
-    let document = Html::parse_document(&body);
-    let selector = Selector::parse("a").unwrap();
+// The page's publication date: article:published_time / pubdate meta, a <time> in the
+// article body, then a Japanese "2024年3月5日" date in its text. Used for
+// published_at and the age limit.
+fn published_time(document: &Html) -> Option<DateTime<Utc>> {
+    let meta = Selector::parse(
+        r#"meta[property="article:published_time"], meta[name="pubdate"], meta[itemprop="datePublished"]"#,
+    )
+    .unwrap();
+    let time = Selector::parse(
+        "article time[datetime], main time[datetime], time[pubdate][datetime], time[itemprop=datePublished][datetime]",
+    )
+    .unwrap();
 
-    let mut added = 0;
+    let tagged = document
+        .select(&meta)
+        .filter_map(|m| m.value().attr("content"))
+        .chain(
+            document
+                .select(&time)
+                .filter_map(|t| t.value().attr("datetime")),
+        )
+        .find_map(|value| parse_page_date(value.trim()));
 
-    for element in document.select(&selector) {
-        if let Some(href) = element.value().attr("href") {
-            let next_url = normalize_url(url, href);
+    if tagged.is_some() {
+        return tagged;
+    }
 
-            if !same_domain(url, &next_url) {
-                continue;
-            }
+    // Last resort: the first written-out date of the article (or the whole body)
+    let body = Selector::parse("article, main, body").unwrap();
+    let text: String = document.select(&body).next()?.text().collect();
+    let re = Regex::new(r"(\d{4})\s*年\s*(\d{1,2})\s*月\s*(\d{1,2})\s*日").ok()?;
+    let caps = re.captures(&text)?;
 
-            if db::enqueue(conn, &next_url, Some(url))? {
-                added += 1;
-            }
-        }
+    NaiveDate::from_ymd_opt(
+        caps[1].parse().ok()?,
+        caps[2].parse().ok()?,
+        caps[3].parse().ok()?,
+    )
+    .and_then(|d| d.and_hms_opt(0, 0, 0))
+    .and_then(|naive| naive.and_local_timezone(jst()).single())
+    .map(|d| d.with_timezone(&Utc))
+}
+
+// Timestamps as found in pages; ones without an offset are read as JST
+fn parse_page_date(text: &str) -> Option<DateTime<Utc>> {
+    if let Some(date) = parse_loose_date(text) {
+        return Some(date);
     }
 
-    Ok(added)
+    [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y/%m/%d",
+    ]
+    .iter()
+    .find_map(|format| {
+        chrono::NaiveDateTime::parse_from_str(text, format)
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(text, format)
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+            })
+    })
+    .and_then(|naive| naive.and_local_timezone(jst()).single())
+    .map(|d| d.with_timezone(&Utc))
 }
 
-async fn crawl_article(
-    conn: &Connection,
-    client: &Client,
-    url: &str,
-    fetched_at: &str,
-    ignore_skip: bool,
-) -> Result<bool> {
-    if db::should_skip(conn, url)? && !ignore_skip {
-        println!("Skipping {} due to recent error", url);
-        return Ok(false);
-    }
+fn jst() -> FixedOffset {
+    FixedOffset::east_opt(9 * 3600).unwrap()
+}
 
-    let fetch_result = fetch_html(client, url).await;
+// Card image: og:image, then twitter:image, then the first image in the article body.
+// Empty values, data: URIs and 1x1 tracking pixels (by name, or an <img> sized 1 by
+// 1) are passed over.
+fn thumbnail(document: &Html, page_url: &str) -> Option<String> {
+    let meta = Selector::parse(
+        r#"meta[property="og:image"], meta[name="twitter:image"], meta[property="twitter:image"]"#,
+    )
+    .unwrap();
+    let image = Selector::parse("article img[src], main img[src]").unwrap();
 
-    if let Err(ref e) = fetch_result {
-        if let Some(crawl_err) = e.downcast_ref::<CrawlError>() {
-            match crawl_err {
-                CrawlError::HttpStatus { status, url } => {
-                    println!("Status error: {} {}", status, url);
+    let metas = ["og:image", "twitter:image"].into_iter().flat_map(|name| {
+        document
+            .select(&meta)
+            .filter(move |m| m.value().attr("property").or(m.value().attr("name")) == Some(name))
+            .filter_map(|m| m.value().attr("content"))
+    });
+    let images = document
+        .select(&image)
+        .filter(|img| {
+            let size = |name| img.value().attr(name).map(str::trim);
+            !(size("width") == Some("1") && size("height") == Some("1"))
+        })
+        .filter_map(|img| img.value().attr("src"));
 
-                    if *status == StatusCode::NOT_FOUND {
-                        db::register_error(conn, url, "404", 7)?;
-                    }
-                }
-            }
+    metas
+        .chain(images)
+        .map(str::trim)
+        .find(|src| {
+            !src.is_empty() && !src.starts_with("data:") && !src.to_lowercase().contains("1x1")
+        })
+        .map(|src| normalize_url(page_url, src))
+}
+
+// <meta name="robots|googlebot" content="..., unavailable_after: <date>">
+fn unavailable_after(document: &Html) -> Option<DateTime<Utc>> {
+    let selector = Selector::parse("meta[name][content]").unwrap();
+
+    document
+        .select(&selector)
+        .filter(|m| {
+            m.value().attr("name").is_some_and(|name| {
+                name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case("googlebot")
+            })
+        })
+        .filter_map(|m| m.value().attr("content"))
+        .find_map(|content| {
+            let lower = content.to_ascii_lowercase();
+            let start = lower.find("unavailable_after:")? + "unavailable_after:".len();
+            parse_directive_date(&content[start..])
+        })
+}
+
+// Dates in the formats unavailable_after is written in (RFC 850, RFC 822, ISO 8601).
+// Directives may follow after a comma, and RFC 850 dates have one of their own, so
+// shorter comma-separated prefixes are tried too.
+fn parse_directive_date(text: &str) -> Option<DateTime<Utc>> {
+    let mut candidate = text.trim();
+
+    loop {
+        if let Some(date) = parse_loose_date(candidate) {
+            return Some(date);
         }
+
+        let (head, _) = candidate.rsplit_once(',')?;
+        candidate = head.trim();
+    }
+}
+
+fn parse_loose_date(text: &str) -> Option<DateTime<Utc>> {
+    if let Some(date) = parse_lastmod(text) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(text) {
+        return Some(date.with_timezone(&Utc));
     }
 
-    let body = fetch_result?;
+    // "Friday, 25-Jun-10 15:00:00 GMT" or "25-Jun-2010 15:00:00 PST": zone names by hand
+    let (rest, zone) = text.rsplit_once(' ')?;
+    let offset_hours = match zone.to_ascii_uppercase().as_str() {
+        "GMT" | "UTC" | "Z" => 0,
+        "JST" => 9,
+        "EST" => -5,
+        "EDT" => -4,
+        "CST" => -6,
+        "CDT" => -5,
+        "MST" => -7,
+        "MDT" => -6,
+        "PST" => -8,
+        "PDT" => -7,
+        _ => return None,
+    };
+    let rest = rest.split_once(", ").map_or(rest, |(_, date)| date);
 
-    let document = Html::parse_document(&body);
+    [
+        "%d-%b-%y %H:%M:%S",
+        "%d-%b-%Y %H:%M:%S",
+        "%d %b %Y %H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| chrono::NaiveDateTime::parse_from_str(rest, format).ok())
+    .map(|naive| naive.and_utc() - chrono::Duration::hours(offset_hours))
+}
 
-    let title_selector = Selector::parse("title").unwrap();
-    let meta_selector = Selector::parse("meta[name=description]").unwrap();
+// First non-empty content attribute of the selectors, tried in order
+fn meta_content(document: &Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|m| m.value().attr("content"))
+            .map(str::trim)
+            .find(|s| !s.is_empty())
+            .map(str::to_string)
+    })
+}
 
-    let title = document
-        .select(&title_selector)
-        .next()
-        .map(|t| t.text().collect::<String>())
-        .unwrap_or_else(|| "No Title".to_string());
+// Separators between a page title and a trailing site name
+const TITLE_SEPARATORS: &[&str] = &["｜", "|", " – ", " — ", " - ", " : "];
+
+// The title without its last "| Site" part, when `is_site_name` accepts that part.
+// A separator that belongs to the title itself is left alone.
+fn strip_site_suffix(title: &str, is_site_name: impl Fn(&str) -> bool) -> Option<String> {
+    let (index, separator) = TITLE_SEPARATORS
+        .iter()
+        .filter_map(|sep| title.rfind(sep).map(|index| (index, sep)))
+        .max_by_key(|(index, _)| *index)?;
+
+    let prefix = title[..index].trim();
+    let suffix = title[index + separator.len()..].trim();
+    (!prefix.is_empty() && !suffix.is_empty() && is_site_name(suffix)).then(|| prefix.to_string())
+}
+
+// Site names compare case- and whitespace-insensitively
+fn same_name(a: &str, b: &str) -> bool {
+    let fold = |s: &str| s.split_whitespace().collect::<String>().to_lowercase();
+    fold(a) == fold(b)
+}
 
-    let description = document
-        .select(&meta_selector)
+// og:site_name, else the last part of a "Post | Site" style title
+fn site_name(document: &Html, title: &str) -> Option<String> {
+    let selector = Selector::parse(r#"meta[property="og:site_name"]"#).unwrap();
+
+    let og = document
+        .select(&selector)
         .next()
         .and_then(|m| m.value().attr("content"))
-        .map(|s| s.to_string());
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
 
-    let result = db::insert(
-        conn,
-        url, // URL as unique ID
-        "blog",
-        &title,
-        url,
-        description.as_deref(),
-        None,
-        None,
-        fetched_at,
-    );
+    og.or_else(|| {
+        [" | ", " - ", " – ", " — ", "｜", " : "]
+            .iter()
+            .find_map(|sep| title.rsplit_once(sep))
+            .map(|(_, suffix)| suffix.trim().to_string())
+            .filter(|s| !s.is_empty())
+    })
+}
+
+// (primary language subtag, absolute URL) of link[rel=alternate][hreflang]; x-default is ignored
+fn hreflang_alternates(document: &Html, page_url: &str) -> Vec<(String, String)> {
+    let selector = Selector::parse(r#"link[rel~="alternate"][hreflang][href]"#).unwrap();
+
+    document
+        .select(&selector)
+        .filter_map(|link| {
+            let lang = link.value().attr("hreflang")?;
+            let href = link.value().attr("href")?;
+            if lang.eq_ignore_ascii_case("x-default") {
+                return None;
+            }
+            Some((primary_language(lang), normalize_url(page_url, href)))
+        })
+        .collect()
+}
+
+fn page_language(document: &Html) -> Option<String> {
+    let selector = Selector::parse("html[lang]").unwrap();
+
+    document
+        .select(&selector)
+        .next()
+        .and_then(|html| html.value().attr("lang"))
+        .map(primary_language)
+}
 
-    if let Ok(bool) = result {
-        if bool {
-            println!("Crawl and insert article: {}", url);
+// "ja-JP" -> "ja"
+fn primary_language(tag: &str) -> String {
+    tag.split(['-', '_'])
+        .next()
+        .unwrap_or(tag)
+        .trim()
+        .to_lowercase()
+}
+
+// YouTube video ids of iframe embeds (youtube.com/embed/ID and the nocookie variant)
+fn embedded_video_ids(document: &Html) -> Vec<String> {
+    let selector = Selector::parse("iframe[src]").unwrap();
+    let mut ids = Vec::new();
+
+    for iframe in document.select(&selector) {
+        let Some(src) = iframe.value().attr("src") else {
+            continue;
+        };
+
+        // Embeds are often protocol-relative
+        let Ok(src_url) = Url::parse("https://www.youtube.com/").and_then(|b| b.join(src)) else {
+            continue;
+        };
+
+        let is_youtube = src_url.domain().is_some_and(|d| {
+            d == "youtube.com"
+                || d.ends_with(".youtube.com")
+                || d == "youtube-nocookie.com"
+                || d.ends_with(".youtube-nocookie.com")
+        });
+
+        if !is_youtube {
+            continue;
+        }
+
+        let mut segments = src_url.path_segments().into_iter().flatten();
+        if segments.next() == Some("embed")
+            && let Some(id) = segments.next()
+            && !id.is_empty()
+            && !ids.iter().any(|known| known == id)
+        {
+            ids.push(id.to_string());
         }
     }
 
-    result
+    ids
 }
 
 fn is_article_link(href: &str) -> bool {
@@ -264,11 +3591,43 @@ fn same_domain(base: &str, target: &str) -> bool {
     let target_url = Url::parse(target).ok();
 
     match (base_url, target_url) {
-        (Some(b), Some(t)) => b.domain() == t.domain(),
+        (Some(b), Some(t)) => match (b.host_str(), t.host_str()) {
+            (Some(b), Some(t)) => same_host(b, t),
+            _ => false,
+        },
         _ => false,
     }
 }
 
+// Hosts equal up to a leading "www."; IDNs compare in their ASCII form, as Url
+// gives them. Registrable domains are not compared: shared hosting subdomains
+// (*.g3.xrea.com, *.blog.fc2.com) are different sites.
+fn same_host(a: &str, b: &str) -> bool {
+    let strip = |host: &str| host.strip_prefix("www.").unwrap_or(host).to_string();
+    strip(a) == strip(b)
+}
+
+// Rewrite http links to https when the base is served over https on the same host
+fn upgrade_scheme(base: &str, target: &str) -> String {
+    let (Ok(base_url), Ok(mut target_url)) = (Url::parse(base), Url::parse(target)) else {
+        return target.to_string();
+    };
+
+    if base_url.scheme() == "https"
+        && target_url.scheme() == "http"
+        && base_url.host_str() == target_url.host_str()
+        && target_url.set_scheme("https").is_ok()
+    {
+        // Drop the explicit default http port, if any, along with the scheme
+        if target_url.port() == Some(80) {
+            let _ = target_url.set_port(None);
+        }
+        return target_url.to_string();
+    }
+
+    target.to_string()
+}
+
 fn normalize_url(base: &str, href: &str) -> String {
     // Parse base URL
     let base_url = match Url::parse(base) {
@@ -278,19 +3637,178 @@ fn normalize_url(base: &str, href: &str) -> String {
 
     // Resolve relative URL correctly
     match base_url.join(href) {
-        Ok(joined) => joined.to_string(),
+        Ok(joined) => db::canonical_url(joined.as_str()),
         Err(_) => href.to_string(),
     }
 }
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use chardetng::EncodingDetector;
 use encoding_rs::Encoding;
+use flate2::read::GzDecoder;
 use regex::Regex;
-use reqwest::header::CONTENT_TYPE;
+use reqwest::header::{
+    AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, COOKIE, ETAG, HeaderMap, HeaderValue,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+};
+use std::io::Read;
+
+// error_sites key for failures that affect a whole host
+fn domain_key(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| format!("domain:{}", host))
+}
+
+// Certificate/handshake failures, as opposed to plain connection errors: a
+// native_tls::Error (reqwest's TLS backend) in the error chain. An io::Error's
+// source() skips the error it wraps, so wrapped ones are looked into too.
+fn tls_failure(error: &reqwest::Error) -> Option<String> {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+
+    while let Some(e) = source {
+        let tls = e.downcast_ref::<native_tls::Error>().or_else(|| {
+            e.downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+                .and_then(|inner| inner.downcast_ref::<native_tls::Error>())
+        });
+        if let Some(tls) = tls {
+            return Some(tls.to_string());
+        }
+        source = e.source();
+    }
+
+    None
+}
+
+fn is_rate_limited(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<CrawlError>(),
+        Some(CrawlError::RateLimited { .. })
+    )
+}
+
+// GET with certificate failures reported as CrawlError::Tls
+async fn send(client: &Client, url: &str) -> Result<reqwest::Response> {
+    send_request(url, client.get(url)).await
+}
+
+// Timeouts, refused or reset connections and 502/503/504 are retried with jittered
+// exponential backoff; the last attempt's outcome is returned. Other statuses are
+// left to the caller.
+async fn send_request(
+    url: &str,
+    mut request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let mut attempt = 1;
+
+    loop {
+        let next = request.try_clone();
+        let outcome = request.send().await;
+
+        let transient = match &outcome {
+            Ok(response) => matches!(
+                response.status(),
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Err(e) => tls_failure(e).is_none() && (e.is_timeout() || e.is_connect() || is_reset(e)),
+        };
+
+        match next {
+            Some(next) if transient && attempt < MAX_ATTEMPTS => {
+                let delay = retry_delay(attempt);
+                warn!(
+                    "Transient failure on {} (attempt {}), retrying in {} ms",
+                    url,
+                    attempt,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                request = next;
+                attempt += 1;
+            }
+            _ => {
+                return outcome.map_err(|e| match tls_failure(&e) {
+                    Some(detail) => CrawlError::Tls {
+                        url: url.to_string(),
+                        detail,
+                    }
+                    .into(),
+                    None => e.into(),
+                });
+            }
+        }
+    }
+}
+
+// RETRY_BASE_MS doubled per attempt, plus up to half of that again
+fn retry_delay(attempt: usize) -> std::time::Duration {
+    let base = RETRY_BASE_MS << (attempt - 1);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+
+    std::time::Duration::from_millis(base + nanos % (base / 2 + 1))
+}
+
+// The peer dropped the connection mid-request
+fn is_reset(error: &reqwest::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>()
+            && matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::UnexpectedEof
+            )
+        {
+            return true;
+        }
+        source = e.source();
+    }
+
+    false
+}
+
+pub async fn fetch_html(client: &Client, url: &str) -> Result<String> {
+    read_html(url, send(client, url).await?).await
+}
+
+// fetch_html plus the URL the client's redirects ended at
+async fn fetch_page(client: &Client, url: &str) -> Result<(String, String)> {
+    let response = send(client, url).await?;
+    let final_url = response.url().to_string();
+    Ok((final_url, read_html(url, response).await?))
+}
 
-async fn fetch_html(client: &Client, url: &str) -> Result<String> {
-    let response = client.get(url).send().await?;
+// ETag and Last-Modified of a response, for the next conditional GET. Weak ETags
+// are not kept: some servers answer 304 to them for pages that did change.
+fn response_validators(headers: &HeaderMap) -> Option<db::Validators> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let validators = db::Validators {
+        etag: header(ETAG).filter(|etag| !etag.starts_with("W/")),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    (validators.etag.is_some() || validators.last_modified.is_some()).then_some(validators)
+}
 
+// Status check and charset decoding of a page response
+async fn read_html(url: &str, response: reqwest::Response) -> Result<String> {
     if !response.status().is_success() {
         return Err(CrawlError::HttpStatus {
             status: response.status(),
@@ -302,39 +3820,293 @@ async fn fetch_html(client: &Client, url: &str) -> Result<String> {
     let headers = response.headers().clone();
     let bytes = response.bytes().await?;
 
+    decode_body(&headers, &bytes)
+}
+
+// Shared by HTML pages and XML documents (sitemaps)
+fn decode_body(headers: &HeaderMap, bytes: &[u8]) -> Result<String> {
     // 1. Try charset from header
-    if let Some(content_type) = headers.get(CONTENT_TYPE) {
-        if let Ok(content_type_str) = content_type.to_str() {
-            if let Some(charset) = content_type_str.split("charset=").nth(1) {
-                if let Some(encoding) = Encoding::for_label(charset.trim().as_bytes()) {
-                    let (text, _, _) = encoding.decode(&bytes);
-                    return Ok(text.into_owned());
-                }
-            }
-        }
+    if let Some(content_type) = headers.get(CONTENT_TYPE)
+        && let Ok(content_type_str) = content_type.to_str()
+        && let Some(charset) = content_type_str.split("charset=").nth(1)
+        && let Some(encoding) = Encoding::for_label(charset.trim().as_bytes())
+    {
+        let (text, _, _) = encoding.decode(bytes);
+        return Ok(text.into_owned());
     }
 
-    // 2. Try to detect charset from meta tag (ASCII-safe)
     let ascii_head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
 
+    // 2. XML prolog: <?xml version="1.0" encoding="EUC-JP"?>
+    let prolog = Regex::new(r#"^\s*<\?xml[^>]*\sencoding\s*=\s*["']([A-Za-z0-9_\-]+)["']"#)?;
+
+    if let Some(cap) = prolog.captures(ascii_head.trim_start_matches('\u{feff}'))
+        && let Some(encoding) = Encoding::for_label(cap[1].as_bytes())
+    {
+        let (text, _, _) = encoding.decode(bytes);
+        return Ok(text.into_owned());
+    }
+
+    // 3. Try to detect charset from meta tag (ASCII-safe)
     let re = Regex::new(r#"charset\s*=\s*["']?([A-Za-z0-9_\-]+)"#)?;
 
     if let Some(cap) = re.captures(&ascii_head) {
         let charset = cap.get(1).unwrap().as_str();
 
         if let Some(encoding) = Encoding::for_label(charset.as_bytes()) {
-            let (text, _, _) = encoding.decode(&bytes);
+            let (text, _, _) = encoding.decode(bytes);
             return Ok(text.into_owned());
         }
     }
 
-    // 3. Fallback: Detect encoding automatically
+    // 4. Fallback: Detect encoding automatically
     let mut detector = EncodingDetector::new();
-    detector.feed(&bytes, true);
+    detector.feed(bytes, true);
 
     let encoding = detector.guess(None, true);
 
-    let (text, _, _) = encoding.decode(&bytes);
+    let (text, _, _) = encoding.decode(bytes);
 
     Ok(text.into_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "https://example.com/report/pr375.html";
+
+    fn card(html: &str) -> Option<String> {
+        thumbnail(&Html::parse_document(html), PAGE)
+    }
+
+    #[test]
+    fn thumbnail_prefers_og_then_twitter_then_the_first_image() {
+        let og = r#"<html><head>
+            <meta name="twitter:image" content="/twitter.jpg">
+            <meta property="og:image" content="https://cdn.example.com/og.jpg">
+            </head><body><article><img src="/body.jpg"></article></body></html>"#;
+        assert_eq!(card(og).as_deref(), Some("https://cdn.example.com/og.jpg"));
+
+        let twitter = r#"<html><head><meta property="twitter:image" content="/twitter.jpg">
+            </head><body><article><img src="/body.jpg"></article></body></html>"#;
+        assert_eq!(
+            card(twitter).as_deref(),
+            Some("https://example.com/twitter.jpg")
+        );
+
+        let body = r#"<html><body><img src="/logo.png">
+            <main><img src="../img/tunnel.jpg"><img src="/second.jpg"></main></body></html>"#;
+        assert_eq!(
+            card(body).as_deref(),
+            Some("https://example.com/img/tunnel.jpg")
+        );
+
+        assert_eq!(card("<html><body><p>no images</p></body></html>"), None);
+    }
+
+    #[test]
+    fn thumbnail_passes_over_placeholders() {
+        let html = r#"<html><head>
+            <meta property="og:image" content="  ">
+            <meta name="twitter:image" content="data:image/gif;base64,R0lGODlhAQABAAAAACw=">
+            </head><body><article>
+            <img src="/spacer_1x1.gif">
+            <img src="/pixel.gif" width="1" height="1">
+            <img src="data:image/png;base64,iVBORw0KGgo=">
+            <img src="/photo.jpg" width="1" height="300">
+            </article></body></html>"#;
+        assert_eq!(card(html).as_deref(), Some("https://example.com/photo.jpg"));
+
+        let only_pixels = r#"<html><head><meta property="og:image" content="/1X1.png">
+            </head><body><article><img src="/t.gif" width="1" height="1"></article></body></html>"#;
+        assert_eq!(card(only_pixels), None);
+    }
+
+    fn bounds(start: u64, min: u64, max: u64) -> DelayBounds {
+        let ms = std::time::Duration::from_millis;
+        DelayBounds {
+            start: ms(start),
+            min: ms(min),
+            max: ms(max),
+        }
+    }
+
+    // The delay in ms after each (latency ms, failed) response
+    fn trajectory(bounds: &DelayBounds, responses: &[(u64, bool)]) -> Vec<u128> {
+        let mut pace = HostPace::new(bounds);
+        responses
+            .iter()
+            .map(|&(latency, failed)| {
+                pace.observe(bounds, std::time::Duration::from_millis(latency), failed);
+                pace.delay.as_millis()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fast_clean_host_tightens_to_the_floor() {
+        let bounds = bounds(1000, 300, 8000);
+        assert_eq!(
+            trajectory(&bounds, &[(100, false); 7]),
+            [800, 640, 512, 409, 327, 300, 300]
+        );
+    }
+
+    #[test]
+    fn slow_host_widens_to_the_ceiling() {
+        let bounds = bounds(1000, 300, 8000);
+        assert_eq!(
+            trajectory(&bounds, &[(4000, false); 6]),
+            [1500, 2250, 3375, 5062, 7593, 8000]
+        );
+
+        // A middling latency holds the delay where it is
+        assert_eq!(trajectory(&bounds, &[(1000, false); 3]), [1000, 1000, 1000]);
+    }
+
+    #[test]
+    fn errors_widen_and_only_a_clean_run_tightens_again() {
+        let bounds = bounds(1000, 300, 8000);
+        let mut responses = vec![(100, true); 3];
+        responses.extend([(100, false); 9]);
+
+        let delays = trajectory(&bounds, &responses);
+        assert_eq!(delays[..3], [2000, 4000, 8000]);
+        // The rolling error rate takes nine clean responses to fall under 5%
+        assert_eq!(delays[3..11], [8000; 8]);
+        assert_eq!(delays[11], 6400);
+    }
+
+    #[test]
+    fn zero_delay_can_back_off() {
+        let bounds = bounds(0, 0, 8000);
+        assert_eq!(
+            trajectory(&bounds, &[(100, true), (100, false)]),
+            [1000, 1000]
+        );
+    }
+
+    #[test]
+    fn delay_bounds_follow_config_and_crawl_delay() {
+        let mut config = Config {
+            request_delay_ms: 1000,
+            max_request_delay_ms: 5000,
+            ..Default::default()
+        };
+        let blog = BlogConfig::for_url(PAGE).unwrap();
+
+        let plain = DelayBounds::new(&config, &blog, None);
+        assert_eq!(plain.start.as_millis(), 1000);
+        assert_eq!(plain.min.as_millis(), 1000);
+        assert_eq!(plain.max.as_millis(), 5000);
+
+        config.min_request_delay_ms = Some(200);
+        let crawl_delay = DelayBounds::new(&config, &blog, Some(std::time::Duration::from_secs(2)));
+        assert_eq!(crawl_delay.start.as_millis(), 2000);
+        assert_eq!(crawl_delay.min.as_millis(), 2000);
+    }
+
+    #[test]
+    fn throttle_reports_per_host_delays() {
+        let throttle = Throttle::with_bounds(bounds(1000, 300, 8000));
+        throttle.observe(
+            "https://slow.example.com/a",
+            std::time::Duration::from_secs(4),
+            false,
+        );
+        throttle.observe(
+            "https://fast.example.com/a",
+            std::time::Duration::from_millis(50),
+            false,
+        );
+        throttle.observe(
+            "https://fast.example.com/b",
+            std::time::Duration::from_millis(50),
+            false,
+        );
+
+        let delays = throttle.delays_ms();
+        assert_eq!(delays["slow.example.com"], 1500);
+        assert_eq!(delays["fast.example.com"], 640);
+
+        // A fixed throttle ignores what it observes
+        let fixed = Throttle::with_delay(std::time::Duration::from_millis(2000));
+        fixed.observe(
+            "https://slow.example.com/a",
+            std::time::Duration::from_secs(4),
+            true,
+        );
+        assert_eq!(fixed.delays_ms()["slow.example.com"], 2000);
+    }
+
+    #[tokio::test]
+    async fn https_failures_tell_a_missing_port_from_a_broken_one() {
+        // A port just released: nothing listens there
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let refused = Client::new()
+            .get(format!("https://127.0.0.1:{}/", port))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(https_failure_status(&refused.into()), "http_only");
+
+        let tls = CrawlError::Tls {
+            url: "https://a.example/".to_string(),
+            detail: "certificate has expired".to_string(),
+        };
+        assert_eq!(https_failure_status(&tls.into()), "https_broken");
+        let status = CrawlError::HttpStatus {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            url: "https://a.example/".to_string(),
+        };
+        assert_eq!(https_failure_status(&status.into()), "https_broken");
+    }
+
+    #[test]
+    fn host_matching_strips_www_only() {
+        for (base, target, same) in [
+            (
+                "https://example.com/",
+                "https://www.example.com/post/1.html",
+                true,
+            ),
+            (
+                "https://www.example.com/",
+                "https://example.com/post/1.html",
+                true,
+            ),
+            ("https://Example.COM/", "http://www.example.com/", true),
+            ("https://example.com/", "https://blog.example.com/", false),
+            // co.jp: registrable domains are not compared, and a shared suffix is no match
+            (
+                "https://a.example.co.jp/",
+                "https://b.example.co.jp/",
+                false,
+            ),
+            ("https://a.example.co.jp/", "https://example.co.jp/", false),
+            ("https://example.co.jp/", "https://www.example.co.jp/", true),
+            ("https://example.co.jp/", "https://example2.co.jp/", false),
+            ("https://example.co.jp/", "https://notexample.co.jp/", false),
+            // IDNs compare in their punycode form, however they were written
+            (
+                "https://道路.jp/",
+                "https://xn--rt3a10c.jp/post/1.html",
+                true,
+            ),
+            ("https://www.道路.jp/", "https://道路.jp/", true),
+            ("https://道路.jp/", "https://隧道.jp/", false),
+            ("https://example.com/", "not a url", false),
+        ] {
+            assert_eq!(same_domain(base, target), same, "{} vs {}", base, target);
+        }
+
+        assert!(same_host("www.example.com", "example.com"));
+        assert!(!same_host("www2.example.com", "example.com"));
+        assert!(!same_host("wwwexample.com", "example.com"));
+    }
+}
@@ -0,0 +1,400 @@
+use aho_corasick::AhoCorasick;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+use url::Url;
+
+use crate::config::{BlogConfig, BoilerplateConfig, ScoringConfig};
+use crate::db;
+
+// URL path patterns of pages that are never articles
+const DEFAULT_BOILERPLATE_PATHS: &[&str] = &[
+    r"(?i)/(wp-)?login(\.php)?/?$",
+    r"(?i)/privacy(-policy)?/?$",
+    r"(?i)/about/?$",
+    r"(?i)/profile/?$",
+    r"(?i)/sample-page/?$",
+];
+
+// Title substrings of pages that are never articles
+const DEFAULT_BOILERPLATE_TITLES: &[&str] = &[
+    "プライバシーポリシー",
+    "利用規約",
+    "プロフィール",
+    "ログイン",
+];
+
+// URL paths of index pages that only list articles: the top page, pagination,
+// category/tag/date archives and search
+static LISTING_PATHS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"^/?$",
+        r"(?i)/page/\d+/?$",
+        r"(?i)^/(category|categories|tag|tags|archive|archives|search)(/|$)",
+        r"^/\d{4}(/\d{1,2}){0,2}/?$",
+    ]
+    .iter()
+    .map(|p| Regex::new(p).unwrap())
+    .collect()
+});
+
+// Whether a URL is covered by a takedown entry (exact, or a prefix ending in "*").
+// Entries are compared in canonical_url form, the form URLs are stored in.
+pub fn is_taken_down(takedowns: &[String], url: &str) -> bool {
+    let url = db::canonical_url(url);
+
+    takedowns.iter().any(|entry| match entry.strip_suffix('*') {
+        // canonical_url drops the trailing slash, which a prefix needs so that
+        // ".../blog/*" doesn't cover ".../blogger"; the directory itself is covered
+        Some(prefix) if prefix.ends_with('/') => {
+            let directory = db::canonical_url(prefix);
+            let base = directory.trim_end_matches('/');
+            url == directory
+                || url
+                    .strip_prefix(base)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        }
+        Some(prefix) => url.starts_with(&db::canonical_url(prefix)),
+        None => url == db::canonical_url(entry),
+    })
+}
+
+// Road-number tags: 国道152号, 長野県道77号線, 東京都道, 北海道道, R152
+static ROAD_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:(?:\S{0,4}[都道府県]道|国道|[市町村]道)\s*[0-9０-９]+\s*号線?|[RrＲ]\s*[0-9０-９]+)$",
+    )
+    .unwrap()
+});
+
+// Whether a tag names one road rather than a genre (廃道, 林道, 酷道)
+pub fn is_road_tag(tag: &str) -> bool {
+    ROAD_TAG.is_match(tag.trim())
+}
+
+// Whether a URL is certainly a listing page. Anything unsure is treated as a
+// possible article and parsed in full.
+pub fn is_listing_url(url: &str) -> bool {
+    let Ok(parsed) = Url::parse(url) else {
+        return false;
+    };
+
+    let paged = parsed
+        .query_pairs()
+        .any(|(key, _)| key == "page" || key == "paged");
+
+    paged || LISTING_PATHS.iter().any(|p| p.is_match(parsed.path()))
+}
+
+// Recognizes login/privacy/profile style pages that should not become contents.
+// Such pages are still crawled for links.
+pub struct Boilerplate {
+    paths: Vec<Regex>,
+    titles: Vec<String>,
+}
+
+impl Boilerplate {
+    // Defaults plus the global extensions, unless the blog overrides a list entirely
+    pub fn new(global: &BoilerplateConfig, blog: &BlogConfig) -> Result<Self> {
+        let paths: Vec<String> = match &blog.boilerplate_paths {
+            Some(paths) => paths.clone(),
+            None => DEFAULT_BOILERPLATE_PATHS
+                .iter()
+                .map(|p| p.to_string())
+                .chain(global.extra_paths.iter().cloned())
+                .collect(),
+        };
+
+        let titles = match &blog.boilerplate_titles {
+            Some(titles) => titles.clone(),
+            None => DEFAULT_BOILERPLATE_TITLES
+                .iter()
+                .map(|t| t.to_string())
+                .chain(global.extra_titles.iter().cloned())
+                .collect(),
+        };
+
+        let paths = paths
+            .iter()
+            .map(|p| {
+                Regex::new(p).with_context(|| format!("Invalid boilerplate path pattern: {}", p))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Boilerplate { paths, titles })
+    }
+
+    pub fn matches_url(&self, url: &str) -> bool {
+        let Ok(parsed) = Url::parse(url) else {
+            return false;
+        };
+
+        self.paths.iter().any(|re| re.is_match(parsed.path()))
+    }
+
+    pub fn matches_title(&self, title: &str) -> bool {
+        self.titles.iter().any(|t| title.contains(t.as_str()))
+    }
+}
+
+// A blog's include_patterns / exclude_patterns, matched against the whole URL. With
+// neither set every URL is allowed.
+#[derive(Default)]
+pub struct UrlFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UrlFilter {
+    pub fn new(blog: &BlogConfig) -> Result<Self> {
+        let compile = |field: &str, patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| {
+                    Regex::new(p)
+                        .with_context(|| format!("{}: invalid {} entry {:?}", blog.name, field, p))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(UrlFilter {
+            include: compile("include_patterns", &blog.include_patterns)?,
+            exclude: compile("exclude_patterns", &blog.exclude_patterns)?,
+        })
+    }
+
+    pub fn allows(&self, url: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(url)))
+            && !self.exclude.iter().any(|re| re.is_match(url))
+    }
+}
+
+// Half-width katakana and punctuation U+FF61..=U+FF9F as full-width, in code point order
+const HALF_WIDTH_KANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+
+// Kana that take the voiced mark, and the voiced forms
+const UNVOICED: &str = "ウカキクケコサシスセソタチツテトハヒフヘホ";
+const VOICED: &str = "ヴガギグゲゴザジズゼゾダヂヅデドバビブベボ";
+
+// Full-width ASCII and the ideographic space to ASCII, half-width katakana to
+// full-width, with a following half-width (semi-)voiced mark joined to its kana
+pub fn fold_width(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        let c = match c {
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            '\u{ff61}'..='\u{ff9f}' => HALF_WIDTH_KANA
+                .chars()
+                .nth((c as u32 - 0xff61) as usize)
+                .unwrap_or(c),
+            _ => c,
+        };
+        let marked = |plain: &str, marked: &str| {
+            let kana = folded.chars().last()?;
+            marked.chars().nth(plain.chars().position(|p| p == kana)?)
+        };
+        let joined = match c {
+            '゛' => marked(UNVOICED, VOICED),
+            '゜' => marked("ハヒフヘホ", "パピプペポ"),
+            _ => None,
+        };
+        if let Some(kana) = joined {
+            folded.pop();
+            folded.push(kana);
+        } else {
+            folded.push(c);
+        }
+    }
+    folded
+}
+
+// Compiled ScoringConfig::term_weights
+pub struct TermMatcher {
+    plain: AhoCorasick,
+    plain_weights: Vec<i32>,
+    patterns: Vec<(Regex, i32)>,
+    cap: i32,
+}
+
+impl TermMatcher {
+    pub fn new(scoring: &ScoringConfig) -> Result<Self> {
+        let mut plain_terms = Vec::new();
+        let mut plain_weights = Vec::new();
+        let mut patterns = Vec::new();
+
+        for (term, weight) in &scoring.term_weights {
+            match term.strip_prefix("re:") {
+                Some(pattern) => {
+                    let re = Regex::new(pattern)
+                        .with_context(|| format!("Invalid term pattern: {}", pattern))?;
+                    patterns.push((re, *weight));
+                }
+                None => {
+                    plain_terms.push(fold_width(term));
+                    plain_weights.push(*weight);
+                }
+            }
+        }
+
+        Ok(TermMatcher {
+            plain: AhoCorasick::builder()
+                .ascii_case_insensitive(true)
+                .build(&plain_terms)?,
+            plain_weights,
+            patterns,
+            cap: scoring.term_weight_cap,
+        })
+    }
+
+    // Each distinct term counts once. Positive weights are taken largest first with
+    // each further hit worth half as much, and capped; negative weights always apply.
+    // Plain terms match regardless of ASCII case and character width; "re:" patterns
+    // see the text as it is.
+    pub fn score(&self, text: &str) -> i32 {
+        let mut hit = vec![false; self.plain_weights.len()];
+        for m in self.plain.find_overlapping_iter(&fold_width(text)) {
+            hit[m.pattern().as_usize()] = true;
+        }
+
+        let mut weights: Vec<i32> = hit
+            .iter()
+            .zip(&self.plain_weights)
+            .filter(|(hit, _)| **hit)
+            .map(|(_, weight)| *weight)
+            .chain(
+                self.patterns
+                    .iter()
+                    .filter(|(re, _)| re.is_match(text))
+                    .map(|(_, weight)| *weight),
+            )
+            .collect();
+        weights.sort_by(|a, b| b.cmp(a));
+
+        let positive: i32 = weights
+            .iter()
+            .filter(|w| **w > 0)
+            .enumerate()
+            .map(|(i, w)| w >> i.min(31))
+            .sum();
+        let negative: i32 = weights.iter().filter(|w| **w < 0).sum();
+
+        positive.min(self.cap) + negative
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn takedowns(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn exact_takedown_matches_the_stored_form() {
+        let list = takedowns(&["https://Example.com/post/123/"]);
+        assert!(is_taken_down(&list, "https://example.com/post/123"));
+        assert!(is_taken_down(
+            &list,
+            "https://example.com/post/123/#comments"
+        ));
+        assert!(!is_taken_down(&list, "https://example.com/post/1234"));
+    }
+
+    #[test]
+    fn prefix_takedown_covers_the_directory_only() {
+        let list = takedowns(&["https://example.com/blog/*"]);
+        assert!(is_taken_down(&list, "https://example.com/blog"));
+        assert!(is_taken_down(&list, "https://example.com/blog/2024/a.html"));
+        assert!(!is_taken_down(&list, "https://example.com/blogger/a.html"));
+
+        let list = takedowns(&["https://example.com/2024-*"]);
+        assert!(is_taken_down(&list, "https://example.com/2024-05-01.html"));
+        assert!(!is_taken_down(&list, "https://example.com/2023-05-01.html"));
+    }
+
+    #[test]
+    fn road_tags() {
+        for tag in [
+            "国道152号",
+            "長野県道77号線",
+            "北海道道 1号",
+            "国道１５２号",
+            "R152",
+        ] {
+            assert!(is_road_tag(tag), "{}", tag);
+        }
+        for tag in ["国道", "廃道", "酷道", "林道", "国道152号の旧道", "Road"] {
+            assert!(!is_road_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn term_weights_over_titles() {
+        // (term_weights, term_weight_cap, title, score)
+        type Case = (&'static [(&'static str, i32)], i32, &'static str, i32);
+        let cases: &[Case] = &[
+            (&[("旧道", 2)], 6, "国道152号の旧道を歩く", 2),
+            (&[("旧道", 2)], 6, "新道の開通式", 0),
+            // A term counts once however often it appears
+            (&[("峠", 1)], 6, "峠から峠へ", 1),
+            // Negative terms always apply, also past the cap
+            (&[("旧道", 2), ("閉店", -3)], 6, "旧道沿いの食堂が閉店", -1),
+            (&[("PR", -5)], 6, "PR 新商品のお知らせ", -5),
+            (&[("酷道", 8), ("PR", -2)], 6, "酷道ツアー PR", 4),
+            // Overlapping terms both hit, the second worth half
+            (&[("未成道", 3), ("成道", 2)], 6, "未成道を辿る", 4),
+            (&[("未成道", 3), ("成道", 2)], 6, "成道寺", 2),
+            // Diminishing returns, then the cap
+            (&[("酷道", 4), ("険道", 4), ("廃道", 4)], 6, "酷道と険道", 6),
+            (
+                &[("酷道", 4), ("険道", 4), ("廃道", 4)],
+                10,
+                "酷道と険道と廃道",
+                7,
+            ),
+            // ASCII case and character width
+            (&[("R152", 2)], 6, "r152 の旧道", 2),
+            (&[("R152", 2)], 6, "Ｒ１５２の旧道", 2),
+            (&[("ＪＲ", 1)], 6, "jr廃線跡", 1),
+            (&[("トンネル", 2)], 6, "ﾄﾝﾈﾙ探索", 2),
+            (&[("ガイド", 1)], 6, "ｶﾞｲﾄﾞ付き", 1),
+            (&[("ガイド", 1)], 6, "カイド", 0),
+            // Patterns match the text as it is
+            (&[("re:^国道\\d+号", 2)], 6, "国道152号の旧道", 2),
+            (&[("re:^国道\\d+号", 2)], 6, "旧国道152号", 0),
+        ];
+
+        for (terms, cap, title, expected) in cases {
+            let scoring = ScoringConfig {
+                term_weights: terms
+                    .iter()
+                    .map(|(term, weight)| (term.to_string(), *weight))
+                    .collect(),
+                term_weight_cap: *cap,
+                ..ScoringConfig::default()
+            };
+            let matcher = TermMatcher::new(&scoring).unwrap();
+            assert_eq!(
+                matcher.score(title),
+                *expected,
+                "{} with {:?}",
+                title,
+                terms
+            );
+        }
+    }
+
+    #[test]
+    fn listing_urls() {
+        assert!(is_listing_url("https://example.com/"));
+        assert!(is_listing_url("https://example.com/page/3/"));
+        assert!(is_listing_url("https://example.com/category/road"));
+        assert!(is_listing_url("https://example.com/2024/05"));
+        assert!(is_listing_url("https://example.com/entry?page=2"));
+        assert!(!is_listing_url(
+            "https://example.com/2024/05/kokudo-418.html"
+        ));
+    }
+}
@@ -1,27 +1,1040 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use tracing::warn;
+use url::Url;
+
+use crate::classify::UrlFilter;
+
+// Database of the subcommands that take no config (prune, top, stats, ...)
+pub const DEFAULT_DB_PATH: &str = "crawler.db";
+// Starts with the robots.txt product token (robots::AGENT)
+const DEFAULT_USER_AGENT: &str = concat!(
+    "michi_matome_crawler/",
+    env!("CARGO_PKG_VERSION"),
+    " (+",
+    env!("CARGO_PKG_REPOSITORY"),
+    ")"
+);
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub youtube: Vec<YouTubeConfig>,
     pub blogs: Vec<BlogConfig>,
+    // Collapse verbatim reposts across domains into one export item
+    #[serde(default)]
+    pub collapse_syndicated: bool,
+    // Only copies published at most this many hours apart are collapsed; the same
+    // notice text posted again a year later is a new notice
+    #[serde(default = "default_syndication_window_hours")]
+    pub syndication_window_hours: i64,
+    // Log line format when no --log-format flag is given (read before the rest of the
+    // config, see log_format)
+    #[serde(default)]
+    pub log_format: LogFormat,
+    // What happens to a new blog article whose normalized title is already stored
+    #[serde(default)]
+    pub duplicate_titles: DuplicateTitles,
+    // Where the run's new items are posted after a crawl
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+    // Items sharing tags added to each item's related ids at the end of a crawl
+    #[serde(default)]
+    pub related_tags: RelatedTagsConfig,
+    // Check exported thumbnails before each export; broken ones are left out of it
+    #[serde(default)]
+    pub thumbnail_check: Option<ThumbnailCheckConfig>,
+    // Also flag items first seen within this many hours as new, not only this run's
+    #[serde(default)]
+    pub new_window_hours: Option<i64>,
+    // Include each item's discovering page in the export (debugging aid)
+    #[serde(default)]
+    pub export_discovered_from: bool,
+    // Include the title an item had before its latest title change as previous_title
+    #[serde(default)]
+    pub export_previous_title: bool,
+    // Earlier versions kept per item when a refresh or recrawl changes it; 0 keeps none
+    #[serde(default = "default_max_revisions")]
+    pub max_revisions: usize,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub boilerplate: BoilerplateConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    // Written after the export to output_path, from the same options
+    #[serde(default)]
+    pub export_targets: Vec<ExportTarget>,
+    // Wanted page languages (e.g. ["ja"]); hreflang alternates in other languages
+    // are not queued. Empty keeps every link, but non-ja copies of ja pages are still skipped.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    // Also write the exported items grouped by month (JST) to this file
+    #[serde(default)]
+    pub archive_path: Option<String>,
+    // Also write the top feed_items exported items as an Atom feed to this file
+    #[serde(default)]
+    pub feed_path: Option<String>,
+    #[serde(default = "default_feed_items")]
+    pub feed_items: usize,
+    // Meta-refresh stubs with at most this delay are followed to their target
+    #[serde(default = "default_meta_refresh_max_secs")]
+    pub meta_refresh_max_secs: u64,
+    // Link wrappers in feeds: shortener hosts, or a path and query parameter such as
+    // "/outbound?url=" on any host. Feed entries on them are followed to where they
+    // lead, and the destination is what gets scope-checked and stored.
+    #[serde(default = "default_redirectors")]
+    pub redirectors: Vec<String>,
+    // Per-source limits: inserted content rows, and article HTTP fetches (redirect
+    // hops and refetches of known pages included)
+    #[serde(default = "default_max_new_items", alias = "max_new_per_site")]
+    pub max_new_items: usize,
+    #[serde(default = "default_max_fetch_attempts")]
+    pub max_fetch_attempts_per_source: usize,
+    // Sitemap entries (by lastmod) and articles older than this are not added, for
+    // blogs without a max_article_age_days of their own
+    #[serde(default, alias = "max_age_days")]
+    pub max_article_age_days: Option<i64>,
+    // HTML link scraping follows links this many pages away from the blog root;
+    // pages at this depth are still crawled as articles
+    #[serde(default = "default_max_depth")]
+    pub max_depth: i64,
+    // Keep each article's extracted main text (compressed, size-capped) for search
+    #[serde(default)]
+    pub store_fulltext: bool,
+    // Articles removed at the blogger's request: exact URLs, or prefixes ending in "*".
+    // Never exported and never stored again.
+    #[serde(default)]
+    pub takedowns: Vec<String>,
+    // Minimum gap between requests to one host; a longer robots.txt Crawl-delay wins
+    #[serde(default = "default_request_delay_ms")]
+    pub request_delay_ms: u64,
+    // Bounds of the per-host delay, which starts at request_delay_ms and adapts to the
+    // host's response times and errors within a run. The floor defaults to
+    // request_delay_ms itself, so a fast host is only sped up when it is set lower.
+    #[serde(default)]
+    pub min_request_delay_ms: Option<u64>,
+    #[serde(default = "default_max_request_delay_ms")]
+    pub max_request_delay_ms: u64,
+    // HTTP timeouts: establishing a connection, and a whole request
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    // Replaces the default User-Agent (crate name, version and repository URL)
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    // Sources (blogs and channels) crawled at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    // Sources that may fail (error out or panic) before a run exits non-zero; the
+    // export is written either way
+    #[serde(default)]
+    pub max_failed_sources: usize,
+    // Data-quality counts above which a run exits non-zero, likewise after the export
+    #[serde(default)]
+    pub quality_thresholds: QualityThresholds,
+    // SQLite file and export destination; separate values let several topic
+    // crawlers share one working directory
+    #[serde(default = "default_db_path")]
+    pub db_path: String,
+    #[serde(default = "default_output_path")]
+    pub output_path: String,
+    // Per-source report of the last crawl
+    #[serde(default = "default_summary_path")]
+    pub summary_path: String,
+    // `crawler check-links`: stored articles checked per run, and the gap between
+    // requests to one host
+    #[serde(default = "default_link_check_batch")]
+    pub link_check_batch: usize,
+    #[serde(default = "default_link_check_delay_ms")]
+    pub link_check_delay_ms: u64,
+    // `crawler refresh` refetches articles stored or refreshed longer ago than this
+    #[serde(default = "default_refresh_after_days")]
+    pub refresh_after_days: i64,
+    // Set by --dry-run, never read from the file: new articles are printed with their
+    // score instead of being stored
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+// No sources, every setting at its default
+impl Default for Config {
+    fn default() -> Self {
+        serde_json::from_str(r#"{"youtube": [], "blogs": []}"#).expect("the defaults deserialize")
+    }
+}
+
+impl Config {
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+}
+
+fn default_meta_refresh_max_secs() -> u64 {
+    5
+}
+
+fn default_redirectors() -> Vec<String> {
+    [
+        "t.co",
+        "bit.ly",
+        "buff.ly",
+        "ow.ly",
+        "tinyurl.com",
+        "amzn.to",
+        "/outbound?url=",
+    ]
+    .map(str::to_string)
+    .to_vec()
+}
+
+fn default_syndication_window_hours() -> i64 {
+    72
+}
+
+fn default_max_depth() -> i64 {
+    3
+}
+
+fn default_feed_items() -> usize {
+    50
+}
+
+fn default_max_new_items() -> usize {
+    5
+}
+
+fn default_max_fetch_attempts() -> usize {
+    30
+}
+
+fn default_request_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_revisions() -> usize {
+    10
+}
+
+fn default_max_request_delay_ms() -> u64 {
+    30_000
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_db_path() -> String {
+    DEFAULT_DB_PATH.to_string()
+}
+
+fn default_output_path() -> String {
+    "index.json".to_string()
+}
+
+fn default_summary_path() -> String {
+    "summary.json".to_string()
+}
+
+fn default_link_check_batch() -> usize {
+    100
+}
+
+fn default_link_check_delay_ms() -> u64 {
+    2000
+}
+
+fn default_refresh_after_days() -> i64 {
+    30
+}
+
+// Trimming of index.json; the defaults export every item into the one file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    // Keep only the first max_items after sorting by score
+    pub max_items: Option<usize>,
+    // Drop items scoring below this (listing pages typically score 0)
+    pub min_score: Option<i32>,
+    // Also write index_<type>.json next to the output file for each content type
+    pub per_type: bool,
+    // Inferred from the output file extension when not set
+    pub format: Option<ExportFormat>,
+    // Write only the items added since the previous incremental export, to delta_path
+    // (default delta.json), and leave the full export alone unless also_full is set
+    pub incremental: bool,
+    pub also_full: bool,
+    pub delta_path: Option<String>,
+    // Which stored rows the export is made of
+    pub include: ExportInclude,
+    // Fields added to every item, from EXPORT_EXTRA_FIELDS
+    pub extra_fields: Vec<String>,
+    // Item order; undated items go last either way
+    pub sort: ExportSort,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportSort {
+    #[default]
+    Score,
+    PublishedAt,
+    FirstSeenAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+// Optional item fields: fetched_at is the latest fetch, first_seen_at the first one,
+// updated_at the last refresh or recrawl, domain the URL's host and word_count the
+// stored main text length in characters. source and tags are always exported.
+pub const EXPORT_EXTRA_FIELDS: &[&str] = &[
+    "fetched_at",
+    "first_seen_at",
+    "updated_at",
+    "source",
+    "domain",
+    "tags",
+    "word_count",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportInclude {
+    // Rows with a working link that nothing replaced
+    #[default]
+    Live,
+    // Only dead links and superseded rows, with dead_at and any archived_url
+    Dead,
+    All,
+}
+
+// A further export file, e.g. {"path": "archive.json", "include": "dead"}. Items are
+// scored and ordered as in index.json.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportTarget {
+    pub path: String,
+    #[serde(default)]
+    pub include: ExportInclude,
+    // Inferred from the path's extension when not set
+    #[serde(default)]
+    pub format: Option<ExportFormat>,
+    #[serde(default)]
+    pub max_items: Option<usize>,
+    #[serde(default)]
+    pub min_score: Option<i32>,
+    #[serde(default)]
+    pub extra_fields: Vec<String>,
+    #[serde(default)]
+    pub sort: ExportSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    // One pretty-printed array
+    #[default]
+    Json,
+    // One compact item per line
+    Ndjson,
+    // Header row, RFC 4180 quoting; missing values are empty cells
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<ExportFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "ndjson" | "jsonl" => Some(ExportFormat::Ndjson),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    // An explicit format wins; otherwise .ndjson/.jsonl/.csv, and JSON for anything else
+    pub fn resolve(explicit: Option<ExportFormat>, path: &str) -> ExportFormat {
+        explicit
+            .or_else(|| {
+                std::path::Path::new(path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .and_then(ExportFormat::parse)
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateTitles {
+    // Not stored
+    #[default]
+    Skip,
+    // Stored when published earlier than the stored copy, which is then hidden
+    KeepEarliest,
+    // Stored anyway (blogs whose titles repeat, e.g. "Site | title" themes)
+    Keep,
+}
+
+// Outgoing proxy for every client. Without one, reqwest's own lookup of the
+// HTTP_PROXY / HTTPS_PROXY / ALL_PROXY / NO_PROXY variables applies.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    // e.g. "http://proxy.example:3128"; credentials may be part of the URL
+    pub proxy: Option<String>,
+    // Hosts reached directly, in NO_PROXY syntax ("localhost,.lan,10.0.0.0/8");
+    // defaults to the NO_PROXY variable
+    pub no_proxy: Option<String>,
+    // Hosts whose certificates aren't verified (expired/self-signed), e.g.
+    // ["old-blog.example"]. Applies to the clients of blogs whose url is on one of
+    // them; such a client skips verification for every request it makes, redirects
+    // to other hosts included.
+    pub danger_accept_invalid_certs: Option<Vec<String>>,
+}
+
+impl HttpConfig {
+    pub fn accepts_invalid_certs(&self, url: &str) -> bool {
+        let Some(hosts) = &self.danger_accept_invalid_certs else {
+            return false;
+        };
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+        else {
+            return false;
+        };
+        hosts.iter().any(|h| h.eq_ignore_ascii_case(&host))
+    }
+}
+
+// HEAD requests to the thumbnails of exported items. 404s, hotlink refusals (403)
+// and non-image answers are cached as broken, OK answers as fine, both for ttl_hours;
+// 5xx and network failures are tried again next time.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ThumbnailCheckConfig {
+    #[serde(default = "default_thumbnail_check_ttl_hours")]
+    pub ttl_hours: i64,
+    // Image hosts checked at once; each host's images are requested one at a time
+    #[serde(default = "default_thumbnail_check_concurrency")]
+    pub concurrency: usize,
+    // Gap between two requests to one host
+    #[serde(default = "default_thumbnail_check_delay_ms")]
+    pub delay_ms: u64,
+}
+
+fn default_thumbnail_check_ttl_hours() -> i64 {
+    7 * 24
+}
+
+fn default_thumbnail_check_concurrency() -> usize {
+    4
+}
+
+fn default_thumbnail_check_delay_ms() -> u64 {
+    500
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    // Queued items the webhook hasn't accepted within this many hours are dropped
+    #[serde(default = "default_notification_max_age_hours")]
+    pub max_age_hours: i64,
+}
+
+fn default_notification_max_age_hours() -> i64 {
+    72
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    // One embed per item
+    Discord,
+    // A JSON array of {title, url, source, score}
+    #[default]
+    Generic,
+}
+
+// Additions to the built-in boilerplate (login/privacy/profile) page patterns
+#[derive(Debug, Default, Deserialize)]
+pub struct BoilerplateConfig {
+    // Regexes matched against the URL path
+    #[serde(default)]
+    pub extra_paths: Vec<String>,
+    // Substrings matched against the page title
+    #[serde(default)]
+    pub extra_titles: Vec<String>,
+}
+
+// Used when the config has no term_weights of its own
+const DEFAULT_TERM_WEIGHTS: &[(&str, i32)] = &[
+    ("酷道", 3),
+    ("険道", 3),
+    ("廃道", 3),
+    ("未成道", 3),
+    ("旧道", 2),
+    ("隧道", 2),
+    ("廃橋", 2),
+    ("林道", 1),
+    ("洞門", 1),
+    ("峠", 1),
+    ("冬季閉鎖", 1),
+];
+
+// Tunables for export::calculate_score
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    // Bonus for long reports (visible body characters above the threshold)
+    pub long_text_chars: i64,
+    pub long_text_bonus: i32,
+    // Bonus for photo-heavy reports
+    pub many_images: i64,
+    pub many_images_bonus: i32,
+    // Host pattern ("example.com" or "*.example.com") to score adjustment.
+    // An exact host beats wildcards; the longest wildcard suffix beats shorter ones.
+    pub domain_weights: HashMap<String, DomainWeight>,
+    // Hobby vocabulary to weight, matched over title + description.
+    // Keys are plain substrings, or regexes when prefixed with "re:".
+    pub term_weights: HashMap<String, i32>,
+    // Upper bound on the combined positive term contribution
+    pub term_weight_cap: i32,
+    // Term weights matched in the body count this percentage of their value: the
+    // stored main text of blogs with store_fulltext, the 200-character excerpt of others
+    pub body_term_percent: i32,
+    // Bonus for fresh items: recency_bonus up to recency_full_days old, falling
+    // linearly to 0 at recency_days. Items dated only by fetched_at get
+    // recency_fetched_percent of it.
+    pub recency_bonus: i32,
+    pub recency_full_days: i64,
+    pub recency_days: i64,
+    pub recency_fetched_percent: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RelatedTagsConfig {
+    pub enabled: bool,
+    // Neighbours per item, besides its explicit relations
+    pub max_items: usize,
+}
+
+impl Default for RelatedTagsConfig {
+    fn default() -> Self {
+        RelatedTagsConfig {
+            enabled: true,
+            max_items: 5,
+        }
+    }
+}
+
+// Upper bounds on the end-of-run quality report (db::QualityReport); unset ones
+// are not checked
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QualityThresholds {
+    pub max_duplicate_rows: Option<i64>,
+    pub max_placeholder_titles: Option<i64>,
+    pub max_missing_published_at: Option<i64>,
+    pub max_missing_thumbnail: Option<i64>,
+    pub max_invalid_urls: Option<i64>,
+    // Sources whose new items all share one title
+    pub max_identical_title_sources: Option<usize>,
+}
+
+// Applied as score * multiplier + delta
+#[derive(Debug, Clone, Deserialize)]
+pub struct DomainWeight {
+    #[serde(default)]
+    pub multiplier: Option<f64>,
+    #[serde(default)]
+    pub delta: Option<i32>,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            long_text_chars: 3000,
+            long_text_bonus: 2,
+            many_images: 10,
+            many_images_bonus: 1,
+            domain_weights: HashMap::new(),
+            term_weights: DEFAULT_TERM_WEIGHTS
+                .iter()
+                .map(|(term, weight)| (term.to_string(), *weight))
+                .collect(),
+            term_weight_cap: 6,
+            body_term_percent: 50,
+            recency_bonus: 10,
+            recency_full_days: 7,
+            recency_days: 180,
+            recency_fetched_percent: 50,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct YouTubeConfig {
     pub channel_id: String,
     pub name: String,
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct BlogConfig {
     pub name: String,
     pub url: String,
+    // Mirrors or former domains of the same blog; their URLs are stored under `url`'s domain
+    #[serde(default)]
+    pub alt_urls: Vec<String>,
+    // Other hosts the blog's own pages live on (e.g. a custom domain next to the
+    // hosting service's subdomain); their links are followed and stored as they are.
+    // www. and bare variants of a host never need listing.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    // Sitemaps read instead of {url}/sitemap.xml, in order, their entries combined:
+    // absolute URLs or paths resolved against url like links. Without them, the
+    // robots.txt Sitemap: lines are tried before the default path.
+    #[serde(default)]
+    pub sitemaps: Vec<String>,
+    // Free-form group label (e.g. 酷道系) carried through to the export
+    #[serde(default)]
+    pub category: Option<String>,
+    // Exported in place of a thumbnail that failed thumbnail_check
+    #[serde(default)]
+    pub default_thumbnail: Option<String>,
+    // Replace the boilerplate path/title patterns for this blog only
+    #[serde(default)]
+    pub boilerplate_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub boilerplate_titles: Option<Vec<String>>,
+    // Credentials for this blog's requests only; secrets come from environment variables
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    // Local time window in which this blog is not crawled (the run defers it)
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    // Known articles unreachable by discovery; fetched once, before the crawl
+    #[serde(default)]
+    pub seed_urls: Vec<String>,
+    // Let inserted seeds use up the per-site new-item limit
+    #[serde(default)]
+    pub seeds_use_budget: bool,
+    // Don't add articles older than this; already stored ones are kept. Overrides the
+    // global max_article_age_days.
+    #[serde(default, alias = "max_age_days")]
+    pub max_article_age_days: Option<i64>,
+    // With max_article_age_days, also drop sitemap entries without lastmod and pages
+    // without article:published_time
+    #[serde(default)]
+    pub max_article_age_strict: bool,
+    // Moved to http.danger_accept_invalid_certs; only read to refuse old configs
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+    // Overrides the global store_fulltext for this blog
+    #[serde(default)]
+    pub store_fulltext: Option<bool>,
+    // Overrides the global request_delay_ms for this blog
+    #[serde(default)]
+    pub request_delay_ms: Option<u64>,
+    // Overrides the global max_new_items for this blog
+    #[serde(default)]
+    pub max_new: Option<usize>,
+    // Overrides the global max_depth for this blog
+    #[serde(default)]
+    pub max_depth: Option<i64>,
+    // Sent with every request for this blog (e.g. a Referer the site insists on)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // Regexes matched against the whole URL before it is queued or stored: when any
+    // include pattern is given one must match, and no exclude pattern may
+    #[serde(default)]
+    pub include_patterns: Vec<String>,
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+}
+
+impl BlogConfig {
+    // A blog of just the origin of `url`, named after its host, every setting at its
+    // default
+    pub fn for_url(url: &str) -> Result<BlogConfig> {
+        let parsed = Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+        let host = parsed
+            .host_str()
+            .with_context(|| format!("No host in {}", url))?;
+
+        Ok(serde_json::from_value(serde_json::json!({
+            "name": host,
+            "url": format!("{}/", parsed.origin().ascii_serialization()),
+        }))?)
+    }
+
+    // `headers` as request headers; fails on a malformed name or value
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("{}: invalid header name {:?}", self.name, name))?;
+            let header_value = HeaderValue::from_str(value)
+                .with_context(|| format!("{}: invalid value for header {}", self.name, name))?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuietHours {
+    // "HH:MM"; a start after the end spans midnight
+    pub start: String,
+    pub end: String,
+    #[serde(default = "default_quiet_tz")]
+    pub tz: String,
+}
+
+fn default_quiet_tz() -> String {
+    "Asia/Tokyo".to_string()
+}
+
+impl QuietHours {
+    // Parsed start, end and time zone; also run at load by validate
+    fn window(&self) -> Result<(NaiveTime, NaiveTime, Tz)> {
+        let parse = |t: &str| {
+            NaiveTime::parse_from_str(t, "%H:%M")
+                .with_context(|| format!("Invalid quiet_hours time: {}", t))
+        };
+        let tz: Tz = self
+            .tz
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid quiet_hours tz: {}", self.tz))?;
+
+        Ok((parse(&self.start)?, parse(&self.end)?, tz))
+    }
+
+    // Whether `now` falls inside [start, end) in the window's time zone
+    pub fn contains(&self, now: DateTime<Utc>) -> Result<bool> {
+        let (start, end, tz) = self.window()?;
+        let local = now.with_timezone(&tz).time();
+
+        Ok(if start <= end {
+            start <= local && local < end
+        } else {
+            local >= start || local < end
+        })
+    }
+}
+
+// Unknown fields are rejected so an inline `password`/`cookie` fails to load
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AuthConfig {
+    Basic {
+        username: String,
+        password_env: String,
+    },
+    Cookie {
+        cookie_env: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    // Human-readable lines
+    #[default]
+    Text,
+    // One JSON object per line (logging::JsonLayer)
+    Json,
+}
+
+// log_format of the config file at `path`, for setting up logging before the config
+// is loaded (and its errors can be logged). None when the file can't be read.
+pub fn log_format(path: &str) -> Option<LogFormat> {
+    #[derive(Deserialize)]
+    struct Logging {
+        #[serde(default)]
+        log_format: LogFormat,
+    }
+
+    let text = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<Logging>(&text)
+        .ok()
+        .map(|logging| logging.log_format)
 }
 
 pub fn load(path: &str) -> Result<Config> {
     let text = fs::read_to_string(path)?;
     let config: Config = serde_json::from_str(&text)?;
+    validate(&config)?;
+    for host in config.http.danger_accept_invalid_certs.iter().flatten() {
+        warn!(
+            "certificate verification is disabled for {} (http.danger_accept_invalid_certs)",
+            host
+        );
+    }
     Ok(config)
 }
+
+fn validate(config: &Config) -> Result<()> {
+    HeaderValue::from_str(config.user_agent())
+        .with_context(|| format!("Invalid user_agent: {:?}", config.user_agent()))?;
+
+    // Not echoed: the URL may carry proxy credentials
+    if let Some(proxy) = &config.http.proxy {
+        reqwest::Proxy::all(proxy).context("Invalid http.proxy URL")?;
+    }
+
+    if let Some(hosts) = &config.http.danger_accept_invalid_certs {
+        if hosts.is_empty() {
+            anyhow::bail!("http.danger_accept_invalid_certs lists no hosts");
+        }
+        for host in hosts {
+            if host.is_empty() || host.contains('/') || host.contains(':') {
+                anyhow::bail!(
+                    "http.danger_accept_invalid_certs takes host names, not URLs: {:?}",
+                    host
+                );
+            }
+        }
+    }
+
+    if let Some(min) = config.min_request_delay_ms
+        && min > config.max_request_delay_ms
+    {
+        anyhow::bail!(
+            "min_request_delay_ms ({}) is above max_request_delay_ms ({})",
+            min,
+            config.max_request_delay_ms
+        );
+    }
+
+    let extra_fields = std::iter::once(("export", &config.export.extra_fields)).chain(
+        config
+            .export_targets
+            .iter()
+            .map(|target| (target.path.as_str(), &target.extra_fields)),
+    );
+    for (export, fields) in extra_fields {
+        if let Some(field) = fields
+            .iter()
+            .find(|field| !EXPORT_EXTRA_FIELDS.contains(&field.as_str()))
+        {
+            anyhow::bail!(
+                "{}: unknown extra_fields entry {:?} (one of {})",
+                export,
+                field,
+                EXPORT_EXTRA_FIELDS.join(", ")
+            );
+        }
+    }
+
+    for blog in &config.blogs {
+        if blog.danger_accept_invalid_certs {
+            anyhow::bail!(
+                "{}: danger_accept_invalid_certs is now http.danger_accept_invalid_certs, a list of hosts",
+                blog.name
+            );
+        }
+
+        let host_of = |url: &str| -> Result<String> {
+            Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()))
+                .with_context(|| format!("{}: invalid URL {}", blog.name, url))
+        };
+
+        let mut hosts = vec![host_of(&blog.url)?];
+        for alt in &blog.alt_urls {
+            hosts.push(host_of(alt)?);
+        }
+        for host in &blog.allowed_hosts {
+            if host.contains('/') {
+                anyhow::bail!(
+                    "{}: allowed_hosts takes host names, not URLs: {}",
+                    blog.name,
+                    host
+                );
+            }
+            hosts.push(host_of(&format!("http://{}/", host))?);
+        }
+
+        UrlFilter::new(blog)?;
+        blog.header_map()?;
+        if let Some(quiet_hours) = &blog.quiet_hours {
+            quiet_hours
+                .window()
+                .with_context(|| format!("{}: invalid quiet_hours", blog.name))?;
+        }
+
+        for seed in &blog.seed_urls {
+            if !hosts.contains(&host_of(seed)?) {
+                anyhow::bail!(
+                    "{}: seed URL {} is outside the blog's domains",
+                    blog.name,
+                    seed
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet(start: &str, end: &str) -> QuietHours {
+        QuietHours {
+            start: start.to_string(),
+            end: end.to_string(),
+            tz: default_quiet_tz(),
+        }
+    }
+
+    // A JST wall-clock time on some day
+    fn jst(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2024-05-01T{}:00+09:00", time))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn quiet_hours_same_day_window() {
+        let window = quiet("19:00", "23:00");
+        assert!(!window.contains(jst("18:59")).unwrap());
+        assert!(window.contains(jst("19:00")).unwrap());
+        assert!(window.contains(jst("22:59")).unwrap());
+        assert!(!window.contains(jst("23:00")).unwrap());
+    }
+
+    #[test]
+    fn quiet_hours_window_across_midnight() {
+        let window = quiet("22:00", "06:00");
+        assert!(window.contains(jst("23:30")).unwrap());
+        assert!(window.contains(jst("00:00")).unwrap());
+        assert!(window.contains(jst("05:59")).unwrap());
+        assert!(!window.contains(jst("06:00")).unwrap());
+        assert!(!window.contains(jst("12:00")).unwrap());
+    }
+
+    #[test]
+    fn unknown_extra_fields_fail_at_load() {
+        let config = |fields: &str| -> Config {
+            serde_json::from_str(&format!(
+                r#"{{"youtube": [], "blogs": [], "export_targets": [{{"path": "new.json", "extra_fields": {}}}]}}"#,
+                fields
+            ))
+            .unwrap()
+        };
+
+        assert!(validate(&config(r#"["first_seen_at", "domain"]"#)).is_ok());
+        let error = validate(&config(r#"["first_seen_at", "slug"]"#)).unwrap_err();
+        assert!(error.to_string().contains("\"slug\""), "{}", error);
+    }
+
+    #[test]
+    fn unknown_sort_options_fail_at_load() {
+        let target = |options: &str| {
+            serde_json::from_str::<Config>(&format!(
+                r#"{{"youtube": [], "blogs": [], "export_targets": [{{"path": "new.json", {}}}]}}"#,
+                options
+            ))
+        };
+
+        let config = target(r#""sort": "first_seen_at", "direction": "asc""#).unwrap();
+        assert_eq!(config.export_targets[0].sort, ExportSort::FirstSeenAt);
+        assert_eq!(config.export_targets[0].direction, SortDirection::Asc);
+        assert!(target(r#""sort": "title""#).is_err());
+        assert!(target(r#""direction": "up""#).is_err());
+    }
+
+    #[test]
+    fn invalid_quiet_hours_fail_at_load() {
+        for quiet_hours in [
+            r#"{"start": "25:00", "end": "06:00"}"#,
+            r#"{"start": "22:00", "end": "6pm"}"#,
+            r#"{"start": "22:00", "end": "06:00", "tz": "Mars/Olympus"}"#,
+        ] {
+            let text = format!(
+                r#"{{"youtube": [], "blogs": [{{"name": "a", "url": "https://example.com/", "quiet_hours": {}}}]}}"#,
+                quiet_hours
+            );
+            let config: Config = serde_json::from_str(&text).unwrap();
+            assert!(
+                validate(&config).is_err(),
+                "{} should be rejected",
+                quiet_hours
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_certificate_hosts_are_checked_at_load() {
+        let config = |http: &str, blog: &str| -> Config {
+            serde_json::from_str(&format!(
+                r#"{{"youtube": [], "blogs": [{{"name": "a", "url": "https://old.example/"{}}}], "http": {}}}"#,
+                blog, http
+            ))
+            .unwrap()
+        };
+
+        let listed = config(r#"{"danger_accept_invalid_certs": ["OLD.example"]}"#, "");
+        assert!(validate(&listed).is_ok());
+        assert!(
+            listed
+                .http
+                .accepts_invalid_certs("https://old.example/posts/1.html")
+        );
+        assert!(!listed.http.accepts_invalid_certs("https://new.example/"));
+        assert!(
+            !config("{}", "")
+                .http
+                .accepts_invalid_certs("https://old.example/")
+        );
+
+        let error = validate(&config(r#"{"danger_accept_invalid_certs": []}"#, "")).unwrap_err();
+        assert!(error.to_string().contains("lists no hosts"), "{}", error);
+        let url = r#"{"danger_accept_invalid_certs": ["https://old.example/"]}"#;
+        assert!(validate(&config(url, "")).is_err());
+        // The old per-blog switch points at the new setting instead of being ignored
+        let error =
+            validate(&config("{}", r#", "danger_accept_invalid_certs": true"#)).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("http.danger_accept_invalid_certs"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,9 +1,19 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OpenFlags, params};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::debug;
+use url::Url;
+
+use crate::classify;
+use crate::config::RelatedTagsConfig;
+
+const BUSY_TIMEOUT_SECS: u64 = 30;
 
 // Struct used for export
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Content {
     pub id: String,
     pub content_type: String,
@@ -12,6 +22,70 @@ pub struct Content {
     pub description: Option<String>,
     pub thumbnail: Option<String>,
     pub published_at: Option<String>,
+    pub category: Option<String>,
+    pub fingerprint: Option<String>,
+    pub source: Option<String>,
+    pub fetched_at: String,
+    pub discovered_from: Option<String>,
+    pub text_length: Option<i64>,
+    pub image_count: Option<i64>,
+    pub site_name: Option<String>,
+    // From the page's robots unavailable_after; not exported after this time
+    pub expires_at: Option<String>,
+    // Start of the main text (fulltext::excerpt)
+    pub excerpt: Option<String>,
+    // title_hash of blog rows
+    pub title_hash: Option<String>,
+    // Rows out of the live export: when the link was found dead or the row was
+    // superseded, a known archived copy, and the row that replaces it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
+    // Last refresh or recrawl of the row; fetched_at stays the first-seen time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refreshed_at: Option<String>,
+    // Stored main text (content_text), for scoring; never exported
+    #[serde(skip)]
+    pub main_text: Option<String>,
+}
+
+// Every connection waits on locks instead of failing with SQLITE_BUSY. Crawl tasks
+// share one thread and never hold a transaction across an await, so within a run
+// this only matters for other processes (prune, top) using the file at the same time.
+pub fn open(path: &str) -> Result<Connection> {
+    if let Some(dir) = std::path::Path::new(path).parent()
+        && !dir.as_os_str().is_empty()
+        && !dir.is_dir()
+    {
+        anyhow::bail!(
+            "Database directory {} does not exist; create it or change db_path",
+            dir.display()
+        );
+    }
+
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(std::time::Duration::from_secs(BUSY_TIMEOUT_SECS))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+
+    Ok(conn)
+}
+
+// For read-only reports: no schema setup or migration, and nothing is written, so
+// this fails on a database that an up-to-date crawl hasn't run against yet
+pub fn open_read_only(path: &str) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Cannot open database {}", path))?;
+    conn.busy_timeout(std::time::Duration::from_secs(BUSY_TIMEOUT_SECS))?;
+    Ok(conn)
+}
+
+// Consistent copy of the database at `path`, which must not exist yet
+pub fn snapshot(conn: &Connection, path: &str) -> Result<()> {
+    conn.execute("VACUUM INTO ?1", [path])?;
+    Ok(())
 }
 
 // Initialize database and table
@@ -40,7 +114,7 @@ pub fn init(conn: &Connection) -> Result<()> {
         CREATE TABLE IF NOT EXISTS crawl_queue (
             url TEXT PRIMARY KEY,
             parent_url TEXT,
-            status TEXT NOT NULL, -- pending / done / error
+            status TEXT NOT NULL, -- pending / done / error / robots_blocked
             discovered_at TEXT NOT NULL,
             fetched_at TEXT,
             retry_count INTEGER DEFAULT 0,
@@ -56,6 +130,147 @@ pub fn init(conn: &Connection) -> Result<()> {
     )?;
 
     init_error_table(conn)?;
+    init_sources_table(conn)?;
+
+    add_column_if_missing(conn, "contents", "source", "TEXT")?;
+    add_column_if_missing(conn, "contents", "fingerprint", "TEXT")?;
+    add_column_if_missing(conn, "contents", "deleted_at", "TEXT")?;
+    add_column_if_missing(conn, "contents", "discovered_from", "TEXT")?;
+    add_column_if_missing(conn, "contents", "text_length", "INTEGER")?;
+    add_column_if_missing(conn, "contents", "image_count", "INTEGER")?;
+    add_column_if_missing(conn, "contents", "site_name", "TEXT")?;
+    add_column_if_missing(conn, "contents", "expires_at", "TEXT")?;
+    add_column_if_missing(conn, "contents", "refreshed_at", "TEXT")?;
+    add_column_if_missing(conn, "contents", "excerpt", "TEXT")?;
+    add_column_if_missing(conn, "contents", "title_hash", "TEXT")?;
+    // Where a refresh found the row's page moved to (see record_move)
+    add_column_if_missing(conn, "contents", "redirected_to", "TEXT")?;
+    // The row a superseded one was replaced by (supersede, record_move)
+    add_column_if_missing(conn, "contents", "superseded_by", "TEXT")?;
+    // Set when link_status turns dead; archived_url is a copy elsewhere (e.g. Wayback)
+    add_column_if_missing(conn, "contents", "dead_at", "TEXT")?;
+    add_column_if_missing(conn, "contents", "archived_url", "TEXT")?;
+    // check-links: link_status is ok / missing / dead; link_failures counts consecutive
+    // 404/410 answers
+    add_column_if_missing(conn, "contents", "last_checked_at", "TEXT")?;
+    add_column_if_missing(conn, "contents", "link_status", "TEXT")?;
+    add_column_if_missing(
+        conn,
+        "contents",
+        "link_failures",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    // Rows found dead before dead_at was recorded
+    conn.execute(
+        "UPDATE contents SET dead_at = last_checked_at WHERE link_status = 'dead' AND dead_at IS NULL",
+        [],
+    )?;
+    add_column_if_missing(conn, "crawl_queue", "last_error", "TEXT")?;
+    // Links followed from the blog root; rows queued before depth tracking count as roots
+    add_column_if_missing(conn, "crawl_queue", "depth", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_contents_fingerprint ON contents (fingerprint);",
+    )?;
+    backfill_fingerprints(conn)?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_contents_title_hash ON contents (type, title_hash);",
+    )?;
+    backfill_title_hashes(conn)?;
+
+    init_related_tables(conn)?;
+    // Order of the tag pass's neighbours; null for explicit relations
+    add_column_if_missing(conn, "related", "rank", "INTEGER")?;
+    // After init_related_tables: body is filled from content_text
+    init_search_table(conn)?;
+    add_column_if_missing(
+        conn,
+        "stats_history",
+        "fulltext_rows",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(
+        conn,
+        "stats_history",
+        "fulltext_bytes",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(
+        conn,
+        "stats_history",
+        "revisions",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+
+    Ok(())
+}
+
+// Full-text index of title, description, excerpt and the stored main text (body) for
+// `crawler search`. The trigram tokenizer matches any 3+ character substring, which
+// suits Japanese text without word breaks. Keyed by content id rather than rowid:
+// VACUUM may renumber rowids. Filled from existing rows when first created; an index
+// from before the body column is rebuilt.
+fn init_search_table(conn: &Connection) -> Result<()> {
+    let columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('contents_fts')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let current = columns.iter().any(|c| c == "body");
+
+    if !columns.is_empty() && !current {
+        conn.execute_batch(
+            "
+            DROP TRIGGER IF EXISTS contents_fts_insert;
+            DROP TRIGGER IF EXISTS contents_fts_delete;
+            DROP TRIGGER IF EXISTS contents_fts_update;
+            DROP TABLE contents_fts;
+            ",
+        )?;
+    }
+
+    // body is kept by the update trigger: it only comes from set_content_text
+    conn.execute_batch(
+        "
+        CREATE VIRTUAL TABLE IF NOT EXISTS contents_fts USING fts5(
+            id UNINDEXED, title, description, excerpt, body, tokenize = 'trigram'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS contents_fts_insert AFTER INSERT ON contents BEGIN
+            INSERT INTO contents_fts (id, title, description, excerpt)
+            VALUES (new.id, new.title, new.description, new.excerpt);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS contents_fts_delete AFTER DELETE ON contents BEGIN
+            DELETE FROM contents_fts WHERE id = old.id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS contents_fts_update
+        AFTER UPDATE OF title, description, excerpt ON contents BEGIN
+            UPDATE contents_fts
+            SET title = new.title, description = new.description, excerpt = new.excerpt
+            WHERE id = new.id;
+        END;
+        ",
+    )?;
+
+    if !current {
+        conn.execute_batch(
+            "INSERT INTO contents_fts (id, title, description, excerpt)
+             SELECT id, title, description, excerpt FROM contents;",
+        )?;
+
+        let mut stmt = conn.prepare("SELECT content_id, text FROM content_text")?;
+        let texts = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+        for text in texts {
+            let (id, compressed) = text?;
+            set_search_body(
+                conn,
+                &id,
+                &String::from_utf8(zstd::decode_all(&compressed[..])?)?,
+            )?;
+        }
+    }
 
     Ok(())
 }
@@ -74,146 +289,2805 @@ pub fn init_error_table(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-// Returns true if inserted, false if already existed
-pub fn insert(
+// Migration helper for databases created before a column existed
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {};",
+            table, column, decl
+        ))?;
+    }
+
+    Ok(())
+}
+
+pub fn init_related_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        -- YouTube videos embedded in blog articles (video_id = content id of a youtube row)
+        CREATE TABLE IF NOT EXISTS content_embeds (
+            content_id TEXT NOT NULL,
+            video_id TEXT NOT NULL,
+            PRIMARY KEY (content_id, video_id)
+        );
+
+        -- Cross-links between stored contents (embed / embedded_in / link / tag)
+        CREATE TABLE IF NOT EXISTS related (
+            content_id TEXT NOT NULL,
+            related_id TEXT NOT NULL,
+            relation_kind TEXT NOT NULL,
+            PRIMARY KEY (content_id, related_id, relation_kind)
+        );
+
+        -- End-of-run snapshots of queue and contents counts, for graphing growth
+        CREATE TABLE IF NOT EXISTS stats_history (
+            sampled_at TEXT PRIMARY KEY,
+            queue_pending INTEGER NOT NULL,
+            queue_done INTEGER NOT NULL,
+            queue_error INTEGER NOT NULL,
+            contents_blog INTEGER NOT NULL,
+            contents_youtube INTEGER NOT NULL,
+            error_sites INTEGER NOT NULL
+        );
+
+        -- Main text of articles from blogs with store_fulltext, zstd-compressed UTF-8.
+        -- Kept out of contents so the export queries stay slim.
+        CREATE TABLE IF NOT EXISTS content_text (
+            content_id TEXT PRIMARY KEY,
+            text BLOB NOT NULL,
+            chars INTEGER NOT NULL
+        );
+
+        -- Labels attached to contents; tag_type tells their origin (site_tag = the blog's own)
+        CREATE TABLE IF NOT EXISTS tags (
+            content_id TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            tag_type TEXT NOT NULL,
+            PRIMARY KEY (content_id, tag_type, tag)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_tags_tag ON tags (tag);
+
+        -- Earlier versions of contents rows, kept when a refresh or recrawl changes
+        -- them; captured_at is when the stored version was replaced
+        CREATE TABLE IF NOT EXISTS content_revisions (
+            content_id TEXT NOT NULL,
+            revision_no INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            description TEXT,
+            thumbnail TEXT,
+            published_at TEXT,
+            captured_at TEXT NOT NULL,
+            PRIMARY KEY (content_id, revision_no)
+        );
+
+        -- Last export-time check of a thumbnail URL; ok = 0 keeps it out of the export
+        CREATE TABLE IF NOT EXISTS thumbnail_checks (
+            url TEXT PRIMARY KEY,
+            ok INTEGER NOT NULL,
+            status INTEGER,
+            checked_at TEXT NOT NULL
+        );
+
+        -- Items to announce on the webhook, one row per item ever queued so none is
+        -- announced twice; sent_at / dropped_at end an entry
+        CREATE TABLE IF NOT EXISTS pending_notifications (
+            content_id TEXT PRIMARY KEY,
+            queued_at TEXT NOT NULL,
+            sent_at TEXT,
+            dropped_at TEXT
+        );
+
+        -- Validators of the last full response, for conditional GETs
+        CREATE TABLE IF NOT EXISTS http_cache (
+            url TEXT PRIMARY KEY,
+            etag TEXT,
+            last_modified TEXT,
+            stored_at TEXT NOT NULL
+        );
+
+        -- One row per crawl run; sources holds the per-source counts as JSON
+        CREATE TABLE IF NOT EXISTS crawl_runs (
+            started_at TEXT PRIMARY KEY,
+            finished_at TEXT NOT NULL,
+            pages_fetched INTEGER NOT NULL,
+            articles_inserted INTEGER NOT NULL,
+            articles_skipped INTEGER NOT NULL,
+            errors INTEGER NOT NULL,
+            sources TEXT NOT NULL
+        );
+
+        -- Newest fetched_at included in the last incremental export, per delta file
+        CREATE TABLE IF NOT EXISTS export_state (
+            path TEXT PRIMARY KEY,
+            last_fetched_at TEXT NOT NULL,
+            exported_at TEXT NOT NULL
+        );
+
+        -- Pages that only forward to another URL (kind: meta_refresh, wrapper)
+        CREATE TABLE IF NOT EXISTS redirects (
+            url TEXT PRIMARY KEY,
+            redirected_to TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            recorded_at TEXT NOT NULL
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+// Query parameters that only say where a visitor came from
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid"];
+
+// One spelling per URL, applied wherever URLs enter the queue or contents.
+// Raw UTF-8, percent-encoded and double-encoded (%25E3...) paths all end up as single
+// uppercase %XX escapes; escaped reserved characters such as %2F stay escaped.
+// The host is lowercased, the fragment, utm_* / fbclid / gclid parameters and a
+// trailing slash (except the root's) are dropped; other query parameters are kept.
+// Strings that are not http(s) URLs (e.g. video ids) are returned unchanged.
+pub fn canonical_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return url.to_string();
+    }
+
+    parsed.set_fragment(None);
+
+    let path = normalize_escapes(parsed.path());
+    let path = match path.strip_suffix('/') {
+        Some(trimmed) if !trimmed.is_empty() => trimmed.to_string(),
+        _ => path,
+    };
+    parsed.set_path(&path);
+
+    if let Some(query) = parsed.query() {
+        let kept: Vec<&str> = query
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or("");
+                !pair.is_empty() && !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key)
+            })
+            .collect();
+        let query = normalize_escapes(&kept.join("&"));
+        parsed.set_query((!query.is_empty()).then_some(query.as_str()));
+    }
+
+    parsed.to_string()
+}
+
+fn normalize_escapes(text: &str) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let hex_pair = |bytes: &[u8], i: usize| -> Option<u8> {
+        Some(hex(*bytes.get(i)?)? << 4 | hex(*bytes.get(i + 1)?)?)
+    };
+    let escape_at = |bytes: &[u8], i: usize| -> Option<u8> {
+        if bytes.get(i) != Some(&b'%') {
+            return None;
+        }
+        hex_pair(bytes, i + 1)
+    };
+
+    // %25 followed by a non-ASCII byte escape is double encoding; an encoded non-ASCII
+    // byte is never meant literally as "%E3"
+    let mut single = Vec::with_capacity(text.len());
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if escape_at(bytes, i) == Some(b'%') && hex_pair(bytes, i + 3).is_some_and(|b| b >= 0x80) {
+            single.push(b'%');
+            i += 3;
+        } else {
+            single.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    let mut result = String::with_capacity(single.len());
+    let mut i = 0;
+    while i < single.len() {
+        match escape_at(&single, i) {
+            Some(b) if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') => {
+                result.push(b as char);
+                i += 3;
+            }
+            Some(b) => {
+                result.push_str(&format!("%{:02X}", b));
+                i += 3;
+            }
+            None if single[i] >= 0x80 => {
+                result.push_str(&format!("%{:02X}", single[i]));
+                i += 1;
+            }
+            None => {
+                result.push(single[i] as char);
+                i += 1;
+            }
+        }
+    }
+
+    result
+}
+
+// Normalized title + description prefix used to spot reposts of the same text.
+// None when there is no description, since bare titles collide too easily.
+pub fn fingerprint(title: &str, description: Option<&str>) -> Option<String> {
+    let normalize = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    };
+
+    let description = normalize(description?);
+    if description.is_empty() {
+        return None;
+    }
+
+    let text = format!(
+        "{}\n{}",
+        normalize(title),
+        description.chars().take(200).collect::<String>()
+    );
+
+    // FNV-1a, stable across builds unlike std's hasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Some(format!("{:016x}", hash))
+}
+
+// Normalized titles this short are left unhashed: 日記 or 雑記 name many
+// different posts
+const MIN_TITLE_HASH_CHARS: usize = 4;
+
+// Hash of a title with the site-name suffix (after the last "|" or dash), whitespace and
+// zero-width characters removed, full-width ASCII folded to half-width and half-width
+// katakana to full-width, so the same entry under another permalink or mirror
+// matches. None for placeholder titles.
+pub fn title_hash(title: &str) -> Option<String> {
+    let folded = classify::fold_width(
+        &title
+            .chars()
+            .filter(|c| !matches!(c, '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}'))
+            .collect::<String>(),
+    );
+
+    let main = match folded.rsplit_once(['|', '–', '—']) {
+        Some((head, _)) if !head.trim().is_empty() => head,
+        _ => &folded,
+    };
+    let normalized: String = main
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+
+    if normalized.chars().count() < MIN_TITLE_HASH_CHARS || normalized == "notitle" {
+        return None;
+    }
+
+    // FNV-1a, as in fingerprint
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in normalized.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Some(format!("{:016x}", hash))
+}
+
+// Video titles repeat legitimately (series, live streams), so only blog rows get one
+fn content_title_hash(content_type: &str, title: &str) -> Option<String> {
+    (content_type == "blog")
+        .then(|| title_hash(title))
+        .flatten()
+}
+
+fn backfill_title_hashes(conn: &Connection) -> Result<()> {
+    // Titles with half-width kana were hashed before it was folded
+    let mut stmt = conn.prepare(
+        "SELECT id, title FROM contents WHERE type = 'blog'
+         AND (title_hash IS NULL OR title GLOB '*[｡-ﾟ]*')",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (id, title) in rows {
+        if let Some(hash) = title_hash(&title) {
+            conn.execute(
+                "UPDATE contents SET title_hash = ?1 WHERE id = ?2 AND title_hash IS NOT ?1",
+                (hash, id),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// A live row of the same type whose title hashes like `title`, other than `id`:
+// (id, published_at)
+pub fn title_duplicate(
     conn: &Connection,
     id: &str,
     content_type: &str,
     title: &str,
-    url: &str,
-    description: Option<&str>,
-    thumbnail: Option<&str>,
-    published_at: Option<&str>,
-    fetched_at: &str,
-) -> Result<bool> {
-    let affected = conn.execute(
+) -> Result<Option<(String, Option<String>)>> {
+    let Some(hash) = content_title_hash(content_type, title) else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
         "
-        INSERT OR IGNORE INTO contents
-        (id, type, title, url, description, thumbnail, published_at, fetched_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        SELECT id, published_at FROM contents
+        WHERE type = ?1 AND title_hash = ?2 AND id != ?3 AND deleted_at IS NULL
+        LIMIT 1
         ",
-        params![
-            id,
-            content_type,
-            title,
-            url,
-            description,
-            thumbnail,
-            published_at,
-            fetched_at
-        ],
     )?;
+    let mut rows = stmt.query((content_type, hash, canonical_url(id)))?;
 
-    Ok(affected > 0)
+    Ok(match rows.next()? {
+        Some(row) => Some((row.get(0)?, row.get(1)?)),
+        None => None,
+    })
 }
 
-pub fn register_error(conn: &Connection, site: &str, message: &str, retry_days: i64) -> Result<()> {
-    let now = Utc::now();
-    let retry_after = now + Duration::days(retry_days);
+// Hide a row that an earlier copy of the same article, `by`, replaces
+pub fn supersede(conn: &Connection, id: &str, by: &str) -> Result<()> {
+    conn.execute(
+        "
+        UPDATE contents SET deleted_at = ?1, superseded_by = ?3
+        WHERE id = ?2 AND deleted_at IS NULL
+        ",
+        (Utc::now().to_rfc3339(), id, canonical_url(by)),
+    )?;
+    Ok(())
+}
+
+// A copy of a dead or superseded row's page elsewhere (e.g. Wayback), exported with it
+pub fn set_archived_url(conn: &Connection, id: &str, archived_url: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET archived_url = ?2 WHERE id = ?1",
+        (canonical_url(id), archived_url),
+    )?;
+    Ok(())
+}
+
+// First-seen time of a live stored row
+pub fn live_fetched_at(conn: &Connection, id: &str) -> Result<Option<String>> {
+    let mut stmt =
+        conn.prepare("SELECT fetched_at FROM contents WHERE id = ?1 AND deleted_at IS NULL")?;
+    let mut rows = stmt.query([canonical_url(id)])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn content_exists(conn: &Connection, id: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM contents WHERE id = ?1)",
+        [canonical_url(id)],
+        |row| row.get(0),
+    )?)
+}
 
+// A stored row whose page now lands on the row `to`: it is superseded by that row and
+// its id stays an alias of it in the export
+pub fn record_move(conn: &Connection, from: &str, to: &str) -> Result<()> {
     conn.execute(
         "
-        INSERT INTO error_sites (site, last_error_at, retry_after, error_message)
-        VALUES (?1, ?2, ?3, ?4)
-        ON CONFLICT(site) DO UPDATE SET
-            last_error_at = excluded.last_error_at,
-            retry_after = excluded.retry_after,
-            error_message = excluded.error_message
+        UPDATE contents SET redirected_to = ?2, superseded_by = ?2,
+            deleted_at = COALESCE(deleted_at, ?3)
+        WHERE id = ?1
         ",
-        rusqlite::params![site, now.to_rfc3339(), retry_after.to_rfc3339(), message],
+        (
+            canonical_url(from),
+            canonical_url(to),
+            Utc::now().to_rfc3339(),
+        ),
+    )?;
+    Ok(())
+}
+
+// Content id -> ids of moved rows that now stand for it, following chains of moves
+pub fn aliases(conn: &Connection) -> Result<HashMap<String, Vec<String>>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT id, redirected_to FROM contents
+        WHERE redirected_to IS NOT NULL AND deleted_at IS NOT NULL
+        ORDER BY id
+        ",
+    )?;
+    let moves = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    let targets: HashMap<&str, &str> = moves
+        .iter()
+        .map(|(from, to)| (from.as_str(), to.as_str()))
+        .collect();
+
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    for (from, to) in &moves {
+        let mut target = to.as_str();
+        let mut seen = HashSet::from([from.as_str()]);
+        while let Some(next) = targets.get(target) {
+            if !seen.insert(target) {
+                break;
+            }
+            target = next;
+        }
+        if target != from {
+            aliases
+                .entry(target.to_string())
+                .or_default()
+                .push(from.clone());
+        }
+    }
+
+    Ok(aliases)
+}
+
+fn backfill_fingerprints(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, description FROM contents WHERE fingerprint IS NULL AND description IS NOT NULL",
     )?;
 
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (id, title, description) = row?;
+        if let Some(fp) = fingerprint(&title, description.as_deref()) {
+            conn.execute(
+                "UPDATE contents SET fingerprint = ?1 WHERE id = ?2",
+                (fp, id),
+            )?;
+        }
+    }
+
     Ok(())
 }
 
-pub fn mark_done(conn: &Connection, url: &str) -> Result<()> {
+// Configured sources and their per-source crawl state
+pub fn init_sources_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS sources (
+            name TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            category TEXT,
+            https_checked_at TEXT,
+            https_ok INTEGER,
+            https_note TEXT
+        );
+        ",
+    )?;
+    add_column_if_missing(conn, "sources", "category", "TEXT")?;
+    add_column_if_missing(conn, "sources", "sitemap_cursor", "INTEGER")?;
+    add_column_if_missing(conn, "sources", "sitemap_signature", "TEXT")?;
+    add_column_if_missing(conn, "sources", "rate_title", "REAL")?;
+    add_column_if_missing(conn, "sources", "rate_description", "REAL")?;
+    add_column_if_missing(conn, "sources", "rate_date", "REAL")?;
+    add_column_if_missing(conn, "sources", "rate_thumbnail", "REAL")?;
+    add_column_if_missing(conn, "sources", "html_cursor", "INTEGER")?;
+    add_column_if_missing(conn, "sources", "https_status", "TEXT")?;
+
+    Ok(())
+}
+
+pub fn register_source(
+    conn: &Connection,
+    name: &str,
+    url: &str,
+    category: Option<&str>,
+) -> Result<()> {
     conn.execute(
         "
-        UPDATE crawl_queue
-        SET status = 'done',
-            fetched_at = datetime('now')
-        WHERE url = ?1
+        INSERT INTO sources (name, url, category)
+        VALUES (?1, ?2, ?3)
+        ON CONFLICT(name) DO UPDATE SET
+            url = excluded.url,
+            category = excluded.category
         ",
-        [url],
+        params![name, url, category],
     )?;
 
     Ok(())
 }
 
-pub fn next_pending(conn: &Connection, limit: usize) -> Result<Vec<String>> {
+// Fractions of newly inserted pages with a real title / description / date / thumbnail
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionRates {
+    pub title: f64,
+    pub description: f64,
+    pub date: f64,
+    pub thumbnail: f64,
+}
+
+// Trailing average of a source's extraction rates, None before its first measured run
+pub fn extraction_rates(conn: &Connection, name: &str) -> Result<Option<ExtractionRates>> {
     let mut stmt = conn.prepare(
         "
-        SELECT url FROM crawl_queue
-        WHERE status = 'pending'
-        AND (next_retry_at IS NULL OR next_retry_at <= datetime('now'))
-        LIMIT ?1
+        SELECT rate_title, rate_description, rate_date, rate_thumbnail
+        FROM sources
+        WHERE name = ?1 AND rate_title IS NOT NULL
         ",
     )?;
 
-    let rows = stmt.query_map([limit as i64], |row| row.get::<_, String>(0))?;
+    let mut rows = stmt.query([name])?;
 
-    let mut urls = Vec::new();
-    for url in rows {
-        urls.push(url?);
+    if let Some(row) = rows.next()? {
+        return Ok(Some(ExtractionRates {
+            title: row.get(0)?,
+            description: row.get(1)?,
+            date: row.get(2)?,
+            thumbnail: row.get(3)?,
+        }));
     }
 
-    Ok(urls)
+    Ok(None)
 }
 
-pub fn enqueue(conn: &Connection, url: &str, parent: Option<&str>) -> Result<bool> {
-    let rows = conn.execute(
-        "INSERT OR IGNORE INTO crawl_queue
-         (url, parent_url, status, discovered_at)
-         VALUES (?1, ?2, 'pending', datetime('now'))",
-        (url, parent),
+pub fn set_extraction_rates(conn: &Connection, name: &str, rates: &ExtractionRates) -> Result<()> {
+    conn.execute(
+        "
+        UPDATE sources
+        SET rate_title = ?1, rate_description = ?2, rate_date = ?3, rate_thumbnail = ?4
+        WHERE name = ?5
+        ",
+        params![
+            rates.title,
+            rates.description,
+            rates.date,
+            rates.thumbnail,
+            name
+        ],
     )?;
 
-    Ok(rows > 0) // true if newly inserted
+    Ok(())
 }
 
-// Fetch all contents for JSON export
-pub fn fetch_all(conn: &Connection) -> Result<Vec<Content>> {
+// Where the previous run stopped in the sitemap, with the sitemap's signature then
+pub fn sitemap_cursor(conn: &Connection, name: &str) -> Result<Option<(usize, String)>> {
     let mut stmt = conn.prepare(
         "
-        SELECT id, type, title, url, description, thumbnail, published_at
-        FROM contents
-        ORDER BY published_at DESC
+        SELECT sitemap_cursor, sitemap_signature
+        FROM sources
+        WHERE name = ?1 AND sitemap_cursor IS NOT NULL AND sitemap_signature IS NOT NULL
         ",
     )?;
 
-    let rows = stmt.query_map([], |row| {
-        Ok(Content {
-            id: row.get(0)?,
-            content_type: row.get(1)?,
-            title: row.get(2)?,
-            url: row.get(3)?,
-            description: row.get(4)?,
-            thumbnail: row.get(5)?,
-            published_at: row.get(6)?,
-        })
-    })?;
+    let mut rows = stmt.query([name])?;
 
-    let mut results = Vec::new();
-    for item in rows {
-        results.push(item?);
+    if let Some(row) = rows.next()? {
+        let cursor: i64 = row.get(0)?;
+        return Ok(Some((cursor as usize, row.get(1)?)));
     }
 
-    Ok(results)
+    Ok(None)
 }
 
-pub fn should_skip(conn: &Connection, site: &str) -> Result<bool> {
-    let mut stmt = conn.prepare("SELECT retry_after FROM error_sites WHERE site = ?1")?;
+pub fn set_sitemap_cursor(
+    conn: &Connection,
+    name: &str,
+    cursor: usize,
+    signature: &str,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE sources SET sitemap_cursor = ?1, sitemap_signature = ?2 WHERE name = ?3",
+        params![cursor as i64, signature, name],
+    )?;
 
-    let mut rows = stmt.query([site])?;
+    Ok(())
+}
 
-    if let Some(row) = rows.next()? {
-        let retry_after: String = row.get(0)?;
-        let retry_time = DateTime::parse_from_rfc3339(&retry_after)?.with_timezone(&Utc);
+// Queue rowid of the last page crawl_html processed; 0 when not mid-crawl
+pub fn html_cursor(conn: &Connection, name: &str) -> Result<i64> {
+    let cursor = conn
+        .query_row(
+            "SELECT html_cursor FROM sources WHERE name = ?1",
+            [name],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .unwrap_or(None);
 
-        if Utc::now() < retry_time {
-            return Ok(true);
-        }
-    }
+    Ok(cursor.unwrap_or(0))
+}
+
+pub fn set_html_cursor(conn: &Connection, name: &str, cursor: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE sources SET html_cursor = ?1 WHERE name = ?2",
+        params![cursor, name],
+    )?;
+
+    Ok(())
+}
+
+// Result of the last https probe for an http-configured source
+#[derive(Debug)]
+pub struct HttpsProbe {
+    pub checked_at: DateTime<Utc>,
+    pub ok: bool,
+    // "upgradeable" (crawled over https), "https_broken" (https answers, but with a
+    // TLS or HTTP error or another site) or "http_only" (nothing listens for https)
+    pub status: String,
+    pub note: Option<String>,
+}
+
+const HTTPS_PROBE_COLUMNS: &str = "https_checked_at, https_ok, https_status, https_note";
+
+fn https_probe_from_row(row: &rusqlite::Row, offset: usize) -> Result<HttpsProbe> {
+    let checked_at: String = row.get(offset)?;
+    let ok = row.get::<_, Option<bool>>(offset + 1)?.unwrap_or(false);
+    // Probes recorded before the status column only tell ok from not ok
+    let status = row
+        .get::<_, Option<String>>(offset + 2)?
+        .unwrap_or_else(|| if ok { "upgradeable" } else { "https_broken" }.to_string());
+
+    Ok(HttpsProbe {
+        checked_at: DateTime::parse_from_rfc3339(&checked_at)?.with_timezone(&Utc),
+        ok,
+        status,
+        note: row.get(offset + 3)?,
+    })
+}
+
+pub fn https_probe(conn: &Connection, name: &str) -> Result<Option<HttpsProbe>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM sources WHERE name = ?1 AND https_checked_at IS NOT NULL",
+        HTTPS_PROBE_COLUMNS
+    ))?;
+
+    let mut rows = stmt.query([name])?;
+
+    if let Some(row) = rows.next()? {
+        return Ok(Some(https_probe_from_row(row, 0)?));
+    }
+
+    Ok(None)
+}
+
+// Every probed source's last result, by name
+pub fn https_probes(conn: &Connection) -> Result<Vec<(String, HttpsProbe)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name, {} FROM sources WHERE https_checked_at IS NOT NULL ORDER BY name",
+        HTTPS_PROBE_COLUMNS
+    ))?;
+
+    let mut rows = stmt.query([])?;
+    let mut probes = Vec::new();
+    while let Some(row) = rows.next()? {
+        probes.push((row.get(0)?, https_probe_from_row(row, 1)?));
+    }
+
+    Ok(probes)
+}
+
+// `status` as in HttpsProbe; only "upgradeable" switches the source to https
+pub fn record_https_probe(
+    conn: &Connection,
+    name: &str,
+    url: &str,
+    status: &str,
+    note: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO sources (name, url, https_checked_at, https_ok, https_status, https_note)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(name) DO UPDATE SET
+            url = excluded.url,
+            https_checked_at = excluded.https_checked_at,
+            https_ok = excluded.https_ok,
+            https_status = excluded.https_status,
+            https_note = excluded.https_note
+        ",
+        params![
+            name,
+            url,
+            Utc::now().to_rfc3339(),
+            status == "upgradeable",
+            status,
+            note
+        ],
+    )?;
+
+    Ok(())
+}
+
+// One row to store; ids and urls are canonicalized on write
+#[derive(Debug, Clone, Serialize)]
+pub struct NewContent<'a> {
+    pub id: &'a str,
+    pub content_type: &'a str,
+    pub title: &'a str,
+    pub url: &'a str,
+    pub description: Option<&'a str>,
+    pub thumbnail: Option<&'a str>,
+    pub published_at: Option<&'a str>,
+    pub fetched_at: &'a str,
+    pub source: Option<&'a str>,
+    pub discovered_from: Option<&'a str>,
+}
+
+// Returns true if inserted, false if already existed
+pub fn insert(conn: &Connection, content: &NewContent) -> Result<bool> {
+    let affected = conn.execute(
+        "
+        INSERT OR IGNORE INTO contents
+        (id, type, title, url, description, thumbnail, published_at, fetched_at, source,
+         fingerprint, discovered_from, title_hash)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+        ",
+        params![
+            canonical_url(content.id),
+            content.content_type,
+            content.title,
+            canonical_url(content.url),
+            content.description,
+            content.thumbnail,
+            content.published_at,
+            content.fetched_at,
+            content.source,
+            fingerprint(content.title, content.description),
+            content.discovered_from,
+            content_title_hash(content.content_type, content.title)
+        ],
+    )?;
+
+    Ok(affected > 0)
+}
+
+// Insert, or overwrite the extracted fields of the stored row (refresh / recrawl).
+// On conflict fetched_at stays the first-seen time the export and prune go by,
+// refreshed_at records this fetch, and a missing published_at keeps the stored one.
+// A changed row first gets its stored fields kept as a revision (see record_revision).
+// Returns true if inserted, false if an existing row was updated.
+pub fn upsert(conn: &Connection, content: &NewContent, max_revisions: usize) -> Result<bool> {
+    record_revision(conn, content, max_revisions)?;
+
+    let inserted = conn.query_row(
+        "
+        INSERT INTO contents
+        (id, type, title, url, description, thumbnail, published_at, fetched_at, source,
+         fingerprint, discovered_from, title_hash)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+        ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            description = excluded.description,
+            thumbnail = excluded.thumbnail,
+            published_at = COALESCE(excluded.published_at, published_at),
+            refreshed_at = excluded.fetched_at,
+            fingerprint = excluded.fingerprint,
+            title_hash = CASE WHEN type = 'blog' THEN ?13 ELSE title_hash END
+        RETURNING refreshed_at IS NULL
+        ",
+        params![
+            canonical_url(content.id),
+            content.content_type,
+            content.title,
+            canonical_url(content.url),
+            content.description,
+            content.thumbnail,
+            content.published_at,
+            content.fetched_at,
+            content.source,
+            fingerprint(content.title, content.description),
+            content.discovered_from,
+            content_title_hash(content.content_type, content.title),
+            title_hash(content.title)
+        ],
+        |row| row.get(0),
+    )?;
+
+    Ok(inserted)
+}
+
+// Keep the stored title, description, thumbnail and published_at of the row `content`
+// is about to overwrite as its next revision, when any of them changes. Only the
+// newest max_revisions revisions of a row are kept; 0 keeps none.
+fn record_revision(conn: &Connection, content: &NewContent, max_revisions: usize) -> Result<()> {
+    if max_revisions == 0 {
+        return Ok(());
+    }
+
+    let id = canonical_url(content.id);
+    let mut stmt = conn.prepare(
+        "SELECT title, description, thumbnail, published_at FROM contents WHERE id = ?1",
+    )?;
+    let mut rows = stmt.query([&id])?;
+    let Some(row) = rows.next()? else {
+        return Ok(());
+    };
+    let title: String = row.get(0)?;
+    let description: Option<String> = row.get(1)?;
+    let thumbnail: Option<String> = row.get(2)?;
+    let published_at: Option<String> = row.get(3)?;
+
+    // The upsert keeps the stored published_at when the page no longer gives one
+    let changed = title != content.title
+        || description.as_deref() != content.description
+        || thumbnail.as_deref() != content.thumbnail
+        || content
+            .published_at
+            .is_some_and(|date| published_at.as_deref() != Some(date));
+    if !changed {
+        return Ok(());
+    }
+
+    conn.execute(
+        "
+        INSERT INTO content_revisions
+        (content_id, revision_no, title, description, thumbnail, published_at, captured_at)
+        SELECT ?1, COALESCE(MAX(revision_no), 0) + 1, ?2, ?3, ?4, ?5, ?6
+        FROM content_revisions WHERE content_id = ?1
+        ",
+        params![
+            id,
+            title,
+            description,
+            thumbnail,
+            published_at,
+            content.fetched_at
+        ],
+    )?;
+    conn.execute(
+        "
+        DELETE FROM content_revisions
+        WHERE content_id = ?1
+        AND revision_no <= (SELECT MAX(revision_no) FROM content_revisions WHERE content_id = ?1) - ?2
+        ",
+        params![id, max_revisions as i64],
+    )?;
+
+    Ok(())
+}
+
+// One earlier version of a contents row
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub revision_no: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    pub published_at: Option<String>,
+    pub captured_at: String,
+}
+
+// The kept revisions of a row, oldest first
+pub fn revisions(conn: &Connection, content_id: &str) -> Result<Vec<Revision>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT revision_no, title, description, thumbnail, published_at, captured_at
+        FROM content_revisions
+        WHERE content_id = ?1
+        ORDER BY revision_no
+        ",
+    )?;
+
+    let rows = stmt.query_map([canonical_url(content_id)], |row| {
+        Ok(Revision {
+            revision_no: row.get(0)?,
+            title: row.get(1)?,
+            description: row.get(2)?,
+            thumbnail: row.get(3)?,
+            published_at: row.get(4)?,
+            captured_at: row.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+// Content id -> the title of its newest revision that differs from the current title
+pub fn previous_titles(conn: &Connection) -> Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT r.content_id, r.title
+        FROM content_revisions r
+        JOIN contents c ON c.id = r.content_id
+        WHERE r.revision_no = (
+            SELECT MAX(revision_no) FROM content_revisions
+            WHERE content_id = r.content_id AND title != c.title
+        )
+        ",
+    )?;
+
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+}
+
+// A refresh fetch that left the row as it was
+pub fn mark_refreshed(conn: &Connection, id: &str, refreshed_at: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET refreshed_at = ?2 WHERE id = ?1",
+        (canonical_url(id), refreshed_at),
+    )?;
+
+    Ok(())
+}
+
+// Live blog rows of a source first stored (or last refreshed) before `before`,
+// least recently fetched first
+pub fn stale_contents(
+    conn: &Connection,
+    source: &str,
+    before: &str,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT id FROM contents
+        WHERE source = ?1 AND type = 'blog' AND deleted_at IS NULL
+        AND COALESCE(refreshed_at, fetched_at) < ?2
+        ORDER BY COALESCE(refreshed_at, fetched_at)
+        LIMIT ?3
+        ",
+    )?;
+
+    let rows = stmt.query_map(params![source, before, limit as i64], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<String>>>()?)
+}
+
+// Content-quality signals measured on the article body
+pub fn set_content_stats(
+    conn: &Connection,
+    id: &str,
+    text_length: i64,
+    image_count: i64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET text_length = ?1, image_count = ?2 WHERE id = ?3",
+        params![text_length, image_count, id],
+    )?;
+
+    Ok(())
+}
+
+// Display name the page gives its own site (og:site_name or a title suffix)
+pub fn set_site_name(conn: &Connection, id: &str, site_name: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET site_name = ?1 WHERE id = ?2",
+        (site_name, id),
+    )?;
+
+    Ok(())
+}
+
+// Consecutive 404/410 answers before a row is marked dead
+const DEAD_AFTER_FAILURES: i64 = 2;
+
+// Outcome of one check-links request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkCheck {
+    Ok,
+    // 404 / 410
+    Missing,
+    // Timeout, 5xx and the like: recorded as checked, status unchanged
+    Transient,
+}
+
+// Queue items for the webhook; ones queued before (sent or not) are left alone
+pub fn enqueue_notifications(conn: &Connection, ids: &[String], queued_at: &str) -> Result<()> {
+    for id in ids {
+        conn.execute(
+            "INSERT OR IGNORE INTO pending_notifications (content_id, queued_at) VALUES (?1, ?2)",
+            (id, queued_at),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Queued entries neither sent nor dropped, as (content id, queued_at), oldest first
+pub fn pending_notifications(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT content_id, queued_at FROM pending_notifications
+        WHERE sent_at IS NULL AND dropped_at IS NULL
+        ORDER BY queued_at, content_id
+        ",
+    )?;
+
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub fn mark_notified(conn: &Connection, ids: &[String], sent_at: &str) -> Result<()> {
+    for id in ids {
+        conn.execute(
+            "UPDATE pending_notifications SET sent_at = ?2 WHERE content_id = ?1",
+            (id, sent_at),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn drop_notifications(conn: &Connection, ids: &[String], dropped_at: &str) -> Result<()> {
+    for id in ids {
+        conn.execute(
+            "UPDATE pending_notifications SET dropped_at = ?2 WHERE content_id = ?1",
+            (id, dropped_at),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Distinct thumbnails of exportable rows never checked, or last checked before
+// `checked_before`
+pub fn thumbnails_to_check(conn: &Connection, checked_before: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT DISTINCT c.thumbnail
+        FROM contents c
+        LEFT JOIN thumbnail_checks t ON t.url = c.thumbnail
+        WHERE c.thumbnail IS NOT NULL AND c.deleted_at IS NULL
+        AND c.link_status IS NOT 'dead'
+        AND (t.url IS NULL OR t.checked_at < ?1)
+        ORDER BY c.thumbnail
+        ",
+    )?;
+
+    let rows = stmt.query_map([checked_before], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<String>>>()?)
+}
+
+pub fn record_thumbnail_check(
+    conn: &Connection,
+    url: &str,
+    ok: bool,
+    status: Option<u16>,
+    checked_at: &str,
+) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO thumbnail_checks (url, ok, status, checked_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(url) DO UPDATE SET
+            ok = excluded.ok,
+            status = excluded.status,
+            checked_at = excluded.checked_at
+        ",
+        params![url, ok, status, checked_at],
+    )?;
+
+    Ok(())
+}
+
+// Thumbnail URLs whose last check failed
+pub fn broken_thumbnails(conn: &Connection) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT url FROM thumbnail_checks WHERE ok = 0")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<HashSet<String>>>()?)
+}
+
+// Stored blog rows whose link was checked longest ago (never checked first).
+// Returns (id, url) pairs.
+pub fn link_check_batch(conn: &Connection, limit: usize) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT id, url FROM contents
+        WHERE type = 'blog' AND deleted_at IS NULL
+        ORDER BY last_checked_at IS NOT NULL, last_checked_at, fetched_at
+        LIMIT ?1
+        ",
+    )?;
+
+    let rows = stmt.query_map([limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    let mut batch = Vec::new();
+    for row in rows {
+        batch.push(row?);
+    }
+
+    Ok(batch)
+}
+
+// Record a check; returns the row's link_status afterwards
+pub fn record_link_check(
+    conn: &Connection,
+    id: &str,
+    check: LinkCheck,
+    checked_at: &str,
+) -> Result<Option<String>> {
+    let update = match check {
+        LinkCheck::Ok => "link_status = 'ok', link_failures = 0, dead_at = NULL".to_string(),
+        LinkCheck::Missing => format!(
+            "link_failures = link_failures + 1,
+             link_status = CASE WHEN link_failures + 1 >= {0} THEN 'dead' ELSE 'missing' END,
+             dead_at = CASE WHEN link_failures + 1 >= {0} THEN COALESCE(dead_at, ?2) END",
+            DEAD_AFTER_FAILURES
+        ),
+        LinkCheck::Transient => "link_failures = link_failures".to_string(),
+    };
+
+    let status = conn.query_row(
+        &format!(
+            "UPDATE contents SET last_checked_at = ?2, {} WHERE id = ?1 RETURNING link_status",
+            update
+        ),
+        params![id, checked_at],
+        |row| row.get(0),
+    )?;
+
+    Ok(status)
+}
+
+// Whether a row of `source` other than `id` was stored with this site name
+pub fn site_name_seen(conn: &Connection, source: &str, site_name: &str, id: &str) -> Result<bool> {
+    let seen = conn.query_row(
+        "SELECT EXISTS (
+             SELECT 1 FROM contents WHERE source = ?1 AND site_name = ?2 AND id != ?3
+         )",
+        params![source, site_name, canonical_url(id)],
+        |row| row.get(0),
+    )?;
+
+    Ok(seen)
+}
+
+// ETag / Last-Modified header values of an earlier response
+#[derive(Debug)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub fn http_validators(conn: &Connection, url: &str) -> Result<Option<Validators>> {
+    let mut stmt = conn.prepare("SELECT etag, last_modified FROM http_cache WHERE url = ?1")?;
+    let mut rows = stmt.query([canonical_url(url)])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(Validators {
+            etag: row.get(0)?,
+            last_modified: row.get(1)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+// Replace a URL's validators; None forgets them, so the next request is unconditional
+pub fn store_validators(
+    conn: &Connection,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<()> {
+    let url = canonical_url(url);
+
+    let Some(validators) = validators else {
+        conn.execute("DELETE FROM http_cache WHERE url = ?1", [&url])?;
+        return Ok(());
+    };
+
+    conn.execute(
+        "
+        INSERT INTO http_cache (url, etag, last_modified, stored_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(url) DO UPDATE SET
+            etag = excluded.etag,
+            last_modified = excluded.last_modified,
+            stored_at = excluded.stored_at
+        ",
+        (
+            &url,
+            &validators.etag,
+            &validators.last_modified,
+            Utc::now().to_rfc3339(),
+        ),
+    )?;
+
+    Ok(())
+}
+
+pub fn record_redirect(
+    conn: &Connection,
+    url: &str,
+    redirected_to: &str,
+    kind: &str,
+) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO redirects (url, redirected_to, kind, recorded_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(url) DO UPDATE SET
+            redirected_to = excluded.redirected_to,
+            kind = excluded.kind,
+            recorded_at = excluded.recorded_at
+        ",
+        (url, redirected_to, kind, Utc::now().to_rfc3339()),
+    )?;
+
+    Ok(())
+}
+
+// Where a recorded redirect (e.g. a feed's shortener link) leads
+pub fn redirect_target(conn: &Connection, url: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT redirected_to FROM redirects WHERE url = ?1")?;
+    let mut rows = stmt.query([url])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+// None clears an expiry the page no longer declares
+// Only for rows whose page had no date of its own
+pub fn fill_published_at(conn: &Connection, id: &str, published_at: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET published_at = ?1 WHERE id = ?2 AND published_at IS NULL",
+        params![published_at, canonical_url(id)],
+    )?;
+
+    Ok(())
+}
+
+pub fn set_expires_at(conn: &Connection, id: &str, expires_at: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET expires_at = ?1 WHERE id = ?2",
+        params![expires_at, id],
+    )?;
+
+    Ok(())
+}
+
+pub fn set_excerpt(conn: &Connection, id: &str, excerpt: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE contents SET excerpt = ?1 WHERE id = ?2",
+        params![excerpt, id],
+    )?;
+
+    Ok(())
+}
+
+// Replace the stored main text of a content row
+// The row's text goes into the search index here rather than from a trigger on
+// content_text: the stored copy is zstd-compressed, which SQL can't unpack
+pub fn set_content_text(conn: &Connection, id: &str, text: &str) -> Result<()> {
+    let compressed = zstd::encode_all(text.as_bytes(), 3)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO content_text (content_id, text, chars) VALUES (?1, ?2, ?3)",
+        params![id, compressed, text.chars().count() as i64],
+    )?;
+    set_search_body(conn, id, text)?;
+
+    Ok(())
+}
+
+fn set_search_body(conn: &Connection, id: &str, text: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contents_fts SET body = ?2 WHERE id = ?1",
+        (id, text),
+    )?;
+    Ok(())
+}
+
+pub fn record_embed(conn: &Connection, content_id: &str, video_id: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO content_embeds (content_id, video_id) VALUES (?1, ?2)",
+        (content_id, video_id),
+    )?;
+
+    Ok(())
+}
+
+pub fn record_tags(
+    conn: &Connection,
+    content_id: &str,
+    tag_type: &str,
+    tags: &[String],
+) -> Result<()> {
+    for tag in tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (content_id, tag, tag_type) VALUES (?1, ?2, ?3)",
+            (content_id, tag, tag_type),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Tags of every content keyed by content id, in insertion order
+pub fn tags(conn: &Connection) -> Result<HashMap<String, Vec<String>>> {
+    let mut stmt = conn.prepare("SELECT content_id, tag FROM tags ORDER BY content_id, rowid")?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (content_id, tag) = row?;
+        let values = tags.entry(content_id).or_default();
+        if !values.contains(&tag) {
+            values.push(tag);
+        }
+    }
+
+    Ok(tags)
+}
+
+// Relation pass: pair blog articles with the stored videos they embed, then redo the
+// tag neighbours (see link_tag_neighbours). Returns the number of newly recorded
+// embed relations.
+pub fn link_related(conn: &Connection, related_tags: &RelatedTagsConfig) -> Result<usize> {
+    let added = conn.execute(
+        "
+        INSERT OR IGNORE INTO related (content_id, related_id, relation_kind)
+        SELECT e.content_id, v.id, 'embed'
+        FROM content_embeds e
+        JOIN contents v ON v.id = e.video_id AND v.type = 'youtube'
+        UNION ALL
+        SELECT v.id, e.content_id, 'embedded_in'
+        FROM content_embeds e
+        JOIN contents v ON v.id = e.video_id AND v.type = 'youtube'
+        ",
+        [],
+    )?;
+
+    // Switching the pass off drops the neighbours it recorded
+    conn.execute("DELETE FROM related WHERE relation_kind = 'tag'", [])?;
+    if related_tags.enabled && related_tags.max_items > 0 {
+        let linked = link_tag_neighbours(conn, related_tags.max_items)?;
+        debug!("Related by tags: {} neighbours", linked);
+    }
+
+    Ok(added)
+}
+
+// Tags on more live items than this say nothing about which of them belong together
+const MAX_TAG_ITEMS: i64 = 200;
+// A shared road-number tag (国道152号) counts as much as this many genre tags
+const ROAD_TAG_WEIGHT: f64 = 4.0;
+
+// Up to max_items neighbours of every live item among the items sharing its tags,
+// ranked in `related`. A shared tag adds ROAD_TAG_WEIGHT or 1 divided by the number
+// of items carrying it, so rarer tags make closer neighbours; the newer item wins a
+// tie. Items only meet the items of their own tags and tags on more than
+// MAX_TAG_ITEMS items are left out: the work grows with the number of tagged items,
+// not with their pairs.
+fn link_tag_neighbours(conn: &Connection, max_items: usize) -> Result<usize> {
+    let mut stmt = conn.prepare(
+        "
+        WITH shared AS (
+            SELECT t.tag
+            FROM tags t
+            JOIN contents c ON c.id = t.content_id AND c.deleted_at IS NULL
+            GROUP BY t.tag
+            HAVING COUNT(DISTINCT t.content_id) BETWEEN 2 AND ?1
+        )
+        SELECT DISTINCT t.tag, t.content_id, COALESCE(c.published_at, c.fetched_at)
+        FROM shared s
+        JOIN tags t ON t.tag = s.tag
+        JOIN contents c ON c.id = t.content_id AND c.deleted_at IS NULL
+        ORDER BY t.tag, t.content_id
+        ",
+    )?;
+    let rows = stmt.query_map([MAX_TAG_ITEMS], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+
+    let mut carriers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut dated: HashMap<String, Option<DateTime<Utc>>> = HashMap::new();
+    for row in rows {
+        let (tag, content_id, date) = row?;
+        let date = DateTime::parse_from_rfc3339(&date)
+            .ok()
+            .map(|d| d.with_timezone(&Utc));
+        dated.insert(content_id.clone(), date);
+        carriers.entry(tag).or_default().push(content_id);
+    }
+
+    let mut item_tags: BTreeMap<&str, Vec<(&[String], f64)>> = BTreeMap::new();
+    for (tag, items) in &carriers {
+        let weight = if classify::is_road_tag(tag) {
+            ROAD_TAG_WEIGHT
+        } else {
+            1.0
+        };
+        for item in items {
+            item_tags
+                .entry(item)
+                .or_default()
+                .push((items, weight / items.len() as f64));
+        }
+    }
+
+    let mut insert = conn.prepare(
+        "
+        INSERT OR IGNORE INTO related (content_id, related_id, relation_kind, rank)
+        VALUES (?1, ?2, 'tag', ?3)
+        ",
+    )?;
+    let mut linked = 0;
+    for (item, tags) in item_tags {
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for (items, weight) in tags {
+            for other in items.iter().filter(|other| *other != item) {
+                *scores.entry(other).or_default() += weight;
+            }
+        }
+
+        let mut neighbours: Vec<(&str, f64)> = scores.into_iter().collect();
+        neighbours.sort_by(|(a, a_score), (b, b_score)| {
+            b_score
+                .total_cmp(a_score)
+                .then_with(|| dated[*b].cmp(&dated[*a]))
+                .then_with(|| a.cmp(b))
+        });
+        for (rank, (neighbour, _)) in neighbours.into_iter().take(max_items).enumerate() {
+            linked += insert.execute(params![item, neighbour, rank as i64])?;
+        }
+    }
+
+    Ok(linked)
+}
+
+// Related content ids keyed by content id: explicit relations, then the tag
+// neighbours closest first
+pub fn related_ids(conn: &Connection) -> Result<HashMap<String, Vec<String>>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT content_id, related_id
+        FROM related
+        ORDER BY content_id, rank IS NOT NULL, rank, related_id
+        ",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut related: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (content_id, related_id) = row?;
+        let ids = related.entry(content_id).or_default();
+        if !ids.contains(&related_id) {
+            ids.push(related_id);
+        }
+    }
+
+    Ok(related)
+}
+
+// Per-run in-memory view of the existence and error-skip lookups issued from the
+// crawl loops, loaded once at run start and updated as the run writes rows.
+// The database stays authoritative; nothing here outlives the run. The default is
+// empty, for work without a database (crawl_single).
+#[derive(Default)]
+pub struct RunCache {
+    // Content id -> fetched_at (MIN_UTC when unparsable)
+    contents: RefCell<HashMap<String, DateTime<Utc>>>,
+    redirects: RefCell<HashSet<String>>,
+    // error_sites.site -> retry_after
+    errors: RefCell<HashMap<String, DateTime<Utc>>>,
+}
+
+impl RunCache {
+    pub fn load(conn: &Connection) -> Result<Self> {
+        let parse = |text: String| {
+            DateTime::parse_from_rfc3339(&text)
+                .map(|d| d.with_timezone(&Utc))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC)
+        };
+
+        // The latest fetch, so a refetched row isn't taken for changed again
+        let mut stmt =
+            conn.prepare("SELECT id, COALESCE(refreshed_at, fetched_at) FROM contents")?;
+        let contents = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|row| row.map(|(id, fetched_at)| (id, parse(fetched_at))))
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+
+        let mut stmt = conn.prepare("SELECT url FROM redirects")?;
+        let redirects = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<HashSet<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT site, retry_after FROM error_sites")?;
+        let errors = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|row| row.map(|(site, retry_after)| (site, parse(retry_after))))
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+
+        Ok(RunCache {
+            contents: RefCell::new(contents),
+            redirects: RefCell::new(redirects),
+            errors: RefCell::new(errors),
+        })
+    }
+
+    // When a stored content row was last fetched, or None if the id is unknown
+    pub fn fetched_at(&self, id: &str) -> Option<DateTime<Utc>> {
+        self.contents.borrow().get(&canonical_url(id)).copied()
+    }
+
+    // URLs with neither a contents row, an error record nor a recorded redirect
+    pub fn filter_unknown(&self, urls: &[String]) -> HashSet<String> {
+        let contents = self.contents.borrow();
+        let redirects = self.redirects.borrow();
+        let errors = self.errors.borrow();
+
+        urls.iter()
+            .filter(|url| {
+                let key = canonical_url(url);
+                !contents.contains_key(&key)
+                    && !redirects.contains(&key)
+                    && !errors.contains_key(&key)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn should_skip(&self, site: &str) -> bool {
+        self.retry_after(site).is_some()
+    }
+
+    // End of the error backoff for a URL or domain key, while it is still running
+    pub fn retry_after(&self, site: &str) -> Option<DateTime<Utc>> {
+        self.errors
+            .borrow()
+            .get(site)
+            .copied()
+            .filter(|retry_after| Utc::now() < *retry_after)
+    }
+
+    pub fn is_redirect(&self, url: &str) -> bool {
+        self.redirects.borrow().contains(&canonical_url(url))
+    }
+
+    // Call after a successful db::insert
+    pub fn record_content(&self, id: &str, fetched_at: &str) {
+        let fetched_at = DateTime::parse_from_rfc3339(fetched_at)
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        self.contents
+            .borrow_mut()
+            .insert(canonical_url(id), fetched_at);
+    }
+
+    pub fn record_redirect(&self, url: &str) {
+        self.redirects.borrow_mut().insert(canonical_url(url));
+    }
+
+    // db::register_error plus the cached skip
+    pub fn register_error(
+        &self,
+        conn: &Connection,
+        site: &str,
+        message: &str,
+        retry_days: i64,
+    ) -> Result<()> {
+        register_error(conn, site, message, retry_days)?;
+        self.errors
+            .borrow_mut()
+            .insert(site.to_string(), Utc::now() + Duration::days(retry_days));
+
+        Ok(())
+    }
+}
+
+pub fn register_error(conn: &Connection, site: &str, message: &str, retry_days: i64) -> Result<()> {
+    let now = Utc::now();
+    let retry_after = now + Duration::days(retry_days);
+
+    conn.execute(
+        "
+        INSERT INTO error_sites (site, last_error_at, retry_after, error_message)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(site) DO UPDATE SET
+            last_error_at = excluded.last_error_at,
+            retry_after = excluded.retry_after,
+            error_message = excluded.error_message
+        ",
+        rusqlite::params![site, now.to_rfc3339(), retry_after.to_rfc3339(), message],
+    )?;
+
+    Ok(())
+}
+
+pub fn mark_done(conn: &Connection, url: &str) -> Result<()> {
+    conn.execute(
+        "
+        UPDATE crawl_queue
+        SET status = 'done',
+            fetched_at = datetime('now')
+        WHERE url = ?1
+        ",
+        [url],
+    )?;
+    debug!("Queue done: {}", url);
+
+    Ok(())
+}
+
+// Wait before each retry of a failed queue URL; once these are used up the URL is
+// marked `error` and never picked again
+const RETRY_BACKOFF_HOURS: &[i64] = &[1, 6, 24, 72];
+
+// A failed fetch of a queued URL. Returns true when the URL gave up (status `error`).
+pub fn mark_retry(conn: &Connection, url: &str, error_message: &str) -> Result<bool> {
+    let retries: i64 = conn
+        .query_row(
+            "SELECT retry_count FROM crawl_queue WHERE url = ?1",
+            [url],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .map(|count| count.unwrap_or(0))
+        .unwrap_or(0);
+
+    let Some(hours) = RETRY_BACKOFF_HOURS.get(retries as usize) else {
+        conn.execute(
+            "
+            UPDATE crawl_queue
+            SET status = 'error', retry_count = COALESCE(retry_count, 0) + 1,
+                next_retry_at = NULL, last_error = ?2
+            WHERE url = ?1
+            ",
+            (url, error_message),
+        )?;
+        return Ok(true);
+    };
+
+    // Same format as datetime('now'), which next_pending compares against
+    conn.execute(
+        "
+        UPDATE crawl_queue
+        SET retry_count = COALESCE(retry_count, 0) + 1,
+            next_retry_at = datetime('now', ?2),
+            last_error = ?3
+        WHERE url = ?1
+        ",
+        (url, format!("+{} hours", hours), error_message),
+    )?;
 
     Ok(false)
 }
+
+// Disallowed by robots.txt: kept out of the pending set so it isn't retried every run
+pub fn mark_robots_blocked(conn: &Connection, url: &str, parent: Option<&str>) -> Result<()> {
+    let url = canonical_url(url);
+    conn.execute(
+        "
+        INSERT INTO crawl_queue (url, parent_url, status, discovered_at)
+        VALUES (?1, ?2, 'robots_blocked', datetime('now'))
+        ON CONFLICT(url) DO UPDATE SET status = 'robots_blocked'
+        WHERE status = 'pending'
+        ",
+        (&url, parent),
+    )?;
+
+    Ok(())
+}
+
+// Pending URLs starting with one of `scopes` (a site's origins), in discovery order after the
+// queue rowid `after`; URLs waiting out a retry backoff, or deeper than `max_depth`
+// (after the limit was lowered), are left alone. Returns (rowid, url, depth).
+pub fn next_pending(
+    conn: &Connection,
+    scopes: &[String],
+    max_depth: i64,
+    after: i64,
+    limit: usize,
+) -> Result<Vec<(i64, String, i64)>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT rowid, url, depth FROM crawl_queue
+        WHERE status = 'pending'
+        AND (next_retry_at IS NULL OR next_retry_at <= datetime('now'))
+        AND EXISTS (
+            SELECT 1 FROM json_each(?1) WHERE substr(url, 1, length(value)) = value
+        )
+        AND depth <= ?2
+        AND rowid > ?3
+        ORDER BY rowid
+        LIMIT ?4
+        ",
+    )?;
+
+    let scopes = serde_json::to_string(scopes)?;
+    let rows = stmt.query_map(params![scopes, max_depth, after, limit as i64], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+
+    let mut urls = Vec::new();
+    for url in rows {
+        urls.push(url?);
+    }
+
+    Ok(urls)
+}
+
+// The page a queued URL was found on; None for roots and unknown URLs
+pub fn queue_parent(conn: &Connection, url: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT parent_url FROM crawl_queue WHERE url = ?1")?;
+    let mut rows = stmt.query([url])?;
+
+    match rows.next()? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(None),
+    }
+}
+
+// (status, retry_count, next_retry_at) of a queued URL
+pub fn queue_entry(conn: &Connection, url: &str) -> Result<Option<(String, i64, Option<String>)>> {
+    let mut stmt =
+        conn.prepare("SELECT status, retry_count, next_retry_at FROM crawl_queue WHERE url = ?1")?;
+    let mut rows = stmt.query([url])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some((
+            row.get(0)?,
+            row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+            row.get(2)?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+pub fn enqueue(conn: &Connection, url: &str, parent: Option<&str>) -> Result<bool> {
+    let url = canonical_url(url);
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO crawl_queue
+         (url, parent_url, status, discovered_at, depth)
+         VALUES (?1, ?2, 'pending', datetime('now'),
+                 COALESCE((SELECT depth + 1 FROM crawl_queue WHERE url = ?3), 0))",
+        params![url, parent, parent.map(canonical_url)],
+    )?;
+    if rows > 0 {
+        debug!("Enqueued {}", url);
+    }
+
+    Ok(rows > 0) // true if newly inserted
+}
+
+// Columns read by content_from_row, for a `contents c LEFT JOIN sources s` query
+const CONTENT_COLUMNS: &str = "
+    c.id, c.type, c.title, c.url, c.description, c.thumbnail, c.published_at,
+    s.category, c.fingerprint, c.source, c.fetched_at, c.discovered_from,
+    c.text_length, c.image_count, c.site_name, c.expires_at, c.excerpt,
+    c.title_hash, (SELECT t.text FROM content_text t WHERE t.content_id = c.id),
+    COALESCE(c.dead_at, CASE WHEN c.superseded_by IS NOT NULL THEN c.deleted_at END),
+    c.archived_url, c.superseded_by, c.refreshed_at";
+
+fn content_from_row(row: &rusqlite::Row) -> rusqlite::Result<Content> {
+    Ok(Content {
+        id: row.get(0)?,
+        content_type: row.get(1)?,
+        title: row.get(2)?,
+        url: row.get(3)?,
+        description: row.get(4)?,
+        thumbnail: row.get(5)?,
+        published_at: row.get(6)?,
+        category: row.get(7)?,
+        fingerprint: row.get(8)?,
+        source: row.get(9)?,
+        fetched_at: row.get(10)?,
+        discovered_from: row.get(11)?,
+        text_length: row.get(12)?,
+        image_count: row.get(13)?,
+        site_name: row.get(14)?,
+        expires_at: row.get(15)?,
+        excerpt: row.get(16)?,
+        title_hash: row.get(17)?,
+        main_text: row
+            .get::<_, Option<Vec<u8>>>(18)?
+            .and_then(|compressed| zstd::decode_all(&compressed[..]).ok())
+            .and_then(|text| String::from_utf8(text).ok()),
+        dead_at: row.get(19)?,
+        archived_url: row.get(20)?,
+        superseded_by: row.get(21)?,
+        refreshed_at: row.get(22)?,
+    })
+}
+
+// Newest fetched_at in contents. fetched_at is RFC 3339 UTC throughout, so text
+// order is time order.
+pub fn latest_fetched_at(conn: &Connection) -> Result<Option<String>> {
+    Ok(conn.query_row("SELECT MAX(fetched_at) FROM contents", [], |row| row.get(0))?)
+}
+
+// fetched_at watermark of the last incremental export to `path`
+pub fn export_state(conn: &Connection, path: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT last_fetched_at FROM export_state WHERE path = ?1")?;
+    let mut rows = stmt.query([path])?;
+
+    if let Some(row) = rows.next()? {
+        return Ok(Some(row.get(0)?));
+    }
+
+    Ok(None)
+}
+
+pub fn set_export_state(conn: &Connection, path: &str, last_fetched_at: &str) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO export_state (path, last_fetched_at, exported_at)
+        VALUES (?1, ?2, ?3)
+        ON CONFLICT(path) DO UPDATE SET
+            last_fetched_at = excluded.last_fetched_at,
+            exported_at = excluded.exported_at
+        ",
+        params![path, last_fetched_at, Utc::now().to_rfc3339()],
+    )?;
+
+    Ok(())
+}
+
+// Fetch all contents for JSON export; rows check-links found dead only with include_dead
+// Rows left out of the live export for good: dead links and superseded rows (a later
+// copy, a moved permalink). Pruned and taken-down rows are not among them.
+pub fn fetch_archived(conn: &Connection) -> Result<Vec<Content>> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT {}
+        FROM contents c
+        LEFT JOIN sources s ON s.name = c.source
+        WHERE (c.deleted_at IS NULL AND c.link_status = 'dead')
+        OR c.superseded_by IS NOT NULL
+        ORDER BY c.published_at DESC
+        ",
+        CONTENT_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map([], content_from_row)?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub fn fetch_all(conn: &Connection, include_dead: bool) -> Result<Vec<Content>> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT {}
+        FROM contents c
+        LEFT JOIN sources s ON s.name = c.source
+        WHERE c.deleted_at IS NULL
+        AND (?1 OR c.link_status IS NOT 'dead')
+        ORDER BY c.published_at DESC
+        ",
+        CONTENT_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map([include_dead], content_from_row)?;
+
+    let mut results = Vec::new();
+    for item in rows {
+        results.push(item?);
+    }
+
+    Ok(results)
+}
+
+// Stored contents matching every whitespace-separated term of `query` in title,
+// description, excerpt or stored main text, best bm25 rank first. Terms shorter than
+// the trigram tokenizer's 3 characters are matched as plain substrings of title,
+// description and excerpt, unranked.
+pub fn search(conn: &Connection, query: &str, limit: usize) -> Result<Vec<Content>> {
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (indexed, short): (Vec<&str>, Vec<&str>) =
+        terms.iter().partition(|term| term.chars().count() >= 3);
+
+    // Each term quoted as a phrase, so FTS5 operators and punctuation are literal
+    let fts_query = indexed
+        .iter()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut sql = format!(
+        "SELECT {} FROM contents c LEFT JOIN sources s ON s.name = c.source",
+        CONTENT_COLUMNS
+    );
+    let mut conditions = vec!["c.deleted_at IS NULL".to_string()];
+    let mut values: Vec<String> = Vec::new();
+
+    if !indexed.is_empty() {
+        sql.push_str(" JOIN contents_fts f ON f.id = c.id");
+        values.push(fts_query);
+        conditions.push(format!("contents_fts MATCH ?{}", values.len()));
+    }
+    for term in short {
+        values.push(term.to_string());
+        conditions.push(format!(
+            "instr(c.title || ' ' || COALESCE(c.description, '') || ' ' || COALESCE(c.excerpt, ''), ?{}) > 0",
+            values.len()
+        ));
+    }
+
+    sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    if indexed.is_empty() {
+        sql.push_str(" ORDER BY c.published_at DESC");
+    } else {
+        sql.push_str(" ORDER BY bm25(contents_fts)");
+    }
+    sql.push_str(&format!(" LIMIT {}", limit));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), content_from_row)?;
+
+    let mut results = Vec::new();
+    for item in rows {
+        results.push(item?);
+    }
+
+    Ok(results)
+}
+
+// Data-quality counters collected at the end of a run
+#[derive(Debug, Serialize)]
+pub struct QualityReport {
+    pub duplicate_rows: i64,
+    pub placeholder_titles: i64,
+    pub missing_published_at: i64,
+    pub missing_thumbnail: i64,
+    pub invalid_urls: i64,
+    // Sources whose items inserted this run all share one title (broken selector)
+    pub identical_title_sources: Vec<String>,
+    // (source, site_name) where every stored page names the site differently from the config
+    pub renamed_sources: Vec<(String, String)>,
+}
+
+pub fn quality_report(conn: &Connection, since: &str) -> Result<QualityReport> {
+    let count = |sql: &str| -> Result<i64> { Ok(conn.query_row(sql, [], |row| row.get(0))?) };
+
+    let duplicate_rows = count(
+        "
+        SELECT COALESCE(SUM(n), 0) FROM (
+            SELECT COUNT(*) AS n FROM contents
+            WHERE fingerprint IS NOT NULL AND deleted_at IS NULL
+            GROUP BY fingerprint
+            HAVING COUNT(*) > 1
+        )
+        ",
+    )?;
+    let placeholder_titles = count(
+        "SELECT COUNT(*) FROM contents
+         WHERE deleted_at IS NULL AND (TRIM(title) = '' OR title = 'No Title')",
+    )?;
+    let missing_published_at =
+        count("SELECT COUNT(*) FROM contents WHERE deleted_at IS NULL AND published_at IS NULL")?;
+    let missing_thumbnail =
+        count("SELECT COUNT(*) FROM contents WHERE deleted_at IS NULL AND thumbnail IS NULL")?;
+
+    let mut stmt = conn.prepare("SELECT url FROM contents WHERE deleted_at IS NULL")?;
+    let invalid_urls = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|url| url.ok())
+        .filter(|url| url::Url::parse(url).is_err())
+        .count() as i64;
+
+    let mut stmt = conn.prepare(
+        "
+        SELECT source FROM contents
+        WHERE source IS NOT NULL AND fetched_at >= ?1
+        GROUP BY source
+        HAVING COUNT(*) > 1 AND COUNT(DISTINCT title) = 1
+        ORDER BY source
+        ",
+    )?;
+    let identical_title_sources = stmt
+        .query_map([since], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "
+        SELECT source, MAX(site_name) FROM contents
+        WHERE source IS NOT NULL AND site_name IS NOT NULL AND deleted_at IS NULL
+        GROUP BY source
+        HAVING COUNT(*) >= 3 AND COUNT(DISTINCT site_name) = 1 AND MAX(site_name) != source
+        ORDER BY source
+        ",
+    )?;
+    let renamed_sources = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(QualityReport {
+        duplicate_rows,
+        placeholder_titles,
+        missing_published_at,
+        missing_thumbnail,
+        invalid_urls,
+        identical_title_sources,
+        renamed_sources,
+    })
+}
+
+// Samples older than this are dropped when a new one is recorded
+const STATS_RETENTION_DAYS: i64 = 365;
+
+// Queue and contents counts at one point in time
+#[derive(Debug, Serialize)]
+pub struct StatsSample {
+    pub sampled_at: String,
+    pub queue_pending: i64,
+    pub queue_done: i64,
+    pub queue_error: i64,
+    pub contents_blog: i64,
+    pub contents_youtube: i64,
+    pub error_sites: i64,
+    // Stored full texts and their compressed size
+    pub fulltext_rows: i64,
+    pub fulltext_bytes: i64,
+    // Kept earlier versions of changed rows
+    pub revisions: i64,
+}
+
+pub fn current_stats(conn: &Connection) -> Result<StatsSample> {
+    let count = |sql: &str| -> Result<i64> { Ok(conn.query_row(sql, [], |row| row.get(0))?) };
+
+    Ok(StatsSample {
+        sampled_at: Utc::now().to_rfc3339(),
+        queue_pending: count("SELECT COUNT(*) FROM crawl_queue WHERE status = 'pending'")?,
+        queue_done: count("SELECT COUNT(*) FROM crawl_queue WHERE status = 'done'")?,
+        queue_error: count("SELECT COUNT(*) FROM crawl_queue WHERE status = 'error'")?,
+        contents_blog: count(
+            "SELECT COUNT(*) FROM contents WHERE type = 'blog' AND deleted_at IS NULL",
+        )?,
+        contents_youtube: count(
+            "SELECT COUNT(*) FROM contents WHERE type = 'youtube' AND deleted_at IS NULL",
+        )?,
+        error_sites: count("SELECT COUNT(*) FROM error_sites")?,
+        fulltext_rows: count("SELECT COUNT(*) FROM content_text")?,
+        fulltext_bytes: count("SELECT COALESCE(SUM(LENGTH(text)), 0) FROM content_text")?,
+        revisions: count("SELECT COUNT(*) FROM content_revisions")?,
+    })
+}
+
+// Store the current counts in stats_history and drop samples past retention
+pub fn record_stats_sample(conn: &Connection) -> Result<StatsSample> {
+    let sample = current_stats(conn)?;
+
+    conn.execute(
+        "
+        INSERT OR REPLACE INTO stats_history (
+            sampled_at, queue_pending, queue_done, queue_error,
+            contents_blog, contents_youtube, error_sites, fulltext_rows, fulltext_bytes,
+            revisions
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        ",
+        params![
+            sample.sampled_at,
+            sample.queue_pending,
+            sample.queue_done,
+            sample.queue_error,
+            sample.contents_blog,
+            sample.contents_youtube,
+            sample.error_sites,
+            sample.fulltext_rows,
+            sample.fulltext_bytes,
+            sample.revisions
+        ],
+    )?;
+
+    let cutoff = Utc::now() - Duration::days(STATS_RETENTION_DAYS);
+    conn.execute(
+        "DELETE FROM stats_history WHERE sampled_at < ?1",
+        [cutoff.to_rfc3339()],
+    )?;
+
+    Ok(sample)
+}
+
+// Samples taken at or after `since`, oldest first
+pub fn stats_history(conn: &Connection, since: &str) -> Result<Vec<StatsSample>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT sampled_at, queue_pending, queue_done, queue_error,
+               contents_blog, contents_youtube, error_sites, fulltext_rows, fulltext_bytes,
+               revisions
+        FROM stats_history
+        WHERE sampled_at >= ?1
+        ORDER BY sampled_at
+        ",
+    )?;
+
+    let rows = stmt
+        .query_map([since], |row| {
+            Ok(StatsSample {
+                sampled_at: row.get(0)?,
+                queue_pending: row.get(1)?,
+                queue_done: row.get(2)?,
+                queue_error: row.get(3)?,
+                contents_blog: row.get(4)?,
+                contents_youtube: row.get(5)?,
+                error_sites: row.get(6)?,
+                fulltext_rows: row.get(7)?,
+                fulltext_bytes: row.get(8)?,
+                revisions: row.get(9)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+// Totals of one crawl run, with the breakdown per source
+#[derive(Debug, Serialize)]
+pub struct CrawlRun {
+    pub started_at: String,
+    pub finished_at: String,
+    pub pages_fetched: usize,
+    pub articles_inserted: usize,
+    pub articles_skipped: usize,
+    pub errors: usize,
+    pub sources: BTreeMap<String, RunSource>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunSource {
+    pub fetched: usize,
+    pub inserted: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    // Why the source as a whole failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    // Per-host request delays at the end of the run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_delays_ms: BTreeMap<String, u64>,
+    // The run's extraction rates, when enough pages were inserted to measure them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extraction: Option<ExtractionRates>,
+}
+
+pub fn record_run(conn: &Connection, run: &CrawlRun) -> Result<()> {
+    conn.execute(
+        "
+        INSERT OR REPLACE INTO crawl_runs (
+            started_at, finished_at, pages_fetched, articles_inserted,
+            articles_skipped, errors, sources
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ",
+        params![
+            run.started_at,
+            run.finished_at,
+            run.pages_fetched,
+            run.articles_inserted,
+            run.articles_skipped,
+            run.errors,
+            serde_json::to_string(&run.sources)?
+        ],
+    )?;
+
+    Ok(())
+}
+
+// The last `limit` runs, oldest first
+pub fn recent_runs(conn: &Connection, limit: usize) -> Result<Vec<CrawlRun>> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT started_at, finished_at, pages_fetched, articles_inserted,
+               articles_skipped, errors, sources
+        FROM crawl_runs
+        ORDER BY started_at DESC
+        LIMIT ?1
+        ",
+    )?;
+
+    let mut runs = stmt
+        .query_map([limit], |row| {
+            let sources: String = row.get(6)?;
+            Ok(CrawlRun {
+                started_at: row.get(0)?,
+                finished_at: row.get(1)?,
+                pages_fetched: row.get(2)?,
+                articles_inserted: row.get(3)?,
+                articles_skipped: row.get(4)?,
+                errors: row.get(5)?,
+                sources: serde_json::from_str(&sources).unwrap_or_default(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    runs.reverse();
+
+    Ok(runs)
+}
+
+// Rows removed by prune
+#[derive(Debug)]
+pub struct PruneCounts {
+    pub queue_done: usize,
+    pub error_sites: usize,
+}
+
+// Drop finished queue rows fetched more than `older_than_days` ago and error backoffs
+// that expired that long ago, then reclaim the space. Pending, error and
+// robots_blocked queue rows are kept: they still steer the crawls. A pruned page
+// that is linked again is queued and fetched once more.
+pub fn prune(conn: &mut Connection, older_than_days: i64) -> Result<PruneCounts> {
+    let tx = conn.transaction()?;
+    let queue_done = tx.execute(
+        "DELETE FROM crawl_queue WHERE status = 'done' AND fetched_at < datetime('now', ?1)",
+        [format!("-{} days", older_than_days)],
+    )?;
+    let cutoff = (Utc::now() - Duration::days(older_than_days)).to_rfc3339();
+    let error_sites = tx.execute("DELETE FROM error_sites WHERE retry_after < ?1", [&cutoff])?;
+    tx.commit()?;
+
+    // Not allowed inside a transaction
+    conn.execute_batch("VACUUM")?;
+
+    Ok(PruneCounts {
+        queue_done,
+        error_sites,
+    })
+}
+
+// Size of the database's pages, free ones included
+pub fn size_bytes(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+// Hide contents from export without losing the rows
+pub fn soft_delete(conn: &mut Connection, ids: &[String]) -> Result<usize> {
+    let tx = conn.transaction()?;
+    let now = Utc::now().to_rfc3339();
+    let mut affected = 0;
+
+    for id in ids {
+        affected += tx.execute(
+            "UPDATE contents SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            (&now, id),
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(affected)
+}
+
+// Physically delete contents together with their relations, embeds, tags, text and
+// revisions
+pub fn hard_delete(conn: &mut Connection, ids: &[String]) -> Result<usize> {
+    let tx = conn.transaction()?;
+    let mut affected = 0;
+
+    for id in ids {
+        tx.execute(
+            "DELETE FROM related WHERE content_id = ?1 OR related_id = ?1",
+            [id],
+        )?;
+        tx.execute("DELETE FROM content_embeds WHERE content_id = ?1", [id])?;
+        tx.execute("DELETE FROM tags WHERE content_id = ?1", [id])?;
+        tx.execute("DELETE FROM content_text WHERE content_id = ?1", [id])?;
+        tx.execute("DELETE FROM content_revisions WHERE content_id = ?1", [id])?;
+        affected += tx.execute("DELETE FROM contents WHERE id = ?1", [id])?;
+    }
+
+    tx.commit()?;
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn memory() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        conn
+    }
+
+    fn article<'a>(url: &'a str, title: &'a str) -> NewContent<'a> {
+        NewContent {
+            id: url,
+            content_type: "blog",
+            title,
+            url,
+            description: Some("旧道を歩いた"),
+            thumbnail: Some("https://example.com/a.jpg"),
+            published_at: Some("2024-05-01T00:00:00+00:00"),
+            fetched_at: "2024-05-02T00:00:00+00:00",
+            source: Some("blog"),
+            discovered_from: None,
+        }
+    }
+
+    fn stored(conn: &Connection, id: &str) -> Content {
+        fetch_all(conn, true)
+            .unwrap()
+            .into_iter()
+            .find(|c| c.id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn canonical_url_gives_one_key_per_spelling() {
+        let key = "https://example.com/%E9%9A%A7%E9%81%93/a%2Fb?q=%E6%97%A7%E9%81%93";
+        for url in [
+            key,
+            // Raw UTF-8 from hrefs
+            "https://example.com/隧道/a%2Fb?q=旧道",
+            // Lowercase hex
+            "https://example.com/%e9%9a%a7%e9%81%93/a%2fb?q=%e6%97%a7%e9%81%93",
+            // Double-encoded by a CMS
+            "https://example.com/%25E9%259A%25A7%25E9%2581%2593/a%2Fb?q=%25E6%2597%25A7%25E9%2581%2593",
+            // Mixed, with an escaped unreserved character, tracking and a fragment
+            "https://EXAMPLE.com/隧%E9%81%93/%61%2fb/?q=旧%e9%81%93&utm_source=x#top",
+        ] {
+            assert_eq!(canonical_url(url), key, "{}", url);
+        }
+
+        // An escaped / is part of the segment, not a separator
+        assert_ne!(
+            canonical_url("https://example.com/隧道/a/b"),
+            canonical_url("https://example.com/隧道/a%2Fb")
+        );
+    }
+
+    #[test]
+    fn title_hash_folds_full_and_half_width() {
+        for (a, b) in [
+            ("ＡＢＣ国道の旧道", "ABC国道の旧道"),
+            ("国道１２３号！", "国道123号!"),
+            ("ｶﾀｶﾅの旧道", "カタカナの旧道"),
+            ("ｶﾞｲﾄﾞ付き ﾊﾟﾉﾗﾏ", "ガイド付き パノラマ"),
+            ("｢旧道｣を歩く､その先", "「旧道」を歩く、その先"),
+            ("旧道　探索　記録", "旧道 探索 記録"),
+            ("ｳﾞｨﾗ跡の旧道", "ヴィラ跡の旧道"),
+        ] {
+            assert_eq!(title_hash(a), title_hash(b), "{} vs {}", a, b);
+            assert!(title_hash(a).is_some(), "{}", a);
+        }
+        assert_ne!(title_hash("ガイド付き旧道"), title_hash("カイド付き旧道"));
+        assert_ne!(title_hash("パノラマ旧道"), title_hash("バノラマ旧道"));
+
+        // Rows hashed before half-width kana were folded are hashed again
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "ｶﾀｶﾅの旧道")).unwrap();
+        conn.execute("UPDATE contents SET title_hash = 'stale'", [])
+            .unwrap();
+        init(&conn).unwrap();
+        assert_eq!(stored(&conn, url).title_hash, title_hash("カタカナの旧道"));
+    }
+
+    // One source task writes while another, sharing the run's cache, reads: what the
+    // writer records is seen by the reader's next lookup, as the database sees it
+    #[tokio::test]
+    async fn run_cache_sees_writes_from_other_tasks_in_the_run() {
+        let conn = Rc::new(memory());
+        let cache = Rc::new(RunCache::load(&conn).unwrap());
+        let url = "https://example.com/a.html";
+        let moved = "https://example.com/old.html";
+        let broken = "https://example.com/broken.html";
+        let urls = [url, moved, broken].map(str::to_string);
+
+        let (ready, wait_ready) = tokio::sync::oneshot::channel();
+        let (written, wait_written) = tokio::sync::oneshot::channel();
+        let local = tokio::task::LocalSet::new();
+
+        let (reader_conn, reader_cache) = (conn.clone(), cache.clone());
+        let reader = local.spawn_local(async move {
+            assert!(reader_cache.fetched_at(url).is_none());
+            assert_eq!(reader_cache.filter_unknown(&urls).len(), 3);
+            ready.send(()).unwrap();
+            wait_written.await.unwrap();
+
+            let stored: i64 = reader_conn
+                .query_row(
+                    "SELECT COUNT(*) FROM contents WHERE id = ?1",
+                    [url],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(stored, 1);
+            // Looked up by the same canonical key the writer used
+            assert!(
+                reader_cache
+                    .fetched_at("https://EXAMPLE.com/a.html#top")
+                    .is_some()
+            );
+            assert!(reader_cache.is_redirect(moved));
+            assert!(reader_cache.should_skip(broken));
+            assert!(reader_cache.filter_unknown(&urls).is_empty());
+        });
+
+        let writer = local.spawn_local(async move {
+            wait_ready.await.unwrap();
+            let content = article(url, "峠の旧道");
+            assert!(insert(&conn, &content).unwrap());
+            cache.record_content(content.id, content.fetched_at);
+            cache.record_redirect(moved);
+            cache.register_error(&conn, broken, "404", 7).unwrap();
+            written.send(()).unwrap();
+        });
+
+        local
+            .run_until(async {
+                writer.await.unwrap();
+                reader.await.unwrap();
+            })
+            .await;
+    }
+
+    #[test]
+    fn insert_keeps_the_first_row() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+
+        assert!(insert(&conn, &article(url, "First")).unwrap());
+        assert!(!insert(&conn, &article(url, "Second")).unwrap());
+
+        assert_eq!(fetch_all(&conn, true).unwrap().len(), 1);
+        assert_eq!(stored(&conn, url).title, "First");
+    }
+
+    #[test]
+    fn upsert_overwrites_the_extracted_fields() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "First")).unwrap();
+
+        let update = NewContent {
+            description: Some("廃道になった"),
+            thumbnail: Some("https://example.com/b.jpg"),
+            published_at: None,
+            fetched_at: "2024-06-01T00:00:00+00:00",
+            ..article(url, "Second")
+        };
+        assert!(!upsert(&conn, &update, 10).unwrap());
+
+        let row = stored(&conn, url);
+        assert_eq!(row.title, "Second");
+        assert_eq!(row.description.as_deref(), Some("廃道になった"));
+        assert_eq!(row.thumbnail.as_deref(), Some("https://example.com/b.jpg"));
+        // First-seen time and a date the new fetch didn't find are kept
+        assert_eq!(row.fetched_at, "2024-05-02T00:00:00+00:00");
+        assert_eq!(
+            row.published_at.as_deref(),
+            Some("2024-05-01T00:00:00+00:00")
+        );
+        assert_eq!(row.fingerprint, fingerprint("Second", Some("廃道になった")));
+
+        let refreshed_at: Option<String> = conn
+            .query_row(
+                "SELECT refreshed_at FROM contents WHERE id = ?1",
+                [url],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(refreshed_at.as_deref(), Some("2024-06-01T00:00:00+00:00"));
+
+        assert!(upsert(&conn, &article("https://example.com/new.html", "New"), 10).unwrap());
+    }
+
+    #[test]
+    fn optional_columns_are_stored_as_null() {
+        let conn = memory();
+        let url = "https://example.com/bare.html";
+        let bare = NewContent {
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            source: None,
+            discovered_from: None,
+            ..article(url, "Bare")
+        };
+        insert(&conn, &bare).unwrap();
+
+        let row = stored(&conn, url);
+        assert_eq!(row.description, None);
+        assert_eq!(row.thumbnail, None);
+        assert_eq!(row.published_at, None);
+        assert_eq!(row.source, None);
+        assert_eq!(row.discovered_from, None);
+        assert_eq!(row.category, None);
+
+        // An upsert without a description or thumbnail clears the stored ones
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "First")).unwrap();
+        upsert(
+            &conn,
+            &NewContent {
+                description: None,
+                thumbnail: None,
+                ..article(url, "First")
+            },
+            10,
+        )
+        .unwrap();
+        let row = stored(&conn, url);
+        assert_eq!(row.description, None);
+        assert_eq!(row.thumbnail, None);
+    }
+
+    // A refetch of the article at `fetched_at` with another title and description
+    fn edit<'a>(url: &'a str, title: &'a str, fetched_at: &'a str) -> NewContent<'a> {
+        NewContent {
+            description: Some(title),
+            fetched_at,
+            ..article(url, title)
+        }
+    }
+
+    #[test]
+    fn upsert_keeps_the_replaced_versions() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "通行止め")).unwrap();
+
+        // An unchanged refetch adds nothing
+        upsert(&conn, &article(url, "通行止め"), 10).unwrap();
+        assert!(revisions(&conn, url).unwrap().is_empty());
+
+        upsert(
+            &conn,
+            &edit(url, "通行止め延長", "2024-06-01T00:00:00+00:00"),
+            10,
+        )
+        .unwrap();
+        upsert(
+            &conn,
+            &edit(url, "通行止め解除", "2024-07-01T00:00:00+00:00"),
+            10,
+        )
+        .unwrap();
+
+        let chain = revisions(&conn, url).unwrap();
+        let titles: Vec<&str> = chain.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, ["通行止め", "通行止め延長"]);
+        assert_eq!(chain[0].revision_no, 1);
+        assert_eq!(chain[0].description.as_deref(), Some("旧道を歩いた"));
+        assert_eq!(
+            chain[0].thumbnail.as_deref(),
+            Some("https://example.com/a.jpg")
+        );
+        assert_eq!(chain[0].captured_at, "2024-06-01T00:00:00+00:00");
+        assert_eq!(chain[1].revision_no, 2);
+        assert_eq!(chain[1].description.as_deref(), Some("通行止め延長"));
+        assert_eq!(stored(&conn, url).title, "通行止め解除");
+
+        assert_eq!(
+            previous_titles(&conn).unwrap().get(url).map(String::as_str),
+            Some("通行止め延長")
+        );
+        assert_eq!(current_stats(&conn).unwrap().revisions, 2);
+    }
+
+    #[test]
+    fn revisions_are_capped_per_row() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "v0")).unwrap();
+        for title in ["v1", "v2", "v3", "v4"] {
+            upsert(&conn, &edit(url, title, "2024-06-01T00:00:00+00:00"), 2).unwrap();
+        }
+
+        let chain = revisions(&conn, url).unwrap();
+        let kept: Vec<(i64, &str)> = chain
+            .iter()
+            .map(|r| (r.revision_no, r.title.as_str()))
+            .collect();
+        assert_eq!(kept, [(3, "v2"), (4, "v3")]);
+
+        // 0 turns revisions off
+        let other = "https://example.com/b.html";
+        insert(&conn, &article(other, "v0")).unwrap();
+        upsert(&conn, &edit(other, "v1", "2024-06-01T00:00:00+00:00"), 0).unwrap();
+        assert!(revisions(&conn, other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn previous_title_skips_edits_that_kept_the_title() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "旧題")).unwrap();
+        upsert(&conn, &article(url, "新題"), 10).unwrap();
+        // Only the description changes
+        upsert(&conn, &edit(url, "新題", "2024-06-01T00:00:00+00:00"), 10).unwrap();
+
+        assert_eq!(revisions(&conn, url).unwrap().len(), 2);
+        assert_eq!(
+            previous_titles(&conn).unwrap().get(url).map(String::as_str),
+            Some("旧題")
+        );
+    }
+
+    #[test]
+    fn aliases_follow_chains_of_moves() {
+        let conn = memory();
+        let (a, b, c) = (
+            "https://example.com/a.html",
+            "https://example.com/b.html",
+            "https://example.com/c.html",
+        );
+        for url in [a, b, c] {
+            insert(&conn, &article(url, url)).unwrap();
+        }
+        record_move(&conn, a, b).unwrap();
+        record_move(&conn, b, c).unwrap();
+
+        let aliases = aliases(&conn).unwrap();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases[c], [a, b]);
+        assert_eq!(live_fetched_at(&conn, a).unwrap(), None);
+        assert!(content_exists(&conn, a).unwrap());
+    }
+
+    fn tagged(conn: &Connection, url: &str, published_at: &str, tags: &[&str]) {
+        insert(
+            conn,
+            &NewContent {
+                published_at: Some(published_at),
+                ..article(url, url)
+            },
+        )
+        .unwrap();
+        let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+        record_tags(conn, url, "site_tag", &tags).unwrap();
+    }
+
+    #[test]
+    fn tag_neighbours_prefer_road_and_rare_tags_then_newer_items() {
+        let conn = memory();
+        let url = |name: &str| format!("https://example.com/{}.html", name);
+        let (a, b, c, d, e, gone) = (
+            url("a"),
+            url("b"),
+            url("c"),
+            url("d"),
+            url("e"),
+            url("gone"),
+        );
+        tagged(
+            &conn,
+            &a,
+            "2024-01-01T00:00:00+00:00",
+            &["国道152号", "酷道", "廃道"],
+        );
+        tagged(&conn, &b, "2023-01-01T00:00:00+00:00", &["国道152号", "峠"]);
+        tagged(
+            &conn,
+            &c,
+            "2022-01-01T00:00:00+00:00",
+            &["国道152号", "廃道"],
+        );
+        tagged(&conn, &d, "2024-06-01T00:00:00+00:00", &["酷道", "廃道"]);
+        tagged(&conn, &e, "2024-06-01T00:00:00+00:00", &["林道"]);
+        tagged(
+            &conn,
+            &gone,
+            "2024-06-01T00:00:00+00:00",
+            &["国道152号", "峠"],
+        );
+        supersede(&conn, &gone, &b).unwrap();
+        // An explicit relation comes first and isn't repeated
+        conn.execute(
+            "INSERT INTO related (content_id, related_id, relation_kind) VALUES (?1, ?2, 'link')",
+            [&b, &e],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO related (content_id, related_id, relation_kind) VALUES (?1, ?2, 'link')",
+            [&b, &c],
+        )
+        .unwrap();
+
+        let config = RelatedTagsConfig {
+            enabled: true,
+            max_items: 2,
+        };
+        link_related(&conn, &config).unwrap();
+        let related = related_ids(&conn).unwrap();
+        // a-c share the road and 廃道, a-b the road only, a-d two genre tags
+        assert_eq!(related[&a], [c.clone(), b.clone()]);
+        // a and c tie on the road; a is newer
+        assert_eq!(related[&b], [c.clone(), e.clone(), a.clone()]);
+        assert_eq!(related[&d], [a.clone(), c.clone()]);
+        assert!(!related.contains_key(&e));
+        assert!(!related.contains_key(&gone));
+
+        // Run again: the same neighbours, not more
+        link_related(&conn, &config).unwrap();
+        assert_eq!(related_ids(&conn).unwrap()[&a], [c.clone(), b.clone()]);
+
+        let off = RelatedTagsConfig {
+            enabled: false,
+            ..config
+        };
+        link_related(&conn, &off).unwrap();
+        let related = related_ids(&conn).unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[&b], [c, e]);
+    }
+
+    fn search_ids(conn: &Connection, query: &str) -> Vec<String> {
+        search(conn, query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect()
+    }
+
+    #[test]
+    fn search_matches_the_stored_main_text() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "First")).unwrap();
+        assert!(search_ids(&conn, "素掘り隧道").is_empty());
+
+        set_content_text(&conn, url, "峠の手前に素掘り隧道が残っている").unwrap();
+        assert_eq!(search_ids(&conn, "素掘り隧道"), [url]);
+
+        // Updating the row's own fields leaves the indexed body alone
+        upsert(&conn, &article(url, "Second"), 10).unwrap();
+        set_excerpt(&conn, url, Some("峠の手前")).unwrap();
+        assert_eq!(search_ids(&conn, "素掘り隧道"), [url]);
+        assert_eq!(search_ids(&conn, "Second"), [url]);
+    }
+
+    #[test]
+    fn an_index_without_body_is_rebuilt() {
+        let conn = memory();
+        let url = "https://example.com/a.html";
+        insert(&conn, &article(url, "First")).unwrap();
+        set_content_text(&conn, url, "峠の手前に素掘り隧道が残っている").unwrap();
+
+        // The index as the first version of the search command created it
+        conn.execute_batch(
+            "
+            DROP TABLE contents_fts;
+            CREATE VIRTUAL TABLE contents_fts USING fts5(
+                id UNINDEXED, title, description, excerpt, tokenize = 'trigram'
+            );
+            INSERT INTO contents_fts (id, title, description, excerpt)
+            SELECT id, title, description, excerpt FROM contents;
+            ",
+        )
+        .unwrap();
+        init(&conn).unwrap();
+
+        assert_eq!(search_ids(&conn, "素掘り隧道"), [url]);
+        assert_eq!(search_ids(&conn, "First"), [url]);
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM contents_fts", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+}
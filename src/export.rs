@@ -1,94 +1,1426 @@
 use anyhow::Result;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 use regex::Regex;
 use rusqlite::Connection;
 use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::sync::LazyLock;
+use tracing::info;
+use url::Url;
 
+use crate::blog::SourceReport;
+use crate::classify::{self, TermMatcher};
+use crate::config::{
+    DomainWeight, ExportConfig, ExportFormat, ExportInclude, ExportSort, ScoringConfig,
+    SortDirection,
+};
 use crate::db;
 
 #[derive(Serialize)]
-struct ExportItem {
-    id: String,
-    r#type: String,
-    title: String,
-    url: String,
-    description: Option<String>,
-    thumbnail: Option<String>,
-    published_at: Option<String>,
-    score: i32,
+pub struct ExportItem {
+    pub id: String,
+    pub r#type: String,
+    pub title: String,
+    pub url: String,
+    // The meta description, else the excerpt
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+    pub thumbnail: Option<String>,
+    pub published_at: Option<String>,
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    pub related: Vec<String>,
+    pub tags: Vec<String>,
+    pub is_new: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<String>,
+    // Ids of stored rows whose pages moved here; links to them should resolve to this
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    pub score: i32,
+    // Only filled in when ExportOptions::include_discovered_from is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discovered_from: Option<String>,
+    // Only filled in when ExportOptions::include_previous_title is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_title: Option<String>,
+    // Only filled in by dead / all exports (ExportConfig::include): when the link was
+    // found dead or the item was superseded, a Wayback copy, and the replacement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dead_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
+    #[serde(skip)]
+    pub fingerprint: Option<String>,
+    // Configured blog / channel name; null for rows stored before sources were recorded
+    pub source: Option<String>,
+    #[serde(skip)]
+    pub fetched_at: String,
+    #[serde(skip)]
+    pub refreshed_at: Option<String>,
+    // The requested ExportConfig::extra_fields, by name
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
-// Entry point
-pub fn export_json(conn: &Connection, path: &str) -> Result<()> {
-    let items = db::fetch_all(conn)?;
+pub struct ExportOptions {
+    pub collapse_syndicated: bool,
+    // Largest published_at (fetched_at fallback) gap between collapsed copies
+    pub syndication_window: Duration,
+    // Start of the current crawl run; rows first seen since then are new
+    pub run_started_at: DateTime<Utc>,
+    // Optional wider window for the new flag
+    pub new_window: Option<Duration>,
+    pub include_discovered_from: bool,
+    // The title before the latest title change, from content_revisions
+    pub include_previous_title: bool,
+    pub scoring: ScoringConfig,
+    // Config takedowns; matching rows are withheld
+    pub takedowns: Vec<String>,
+    // max_items / min_score / per_type of the index.json export
+    pub limits: ExportConfig,
+    // Leave out all but the earliest of items sharing a title_hash
+    pub dedupe_titles: bool,
+    // Replace thumbnails thumbnail_check found broken with the source's default
+    // image (source name -> URL), or leave them out
+    pub drop_broken_thumbnails: bool,
+    pub default_thumbnails: HashMap<String, String>,
+}
+
+// Stored rows left out of the export, for the run summary
+#[derive(Debug, Default, Serialize)]
+pub struct Withheld {
+    pub taken_down: Vec<String>,
+    // unavailable_after has passed
+    pub expired: Vec<String>,
+    // Later copies of an item with the same normalized title
+    pub duplicate_titles: Vec<String>,
+    // Items exported without their stored thumbnail, which failed thumbnail_check
+    pub thumbnails_suppressed: Vec<String>,
+}
+
+// Whether an item is new for this run. Anything announcing new items should use this.
+pub fn is_new(item: &db::Content, options: &ExportOptions) -> bool {
+    let Ok(first_seen) = DateTime::parse_from_rfc3339(&item.fetched_at) else {
+        return false;
+    };
+    let first_seen = first_seen.with_timezone(&Utc);
+
+    first_seen >= options.run_started_at
+        || options
+            .new_window
+            .is_some_and(|window| first_seen >= Utc::now() - window)
+}
+
+// Entry point. Returns the rows withheld by takedowns and expiry. Items, scores and
+// order are the same in every format.
+pub fn export(
+    conn: &Connection,
+    path: &str,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<Withheld> {
+    let mut withheld = Withheld::default();
+    let mut exported = collect_items(conn, options, &mut withheld)?;
+
+    if !withheld.taken_down.is_empty() || !withheld.expired.is_empty() {
+        info!(
+            "Withheld {} taken-down and {} expired items",
+            withheld.taken_down.len(),
+            withheld.expired.len()
+        );
+    }
+    if !withheld.duplicate_titles.is_empty() {
+        info!(
+            "Withheld {} items with duplicate titles",
+            withheld.duplicate_titles.len()
+        );
+    }
+    if !withheld.thumbnails_suppressed.is_empty() {
+        info!(
+            "Suppressed {} broken thumbnails",
+            withheld.thumbnails_suppressed.len()
+        );
+    }
+
+    if options.collapse_syndicated {
+        let clusters = exported.iter().filter(|i| !i.duplicates.is_empty()).count();
+        let merged: usize = exported.iter().map(|i| i.duplicates.len()).sum();
+        info!(
+            "Collapsed {} syndicated clusters ({} items)",
+            clusters, merged
+        );
+    }
+
+    let limits = &options.limits;
+    apply_limits(&mut exported, limits);
+
+    let new_count = exported.iter().filter(|item| item.is_new).count();
+    info!("Exporting {} items ({} new)", exported.len(), new_count);
+
+    let all: Vec<&ExportItem> = exported.iter().collect();
+    write_items(path, format, &all, limits)?;
+
+    if limits.per_type {
+        let mut types: Vec<&str> = exported.iter().map(|item| item.r#type.as_str()).collect();
+        types.sort_unstable();
+        types.dedup();
+
+        for content_type in types {
+            let items: Vec<&ExportItem> = exported
+                .iter()
+                .filter(|item| item.r#type == content_type)
+                .collect();
+            write_items(&type_path(path, content_type), format, &items, limits)?;
+        }
+    }
+
+    Ok(withheld)
+}
+
+// Default file of the incremental export
+pub const DEFAULT_DELTA_PATH: &str = "delta.json";
+
+// Incremental mode: only the items first stored since the previous incremental export
+// to `path`, everything on the first run. These are the items index.json gained, with
+// the same dedupe, scores and limits. The state only moves once the file is written.
+pub fn export_delta(
+    conn: &Connection,
+    path: &str,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<Withheld> {
+    // Read first: rows stored while the items are built go into the next delta
+    let latest = db::latest_fetched_at(conn)?;
+    let since = db::export_state(conn, path)?;
+
+    let mut withheld = Withheld::default();
+    let mut exported = collect_items(conn, options, &mut withheld)?;
+    apply_limits(&mut exported, &options.limits);
+    exported.retain(|item| {
+        since.as_ref().is_none_or(|since| item.fetched_at > *since)
+            && latest
+                .as_ref()
+                .is_some_and(|latest| item.fetched_at <= *latest)
+    });
+
+    info!(
+        "Exporting {} items added since {} to {}",
+        exported.len(),
+        since.as_deref().unwrap_or("the first export"),
+        path
+    );
+
+    let items: Vec<&ExportItem> = exported.iter().collect();
+    write_items(path, format, &items, &options.limits)?;
+
+    if let Some(latest) = &latest {
+        db::set_export_state(conn, path, latest)?;
+    }
+
+    Ok(withheld)
+}
+
+// min_score, then max_items of the sorted items
+fn apply_limits(items: &mut Vec<ExportItem>, limits: &ExportConfig) {
+    if let Some(min_score) = limits.min_score {
+        items.retain(|item| item.score >= min_score);
+    }
+    if let Some(max_items) = limits.max_items {
+        items.truncate(max_items);
+    }
+}
+
+// CSV columns beyond CSV_COLUMNS follow the export: ARCHIVE_CSV_COLUMNS, which live
+// exports never fill, then the extra_fields in config order
+fn write_items(
+    path: &str,
+    format: ExportFormat,
+    items: &[&ExportItem],
+    limits: &ExportConfig,
+) -> Result<()> {
+    let archive_columns = limits.include != ExportInclude::Live;
+    let mut file = BufWriter::new(File::create(path)?);
+
+    match format {
+        ExportFormat::Json => {
+            let json = serde_json::to_string_pretty(items)?;
+            file.write_all(json.as_bytes())?;
+        }
+        ExportFormat::Ndjson => {
+            for item in items {
+                serde_json::to_writer(&mut file, item)?;
+                file.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::Csv => {
+            let mut columns = CSV_COLUMNS.to_vec();
+            if archive_columns {
+                columns.extend(ARCHIVE_CSV_COLUMNS);
+            }
+            let extra_columns = extra_csv_columns(&limits.extra_fields);
+            columns.extend(extra_columns.iter().copied());
+            write_csv_row(&mut file, columns.iter().map(|c| c.to_string()))?;
+            for item in items {
+                let mut fields = csv_fields(item)?;
+                if archive_columns {
+                    fields.extend(archive_csv_fields(item));
+                }
+                for column in &extra_columns {
+                    fields.push(match item.extra.get(*column) {
+                        Some(serde_json::Value::String(text)) => text.clone(),
+                        Some(serde_json::Value::Null) | None => String::new(),
+                        Some(value) => value.to_string(),
+                    });
+                }
+                write_csv_row(&mut file, fields)?;
+            }
+        }
+    }
+
+    file.flush()?;
+    Ok(())
+}
+
+// Every ExportItem field, whether or not the JSON export would omit it
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "type",
+    "title",
+    "url",
+    "description",
+    "excerpt",
+    "thumbnail",
+    "published_at",
+    "category",
+    "site_name",
+    "related",
+    "tags",
+    "is_new",
+    "duplicates",
+    "aliases",
+    "score",
+    "discovered_from",
+    "previous_title",
+    "source",
+];
+
+// In CSV_COLUMNS order. List fields are JSON arrays, which keeps values containing
+// any separator intact.
+fn csv_fields(item: &ExportItem) -> Result<Vec<String>> {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    Ok(vec![
+        item.id.clone(),
+        item.r#type.clone(),
+        item.title.clone(),
+        item.url.clone(),
+        optional(&item.description),
+        optional(&item.excerpt),
+        optional(&item.thumbnail),
+        optional(&item.published_at),
+        optional(&item.category),
+        optional(&item.site_name),
+        serde_json::to_string(&item.related)?,
+        serde_json::to_string(&item.tags)?,
+        item.is_new.to_string(),
+        serde_json::to_string(&item.duplicates)?,
+        serde_json::to_string(&item.aliases)?,
+        item.score.to_string(),
+        optional(&item.discovered_from),
+        optional(&item.previous_title),
+        optional(&item.source),
+    ])
+}
+
+const ARCHIVE_CSV_COLUMNS: &[&str] = &["dead_at", "archived_url", "superseded_by"];
+
+// Extra fields that aren't CSV_COLUMNS already, once each
+fn extra_csv_columns(extra_fields: &[String]) -> Vec<&str> {
+    let mut columns: Vec<&str> = Vec::new();
+    for field in extra_fields {
+        if !CSV_COLUMNS.contains(&field.as_str()) && !columns.contains(&field.as_str()) {
+            columns.push(field);
+        }
+    }
+    columns
+}
+
+fn archive_csv_fields(item: &ExportItem) -> Vec<String> {
+    [&item.dead_at, &item.archived_url, &item.superseded_by]
+        .into_iter()
+        .map(|value| value.clone().unwrap_or_default())
+        .collect()
+}
+
+// RFC 4180: fields with a comma, quote or line break are quoted, quotes doubled,
+// records end in CRLF
+fn write_csv_row(out: &mut impl Write, fields: impl IntoIterator<Item = String>) -> Result<()> {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+
+    out.write_all(fields.join(",").as_bytes())?;
+    out.write_all(b"\r\n")?;
+    Ok(())
+}
+
+// index.json -> index_blog.json, in the same directory
+fn type_path(path: &str, content_type: &str) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("index");
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_{}.{}", stem, content_type, extension),
+        None => format!("{}_{}", stem, content_type),
+    };
+
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Serialize)]
+struct ArchiveMonth {
+    // "2024-05", or "undated" for the trailing bucket
+    month: String,
+    count: usize,
+    items: Vec<ExportItem>,
+}
+
+#[derive(Serialize)]
+struct Archive {
+    months: Vec<ArchiveMonth>,
+}
+
+// Same items as index.json grouped by calendar month of published_at (fetched_at
+// fallback) in JST, newest month and newest item first
+pub fn export_archive(conn: &Connection, path: &str, options: &ExportOptions) -> Result<()> {
+    let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+
+    let mut dated: Vec<(DateTime<FixedOffset>, ExportItem)> = Vec::new();
+    let mut undated = Vec::new();
+
+    for item in build_items(conn, options)? {
+        let date = item
+            .published_at
+            .as_deref()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .or_else(|| DateTime::parse_from_rfc3339(&item.fetched_at).ok());
+
+        match date {
+            Some(date) => dated.push((date.with_timezone(&jst), item)),
+            None => undated.push(item),
+        }
+    }
+
+    // Stable, so equal dates keep the score order
+    dated.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut months: Vec<ArchiveMonth> = Vec::new();
+    for (date, item) in dated {
+        let month = date.format("%Y-%m").to_string();
+        match months.last_mut() {
+            Some(last) if last.month == month => last.items.push(item),
+            _ => months.push(ArchiveMonth {
+                month,
+                count: 0,
+                items: vec![item],
+            }),
+        }
+    }
+
+    if !undated.is_empty() {
+        months.push(ArchiveMonth {
+            month: "undated".to_string(),
+            count: 0,
+            items: undated,
+        });
+    }
+
+    for month in &mut months {
+        month.count = month.items.len();
+    }
+
+    let json = serde_json::to_string_pretty(&Archive { months })?;
+
+    let mut file = File::create(path)?;
+    file.write_all(json.as_bytes())?;
+
+    Ok(())
+}
+
+// Identifies the feed itself; entries use their content ids
+const FEED_ID: &str = "urn:michi-matome-crawler:feed";
+const FEED_TITLE: &str = "michi_matome_crawler";
+
+// Atom 1.0 feed of the top `limit` items in export order. Entry dates are
+// published_at, else first-seen fetched_at; an item with neither parsable is dated
+// by the run.
+pub fn export_feed(
+    conn: &Connection,
+    path: &str,
+    options: &ExportOptions,
+    limit: usize,
+) -> Result<()> {
+    let items: Vec<ExportItem> = build_items(conn, options)?
+        .into_iter()
+        .take(limit)
+        .collect();
+
+    let entry_date = |item: &ExportItem| {
+        item.published_at
+            .as_deref()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .or_else(|| DateTime::parse_from_rfc3339(&item.fetched_at).ok())
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or(options.run_started_at)
+    };
+    let updated = items
+        .iter()
+        .map(entry_date)
+        .max()
+        .unwrap_or(options.run_started_at);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>{}</id>\n", FEED_ID));
+    xml.push_str(&format!("  <title>{}</title>\n", FEED_TITLE));
+    xml.push_str(&format!("  <updated>{}</updated>\n", atom_date(updated)));
+    xml.push_str(&format!("  <author><name>{}</name></author>\n", FEED_TITLE));
+
+    for item in &items {
+        let date = atom_date(entry_date(item));
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", xml_text(&entry_id(item))));
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            xml_text(item.title.trim())
+        ));
+        xml.push_str(&format!(
+            "    <link rel=\"alternate\" href=\"{}\"/>\n",
+            xml_text(&item.url)
+        ));
+        xml.push_str(&format!("    <published>{}</published>\n", date));
+        xml.push_str(&format!("    <updated>{}</updated>\n", date));
+        if let Some(name) = item.site_name.as_deref().or(item.source.as_deref()) {
+            xml.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                xml_text(name)
+            ));
+        }
+        if let Some(description) = item.description.as_deref().map(str::trim)
+            && !description.is_empty()
+        {
+            xml.push_str(&format!(
+                "    <summary>{}</summary>\n",
+                xml_text(description)
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+
+    let mut file = File::create(path)?;
+    file.write_all(xml.as_bytes())?;
+
+    info!("Wrote {} feed entries to {}", items.len(), path);
+    Ok(())
+}
+
+// Blog ids are their URLs; video ids get the prefix YouTube's own feeds use
+fn entry_id(item: &ExportItem) -> String {
+    if Url::parse(&item.id).is_ok() {
+        item.id.clone()
+    } else if item.r#type == "youtube" {
+        format!("yt:video:{}", item.id)
+    } else {
+        item.url.clone()
+    }
+}
+
+fn atom_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+// Escaped element / attribute text, without the control characters XML 1.0 forbids
+fn xml_text(text: &str) -> String {
+    let cleaned: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    quick_xml::escape::escape(cleaned.as_str()).into_owned()
+}
+
+// Bump when the summary.json layout changes
+const SUMMARY_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    version: u32,
+    run_started_at: String,
+    sources: &'a [SourceReport],
+    withheld: &'a Withheld,
+    quality: &'a db::QualityReport,
+    // quality_thresholds exceeded by `quality`
+    quality_breaches: &'a [String],
+}
+
+// Per-run report for automation
+pub fn export_summary(
+    path: &str,
+    run_started_at: DateTime<Utc>,
+    sources: &[SourceReport],
+    withheld: &Withheld,
+    quality: &db::QualityReport,
+    quality_breaches: &[String],
+) -> Result<()> {
+    let summary = Summary {
+        version: SUMMARY_VERSION,
+        run_started_at: run_started_at.to_rfc3339(),
+        sources,
+        withheld,
+        quality,
+        quality_breaches,
+    };
+
+    let json = serde_json::to_string_pretty(&summary)?;
+
+    let mut file = File::create(path)?;
+    file.write_all(json.as_bytes())?;
+
+    Ok(())
+}
+
+// Scored, collapsed and sorted items exactly as they are exported
+pub fn build_items(conn: &Connection, options: &ExportOptions) -> Result<Vec<ExportItem>> {
+    collect_items(conn, options, &mut Withheld::default())
+}
+
+fn collect_items(
+    conn: &Connection,
+    options: &ExportOptions,
+    withheld: &mut Withheld,
+) -> Result<Vec<ExportItem>> {
+    let include = options.limits.include;
+    let mut items = match include {
+        ExportInclude::Dead => Vec::new(),
+        ExportInclude::Live | ExportInclude::All => db::fetch_all(conn, false)?,
+    };
+    if options.dedupe_titles {
+        items = drop_title_duplicates(items, withheld);
+    }
+    // After the dedupe: a superseded row usually shares its replacement's title
+    if include != ExportInclude::Live {
+        items.extend(db::fetch_archived(conn)?);
+    }
+    let mut related = db::related_ids(conn)?;
+    let mut tags = db::tags(conn)?;
+    let mut aliases = db::aliases(conn)?;
+    let mut previous_titles = if options.include_previous_title {
+        db::previous_titles(conn)?
+    } else {
+        HashMap::new()
+    };
+    let broken_thumbnails = if options.drop_broken_thumbnails {
+        db::broken_thumbnails(conn)?
+    } else {
+        HashSet::new()
+    };
+    let terms = TermMatcher::new(&options.scoring)?;
+    let now = Utc::now();
 
     let mut exported = Vec::new();
 
     for item in items {
-        let score = calculate_score(&item);
+        if classify::is_taken_down(&options.takedowns, &item.url) {
+            withheld.taken_down.push(item.id);
+            continue;
+        }
+
+        let expired = item
+            .expires_at
+            .as_deref()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .is_some_and(|d| d <= now);
+        if expired {
+            withheld.expired.push(item.id);
+            continue;
+        }
+
+        let score = calculate_score(&item, &options.scoring, &terms);
+        let extra = extra_fields(&item, &options.limits.extra_fields);
+        let related = related.remove(&item.id).unwrap_or_default();
+        let tags = tags.remove(&item.id).unwrap_or_default();
+        let is_new = is_new(&item, options);
+        let previous_title = previous_titles.remove(&item.id);
+        let aliases = aliases.remove(&item.id).unwrap_or_default();
+        // The stored value stays; only the export goes without it
+        let thumbnail = match item.thumbnail {
+            Some(thumbnail) if broken_thumbnails.contains(&thumbnail) => {
+                withheld.thumbnails_suppressed.push(item.id.clone());
+                item.source
+                    .as_ref()
+                    .and_then(|source| options.default_thumbnails.get(source))
+                    .cloned()
+            }
+            thumbnail => thumbnail,
+        };
 
         exported.push(ExportItem {
             id: item.id,
             r#type: item.content_type,
             title: item.title,
             url: item.url,
-            description: item.description,
-            thumbnail: item.thumbnail,
+            description: item.description.or_else(|| item.excerpt.clone()),
+            excerpt: item.excerpt,
+            thumbnail,
             published_at: item.published_at,
+            category: item.category,
+            site_name: item.site_name,
+            related,
+            tags,
+            is_new,
+            duplicates: Vec::new(),
+            aliases,
             score,
+            discovered_from: item
+                .discovered_from
+                .filter(|_| options.include_discovered_from),
+            previous_title,
+            dead_at: item.dead_at.filter(|_| include != ExportInclude::Live),
+            archived_url: item.archived_url.filter(|_| include != ExportInclude::Live),
+            superseded_by: item
+                .superseded_by
+                .filter(|_| include != ExportInclude::Live),
+            fingerprint: item.fingerprint,
+            source: item.source,
+            fetched_at: item.fetched_at,
+            refreshed_at: item.refreshed_at,
+            extra,
         });
     }
 
-    // Sort by score descending
-    exported.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    if options.collapse_syndicated {
+        exported = collapse_duplicates(exported, options.syndication_window);
+    }
 
-    let json = serde_json::to_string_pretty(&exported)?;
+    // Sort by score descending, then newest published first (undated last)
+    exported.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| b.published_at.cmp(&a.published_at))
+    });
+    sort_items(&mut exported, &options.limits);
 
-    let mut file = File::create(path)?;
-    file.write_all(json.as_bytes())?;
+    Ok(exported)
+}
 
-    Ok(())
+// ExportConfig::sort other than the default score order. Items without the date go
+// last in both directions; ties keep the score order, then go by id.
+fn sort_items(items: &mut [ExportItem], limits: &ExportConfig) {
+    let date = |value: Option<&str>| value.and_then(|d| DateTime::parse_from_rfc3339(d).ok());
+    let key = |item: &ExportItem| match limits.sort {
+        ExportSort::Score => None,
+        ExportSort::PublishedAt => date(item.published_at.as_deref()),
+        ExportSort::FirstSeenAt => date(Some(&item.fetched_at)),
+        ExportSort::UpdatedAt => date(item.refreshed_at.as_deref()),
+    };
+    let ties = |a: &ExportItem, b: &ExportItem| a.id.cmp(&b.id);
+
+    match (limits.sort, limits.direction) {
+        (ExportSort::Score, SortDirection::Desc) => {}
+        (ExportSort::Score, SortDirection::Asc) => {
+            items.sort_by(|a, b| a.score.cmp(&b.score).then_with(|| ties(a, b)))
+        }
+        (_, direction) => items.sort_by(|a, b| {
+            let order = match (key(a), key(b)) {
+                (Some(a), Some(b)) if direction == SortDirection::Asc => a.cmp(&b),
+                (Some(a), Some(b)) => b.cmp(&a),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            };
+            order
+                .then_with(|| b.score.cmp(&a.score))
+                .then_with(|| ties(a, b))
+        }),
+    }
 }
 
-fn calculate_score(item: &db::Content) -> i32 {
-    let mut score = 0;
-    if Regex::new(r"[一-龠ぁ-んァ-ン]+道\d+号").unwrap().is_match(
-        format!(
-            "{}, {}",
-            &item.title,
-            item.description.clone().unwrap_or("".into())
-        )
-        .as_str(),
-    ) {
-        score += 5
+// The requested EXPORT_EXTRA_FIELDS of a row; source and tags are regular fields
+fn extra_fields(item: &db::Content, fields: &[String]) -> BTreeMap<String, serde_json::Value> {
+    let mut extra = BTreeMap::new();
+    for field in fields {
+        let value = match field.as_str() {
+            "fetched_at" => item
+                .refreshed_at
+                .as_ref()
+                .unwrap_or(&item.fetched_at)
+                .clone()
+                .into(),
+            "first_seen_at" => item.fetched_at.clone().into(),
+            "updated_at" => item.refreshed_at.clone().into(),
+            "domain" => Url::parse(&item.url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .into(),
+            "word_count" => item.text_length.into(),
+            _ => continue,
+        };
+        extra.insert(field.clone(), value);
     }
+    extra
+}
 
-    if Regex::new(r"[一-龠ぁ-んァ-ン]+跡").unwrap().is_match(
-        format!(
-            "{}, {}",
-            &item.title,
-            item.description.clone().unwrap_or("".into())
-        )
-        .as_str(),
-    ) {
-        score += 3
+// One item per type and title_hash, the earliest published (undated ones last);
+// rows stored before duplicate titles were caught at insert time
+fn drop_title_duplicates(items: Vec<db::Content>, withheld: &mut Withheld) -> Vec<db::Content> {
+    let published = |item: &db::Content| {
+        item.published_at
+            .as_deref()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+    };
+
+    let mut keep: HashMap<(&str, &str), usize> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        let Some(hash) = item.title_hash.as_deref() else {
+            continue;
+        };
+        let kept = keep.entry((&item.content_type, hash)).or_insert(index);
+        let earlier = match (published(item), published(&items[*kept])) {
+            (Some(new), Some(old)) => new < old,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        if earlier {
+            *kept = index;
+        }
     }
+    let keep: HashSet<usize> = keep.into_values().collect();
 
-    if Regex::new(r"[一-龠ぁ-んァ-ン]+道").unwrap().is_match(
-        format!(
-            "{}, {}",
-            &item.title,
-            item.description.clone().unwrap_or("".into())
-        )
-        .as_str(),
-    ) {
-        score += 1
+    let mut result = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        if item.title_hash.is_some() && !keep.contains(&index) {
+            withheld.duplicate_titles.push(item.id);
+        } else {
+            result.push(item);
+        }
+    }
+    result
+}
+
+// Merge items sharing a fingerprint on different domains into one representative:
+// highest score, then earliest published. Same-domain matches are left alone.
+fn collapse_duplicates(items: Vec<ExportItem>, window: Duration) -> Vec<ExportItem> {
+    // Keep the incoming order so ties stay deterministic after the score sort
+    let mut groups: HashMap<String, Vec<(usize, ExportItem)>> = HashMap::new();
+    let mut result = Vec::new();
+
+    for (position, item) in items.into_iter().enumerate() {
+        match item.fingerprint.clone() {
+            Some(fp) => groups.entry(fp).or_default().push((position, item)),
+            None => result.push((position, item)),
+        }
+    }
+
+    let date = |item: &ExportItem| {
+        item.published_at
+            .as_deref()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .or_else(|| DateTime::parse_from_rfc3339(&item.fetched_at).ok())
+    };
+
+    for (_, mut group) in groups {
+        group.sort_by(|(_, a), (_, b)| {
+            b.score.cmp(&a.score).then_with(|| {
+                // None sorts after any date
+                match (&a.published_at, &b.published_at) {
+                    (Some(x), Some(y)) => x.cmp(y),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            })
+        });
+
+        // Each item joins the first representative on another domain within the
+        // window, or becomes one itself
+        let mut representatives: Vec<(usize, ExportItem)> = Vec::new();
+        for (position, item) in group {
+            let within = |rep: &ExportItem| match (date(rep), date(&item)) {
+                (Some(a), Some(b)) => (a - b).abs() <= window,
+                _ => true,
+            };
+            match representatives
+                .iter_mut()
+                .find(|(_, rep)| domain_of(&rep.url) != domain_of(&item.url) && within(rep))
+            {
+                Some((_, rep)) => rep.duplicates.push(item.id),
+                None => representatives.push((position, item)),
+            }
+        }
+
+        result.extend(representatives);
+    }
+
+    result.sort_by_key(|(position, _)| *position);
+
+    result.into_iter().map(|(_, item)| item).collect()
+}
+
+fn domain_of(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+}
+
+pub fn calculate_score(item: &db::Content, scoring: &ScoringConfig, terms: &TermMatcher) -> i32 {
+    score_breakdown(item, scoring, terms)
+        .iter()
+        .map(|(_, points)| points)
+        .sum()
+}
+
+// Contribution of each scoring rule that fired, in the order they apply; they sum to
+// calculate_score. "domain_weight" is the change made by the multiplier and delta.
+// Built-in rules of score_breakdown, compiled once: rescore scores every item twice
+static ROUTE_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[一-龠ぁ-んァ-ン]+道\d+号").unwrap());
+static RUINS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[一-龠ぁ-んァ-ン]+跡").unwrap());
+static ROAD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[一-龠ぁ-んァ-ン]+道").unwrap());
+
+pub fn score_breakdown(
+    item: &db::Content,
+    scoring: &ScoringConfig,
+    terms: &TermMatcher,
+) -> Vec<(&'static str, i32)> {
+    let text = format!(
+        "{}, {}",
+        &item.title,
+        item.description.as_deref().unwrap_or("")
+    );
+    let mut parts = Vec::new();
+
+    if ROUTE_NUMBER.is_match(&text) {
+        parts.push(("route_number", 5));
+    }
+
+    if RUINS.is_match(&text) {
+        parts.push(("ruins", 3));
+    }
+
+    if ROAD.is_match(&text) {
+        parts.push(("road", 1));
+    }
+
+    let term_score = terms.score(&text);
+    if term_score != 0 {
+        parts.push(("terms", term_score));
+    }
+
+    // Body text says less about the topic than the title does. The stored main text
+    // where there is one (store_fulltext), else its excerpt.
+    let body_score = item
+        .main_text
+        .as_deref()
+        .or(item.excerpt.as_deref())
+        .map_or(0, |body| {
+            terms.score(body) * scoring.body_term_percent / 100
+        });
+    if body_score != 0 {
+        parts.push(("body_terms", body_score));
     }
 
     if item.title.contains("404 Not Found") {
-        score -= 3
+        parts.push(("not_found", -3));
+    }
+
+    if item
+        .text_length
+        .is_some_and(|n| n > scoring.long_text_chars)
+    {
+        parts.push(("long_text", scoring.long_text_bonus));
+    }
+
+    if item.image_count.is_some_and(|n| n > scoring.many_images) {
+        parts.push(("many_images", scoring.many_images_bonus));
+    }
+
+    if let Some(weight) = domain_of(&item.url).and_then(|host| domain_weight(scoring, &host)) {
+        let base: i32 = parts.iter().map(|(_, points)| points).sum();
+        let mut score = base;
+        if let Some(multiplier) = weight.multiplier {
+            score = (score as f64 * multiplier).round() as i32
+        }
+        score += weight.delta.unwrap_or(0);
+
+        if score != base {
+            parts.push(("domain_weight", score - base));
+        }
     }
 
-    score
+    // Not scaled by the domain weight
+    let recency = recency_bonus(item, scoring);
+    if recency != 0 {
+        parts.push(("recency", recency));
+    }
+
+    parts
+}
+
+// ScoringConfig::recency_*; 0 when the date doesn't parse
+fn recency_bonus(item: &db::Content, scoring: &ScoringConfig) -> i32 {
+    let (date, percent) = match item.published_at.as_deref() {
+        Some(published_at) => (published_at, 100),
+        None => (item.fetched_at.as_str(), scoring.recency_fetched_percent),
+    };
+    let Ok(date) = DateTime::parse_from_rfc3339(date) else {
+        return 0;
+    };
+
+    let age_days = (Utc::now() - date.with_timezone(&Utc)).num_seconds() as f64 / 86_400.0;
+    let full = scoring.recency_full_days as f64;
+    let end = scoring.recency_days as f64;
+
+    let share = if age_days <= full {
+        1.0
+    } else if age_days >= end || end <= full {
+        0.0
+    } else {
+        (end - age_days) / (end - full)
+    };
+
+    (scoring.recency_bonus as f64 * share * percent as f64 / 100.0).round() as i32
+}
+
+// Most specific domain_weights entry for a host
+fn domain_weight<'a>(scoring: &'a ScoringConfig, host: &str) -> Option<&'a DomainWeight> {
+    if let Some(weight) = scoring.domain_weights.get(host) {
+        return Some(weight);
+    }
+
+    scoring
+        .domain_weights
+        .iter()
+        .filter_map(|(pattern, weight)| {
+            let suffix = pattern.strip_prefix("*.")?;
+            let matches = host == suffix || host.ends_with(&format!(".{}", suffix));
+            matches.then_some((suffix.len(), weight))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, weight)| weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(excerpt: Option<&str>, main_text: Option<&str>) -> db::Content {
+        db::Content {
+            id: "https://example.com/a.html".to_string(),
+            content_type: "blog".to_string(),
+            title: "峠歩き".to_string(),
+            url: "https://example.com/a.html".to_string(),
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            category: None,
+            fingerprint: None,
+            source: None,
+            fetched_at: "2020-01-01T00:00:00+00:00".to_string(),
+            discovered_from: None,
+            text_length: None,
+            image_count: None,
+            site_name: None,
+            expires_at: None,
+            excerpt: excerpt.map(str::to_string),
+            title_hash: None,
+            main_text: main_text.map(str::to_string),
+            dead_at: None,
+            archived_url: None,
+            superseded_by: None,
+            refreshed_at: None,
+        }
+    }
+
+    fn body_points(item: &db::Content) -> Option<i32> {
+        let scoring = ScoringConfig {
+            term_weights: [("旧道".to_string(), 4)].into(),
+            recency_bonus: 0,
+            ..ScoringConfig::default()
+        };
+        let terms = TermMatcher::new(&scoring).unwrap();
+        score_breakdown(item, &scoring, &terms)
+            .into_iter()
+            .find(|(part, _)| *part == "body_terms")
+            .map(|(_, points)| points)
+    }
+
+    #[test]
+    fn body_terms_count_half_from_the_main_text_or_the_excerpt() {
+        let long = format!("{}その先に旧道が分岐している", "峠の手前まで。".repeat(40));
+
+        // Past the excerpt: only found in the stored main text
+        assert_eq!(
+            body_points(&content(Some("峠の手前まで。"), Some(&long))),
+            Some(2)
+        );
+        assert_eq!(body_points(&content(Some("峠の手前まで。"), None)), None);
+        assert_eq!(body_points(&content(Some("旧道の入口"), None)), Some(2));
+        assert_eq!(body_points(&content(None, None)), None);
+    }
+
+    fn options() -> ExportOptions {
+        ExportOptions {
+            collapse_syndicated: false,
+            syndication_window: Duration::hours(72),
+            run_started_at: Utc::now(),
+            new_window: None,
+            include_discovered_from: false,
+            include_previous_title: false,
+            scoring: ScoringConfig::default(),
+            takedowns: Vec::new(),
+            limits: ExportConfig::default(),
+            dedupe_titles: false,
+            drop_broken_thumbnails: false,
+            default_thumbnails: HashMap::new(),
+        }
+    }
+
+    fn stored(conn: &Connection, url: &str, title: &str) {
+        let content = db::NewContent {
+            id: url,
+            content_type: "blog",
+            title,
+            url,
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            fetched_at: "2024-05-01T00:00:00+00:00",
+            source: Some("blog"),
+            discovered_from: None,
+        };
+        db::upsert(conn, &content, 10).unwrap();
+    }
+
+    fn dated(conn: &Connection, url: &str, published_at: Option<&str>, fetched_at: &str) {
+        let content = db::NewContent {
+            id: url,
+            content_type: "blog",
+            title: url,
+            url,
+            description: None,
+            thumbnail: None,
+            published_at,
+            fetched_at,
+            source: Some("blog"),
+            discovered_from: None,
+        };
+        db::insert(conn, &content).unwrap();
+    }
+
+    fn sorted(conn: &Connection, sort: ExportSort, direction: SortDirection) -> Vec<String> {
+        let options = ExportOptions {
+            limits: ExportConfig {
+                sort,
+                direction,
+                ..ExportConfig::default()
+            },
+            ..options()
+        };
+        build_items(conn, &options)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.id.rsplit('/').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn targets_sort_by_each_key_with_missing_dates_last() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let url = |name: &str| format!("https://example.com/{}", name);
+        // b and c tie on published_at; d and e are undated
+        dated(
+            &conn,
+            &url("a"),
+            Some("2024-01-01T00:00:00+00:00"),
+            "2024-03-01T00:00:00+00:00",
+        );
+        dated(
+            &conn,
+            &url("b"),
+            Some("2024-02-01T00:00:00+09:00"),
+            "2024-01-01T00:00:00+00:00",
+        );
+        dated(
+            &conn,
+            &url("c"),
+            Some("2024-01-31T15:00:00+00:00"),
+            "2024-02-01T00:00:00+00:00",
+        );
+        dated(&conn, &url("d"), None, "2024-04-01T00:00:00+00:00");
+        dated(&conn, &url("e"), None, "2024-05-01T00:00:00+00:00");
+        db::mark_refreshed(&conn, &url("c"), "2024-06-01T00:00:00+00:00").unwrap();
+        db::mark_refreshed(&conn, &url("e"), "2024-05-15T00:00:00+00:00").unwrap();
+
+        use {ExportSort::*, SortDirection::*};
+        assert_eq!(sorted(&conn, PublishedAt, Desc), ["b", "c", "a", "d", "e"]);
+        assert_eq!(sorted(&conn, PublishedAt, Asc), ["a", "b", "c", "d", "e"]);
+        assert_eq!(sorted(&conn, FirstSeenAt, Desc), ["e", "d", "a", "c", "b"]);
+        assert_eq!(sorted(&conn, FirstSeenAt, Asc), ["b", "c", "a", "d", "e"]);
+        assert_eq!(sorted(&conn, UpdatedAt, Desc), ["c", "e", "a", "b", "d"]);
+        assert_eq!(sorted(&conn, UpdatedAt, Asc), ["e", "c", "a", "b", "d"]);
+        // Every item scores the same: ties go by id
+        assert_eq!(sorted(&conn, Score, Asc), ["a", "b", "c", "d", "e"]);
+
+        // max_items applies to the sorted items
+        let mut items = build_items(
+            &conn,
+            &ExportOptions {
+                limits: ExportConfig {
+                    sort: FirstSeenAt,
+                    max_items: Some(2),
+                    ..ExportConfig::default()
+                },
+                ..options()
+            },
+        )
+        .unwrap();
+        apply_limits(
+            &mut items,
+            &ExportConfig {
+                max_items: Some(2),
+                ..ExportConfig::default()
+            },
+        );
+        assert_eq!(
+            items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            [url("e"), url("d")]
+        );
+    }
+
+    // Months are JST calendar months: late evening UTC on the last day of a month is
+    // already the next month, and the last UTC hours of a year the next year
+    #[test]
+    fn archive_months_follow_jst_across_month_and_year_ends() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let url = |name: &str| format!("https://example.com/{}", name);
+        let fetched = "2024-03-01T00:00:00+00:00";
+        // 2024-02-01 00:30 JST
+        dated(&conn, &url("feb"), Some("2024-01-31T15:30:00Z"), fetched);
+        // 2024-01-31 23:59 JST
+        dated(
+            &conn,
+            &url("jan-end"),
+            Some("2024-01-31T14:59:00Z"),
+            fetched,
+        );
+        // 2024-01-01 00:00 JST exactly, and 01:00 JST from fetched_at
+        dated(
+            &conn,
+            &url("new-year"),
+            Some("2023-12-31T15:00:00Z"),
+            fetched,
+        );
+        dated(&conn, &url("fetched"), None, "2023-12-31T16:00:00+00:00");
+        // 2023-12-31 23:59:59 JST
+        dated(&conn, &url("dec"), Some("2023-12-31T14:59:59Z"), fetched);
+
+        let path = std::env::temp_dir().join(format!("mmc-archive-{}.json", std::process::id()));
+        export_archive(&conn, path.to_str().unwrap(), &options()).unwrap();
+        let archive: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let months: Vec<(String, Vec<String>)> = archive["months"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|month| {
+                let items = month["items"].as_array().unwrap();
+                assert_eq!(month["count"], items.len());
+                let names = items
+                    .iter()
+                    .map(|item| {
+                        item["id"]
+                            .as_str()
+                            .unwrap()
+                            .rsplit('/')
+                            .next()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect();
+                (month["month"].as_str().unwrap().to_string(), names)
+            })
+            .collect();
+        let expected = [
+            ("2024-02", vec!["feb"]),
+            ("2024-01", vec!["jan-end", "fetched", "new-year"]),
+            ("2023-12", vec!["dec"]),
+        ]
+        .map(|(month, names)| {
+            (
+                month.to_string(),
+                names.into_iter().map(str::to_string).collect::<Vec<_>>(),
+            )
+        });
+        assert_eq!(months, expected);
+    }
+
+    // Copies on two domains published `gap` apart, with a 72 hour window
+    fn syndicated(gap: Duration) -> Vec<(String, Vec<String>)> {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let first = DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap();
+        for (url, published_at) in [
+            ("https://a.example/notice.html", first),
+            ("https://b.example/notice.html", first + gap),
+        ] {
+            let published_at = published_at.to_rfc3339();
+            let content = db::NewContent {
+                id: url,
+                content_type: "blog",
+                title: "国道999号 通行止めのお知らせ",
+                url,
+                description: Some("5月1日より旧道区間を全面通行止めとします。"),
+                thumbnail: None,
+                published_at: Some(&published_at),
+                fetched_at: "2024-06-01T00:00:00+00:00",
+                source: Some("blog"),
+                discovered_from: None,
+            };
+            db::insert(&conn, &content).unwrap();
+        }
+
+        let options = ExportOptions {
+            collapse_syndicated: true,
+            syndication_window: Duration::hours(72),
+            ..options()
+        };
+        let mut items: Vec<(String, Vec<String>)> = build_items(&conn, &options)
+            .unwrap()
+            .into_iter()
+            .map(|item| (item.id, item.duplicates))
+            .collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn syndicated_copies_collapse_only_within_the_window() {
+        let a = "https://a.example/notice.html".to_string();
+        let b = "https://b.example/notice.html".to_string();
+        let window = Duration::hours(72);
+
+        for gap in [window - Duration::seconds(1), window] {
+            assert_eq!(syndicated(gap), [(a.clone(), vec![b.clone()])], "{}", gap);
+        }
+        assert_eq!(
+            syndicated(window + Duration::seconds(1)),
+            [(a.clone(), vec![]), (b.clone(), vec![])]
+        );
+    }
+
+    fn keys(item: &ExportItem) -> Vec<String> {
+        let value = serde_json::to_value(item).unwrap();
+        let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn extra_fields_add_exactly_the_requested_keys() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let url = "https://www.example.com/a.html";
+        stored(&conn, url, "峠の旧道");
+        db::mark_refreshed(&conn, url, "2024-06-01T00:00:00+00:00").unwrap();
+
+        let minimal = keys(&build_items(&conn, &options()).unwrap()[0]);
+        assert!(!minimal.contains(&"first_seen_at".to_string()));
+
+        let fields = [
+            "first_seen_at",
+            "updated_at",
+            "fetched_at",
+            "domain",
+            "tags",
+        ];
+        let options = ExportOptions {
+            limits: ExportConfig {
+                extra_fields: fields.map(str::to_string).to_vec(),
+                ..ExportConfig::default()
+            },
+            ..options()
+        };
+        let item = &build_items(&conn, &options).unwrap()[0];
+        let mut expected = minimal.clone();
+        expected
+            .extend(["domain", "fetched_at", "first_seen_at", "updated_at"].map(str::to_string));
+        expected.sort();
+        assert_eq!(keys(item), expected);
+        assert_eq!(item.extra["first_seen_at"], "2024-05-01T00:00:00+00:00");
+        assert_eq!(item.extra["fetched_at"], "2024-06-01T00:00:00+00:00");
+        assert_eq!(item.extra["domain"], "www.example.com");
+
+        // And as CSV columns after the regular ones
+        let path = std::env::temp_dir().join(format!("extra-fields-{}.csv", std::process::id()));
+        write_items(
+            path.to_str().unwrap(),
+            ExportFormat::Csv,
+            &[item],
+            &options.limits,
+        )
+        .unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert!(
+            header.ends_with(",source,first_seen_at,updated_at,fetched_at,domain"),
+            "{}",
+            header
+        );
+        assert!(csv.lines().nth(1).unwrap().ends_with(",www.example.com"));
+    }
+
+    #[test]
+    fn previous_title_is_exported_on_request() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let url = "https://example.com/a.html";
+        stored(&conn, url, "通行止め");
+        stored(&conn, url, "通行止め解除");
+
+        let items = build_items(&conn, &options()).unwrap();
+        assert_eq!(items[0].previous_title, None);
+
+        let options = ExportOptions {
+            include_previous_title: true,
+            ..options()
+        };
+        let items = build_items(&conn, &options).unwrap();
+        assert_eq!(items[0].previous_title.as_deref(), Some("通行止め"));
+        assert!(
+            serde_json::to_string(&items[0])
+                .unwrap()
+                .contains(r#""previous_title":"通行止め""#)
+        );
+    }
 }
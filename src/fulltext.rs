@@ -0,0 +1,178 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+// Stored text is cut at this many characters
+pub const MAX_FULLTEXT_CHARS: usize = 20_000;
+// Length of the excerpt kept on every content row
+const EXCERPT_CHARS: usize = 200;
+
+// Subtrees that are never article body
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "nav", "aside", "footer", "header", "script", "style", "noscript", "template", "form",
+    "iframe", "button", "select",
+];
+
+// Class/id fragments of sidebars, menus, share bars and comment sections. Not applied
+// to body/main/article, whose classes often name the page layout.
+const SKIPPED_HINTS: &[&str] = &[
+    "sidebar",
+    "side-bar",
+    "menu",
+    "navi",
+    "breadcrumb",
+    "share",
+    "social",
+    "comment",
+    "related",
+    "footer",
+    "header",
+    "widget",
+    "banner",
+    "advert",
+    "pager",
+];
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "tr",
+    "br",
+    "dd",
+    "dt",
+    "figcaption",
+];
+
+// A candidate within this share of the best score is preferred when it's deeper,
+// so wrappers around the article body don't win just by containing it
+const DEEPER_SHARE: f64 = 0.9;
+
+// Readability-style main text: the block with the most non-link text, after dropping
+// navigation, sidebars and footers. None when nothing article-like is found.
+pub fn main_text(document: &Html) -> Option<String> {
+    let selector = Selector::parse("body, main, article, section, div, [role=main]").unwrap();
+
+    let candidates: Vec<(ElementRef, f64, usize)> = document
+        .select(&selector)
+        .filter(|element| {
+            !skipped(element)
+                && !element
+                    .ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .any(|a| skipped(&a))
+        })
+        .map(|element| {
+            let (text, link_text) = text_lengths(element);
+            let score = text as f64 - 2.0 * link_text as f64;
+            (element, score, element.ancestors().count())
+        })
+        .filter(|(_, score, _)| *score > 0.0)
+        .collect();
+
+    let best = candidates
+        .iter()
+        .map(|(_, score, _)| *score)
+        .fold(0.0, f64::max);
+    let (element, _, _) = candidates
+        .into_iter()
+        .filter(|(_, score, _)| *score >= best * DEEPER_SHARE)
+        .max_by_key(|(_, _, depth)| *depth)?;
+
+    let mut text = String::new();
+    collect_text(element, &mut text);
+
+    let text = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(text.chars().take(MAX_FULLTEXT_CHARS).collect())
+}
+
+// Start of the main text on one line, for listings and as a description fallback
+pub fn excerpt(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(EXCERPT_CHARS)
+        .collect()
+}
+
+fn skipped(element: &ElementRef) -> bool {
+    let value = element.value();
+    if SKIPPED_ELEMENTS.contains(&value.name()) {
+        return true;
+    }
+    if matches!(value.name(), "body" | "main" | "article") {
+        return false;
+    }
+
+    let hints = format!(
+        "{} {}",
+        value.attr("class").unwrap_or(""),
+        value.attr("id").unwrap_or("")
+    )
+    .to_lowercase();
+
+    SKIPPED_HINTS.iter().any(|hint| hints.contains(hint))
+}
+
+// (visible characters, of which inside links), ignoring skipped subtrees
+fn text_lengths(element: ElementRef) -> (usize, usize) {
+    let mut text = 0;
+    let mut link_text = 0;
+
+    for child in element.children() {
+        if let Node::Text(t) = child.value() {
+            text += t.chars().filter(|c| !c.is_whitespace()).count();
+        } else if let Some(child) = ElementRef::wrap(child)
+            && !skipped(&child)
+        {
+            let (inner, inner_links) = text_lengths(child);
+            text += inner;
+            link_text += if child.value().name() == "a" {
+                inner
+            } else {
+                inner_links
+            };
+        }
+    }
+
+    (text, link_text)
+}
+
+fn collect_text(element: ElementRef, out: &mut String) {
+    for child in element.children() {
+        if let Node::Text(t) = child.value() {
+            out.push_str(t);
+        } else if let Some(child) = ElementRef::wrap(child)
+            && !skipped(&child)
+        {
+            let block = BLOCK_ELEMENTS.contains(&child.value().name());
+            if block {
+                out.push('\n');
+            }
+            collect_text(child, out);
+            if block {
+                out.push('\n');
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use scraper::Html;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::fs;
+use url::Url;
+
+// Options shared by: crawler config import-bookmarks <file> [--folder NAME] [--category C] [--merge config.json]
+//                    crawler config import-subscriptions <file> [--category C] [--merge config.json]
+struct ImportOptions {
+    file: String,
+    folder: Option<String>,
+    category: Option<String>,
+    merge: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<ImportOptions> {
+    let mut args = args.iter();
+    let mut options = ImportOptions {
+        file: args
+            .next()
+            .context("Missing export file to import")?
+            .clone(),
+        folder: None,
+        category: None,
+        merge: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--folder" => {
+                options.folder = Some(args.next().context("--folder needs a value")?.clone())
+            }
+            "--category" => {
+                options.category = Some(args.next().context("--category needs a value")?.clone())
+            }
+            "--merge" => {
+                options.merge = Some(args.next().context("--merge needs a value")?.clone())
+            }
+            other => anyhow::bail!("Unknown import option: {}", other),
+        }
+    }
+
+    Ok(options)
+}
+
+// Entry point for `crawler config <action> ...`
+pub fn run(args: &[String]) -> Result<()> {
+    let action = args.first().context("Missing config action")?;
+
+    match action.as_str() {
+        "import-bookmarks" => {
+            let options = parse_options(&args[1..])?;
+            let text = fs::read_to_string(&options.file)
+                .with_context(|| format!("Failed to read {}", options.file))?;
+            let entries = bookmark_blogs(&text, &options)?;
+            write_entries("blogs", entries, &options)
+        }
+        "import-subscriptions" => {
+            let options = parse_options(&args[1..])?;
+            if options.folder.is_some() {
+                anyhow::bail!("--folder only applies to import-bookmarks");
+            }
+            let text = fs::read_to_string(&options.file)
+                .with_context(|| format!("Failed to read {}", options.file))?;
+            let entries = subscription_channels(&text, &options);
+            write_entries("youtube", entries, &options)
+        }
+        other => anyhow::bail!("Unknown config action: {}", other),
+    }
+}
+
+// One imported source: the key used to match existing entries plus its config JSON
+struct Entry {
+    key: String,
+    value: Value,
+}
+
+// Netscape bookmark file (browser export) -> blog entries, one per site.
+// Folders nest as <DT><H3>name</H3><DL> ... </DL>; bookmarks are <A HREF>.
+fn bookmark_blogs(text: &str, options: &ImportOptions) -> Result<Vec<Entry>> {
+    let token = Regex::new(r#"(?is)<h3[^>]*>(.*?)</h3>|<dl[^>]*>|</dl>|<a\s([^>]*)>(.*?)</a>"#)?;
+    let href = Regex::new(r#"(?i)\bhref\s*=\s*"([^"]*)""#)?;
+
+    let mut folders: Vec<String> = Vec::new();
+    let mut next_folder: Option<String> = None;
+    let mut entries = Vec::new();
+
+    for caps in token.captures_iter(text) {
+        let whole = caps[0].to_ascii_lowercase();
+
+        if let Some(name) = caps.get(1) {
+            next_folder = Some(inner_text(name.as_str()));
+        } else if whole.starts_with("<dl") {
+            // The outermost list has no heading of its own
+            folders.push(next_folder.take().unwrap_or_default());
+        } else if whole.starts_with("</dl") {
+            folders.pop();
+        } else {
+            let title = inner_text(caps.get(3).map_or("", |m| m.as_str()));
+
+            if let Some(folder) = &options.folder
+                && !folders.iter().any(|f| f == folder)
+            {
+                continue;
+            }
+
+            let Some(link) = caps.get(2).and_then(|attrs| href.captures(attrs.as_str())) else {
+                eprintln!("Import warn: bookmark {:?} has no HREF", title);
+                continue;
+            };
+            let link = inner_text(&link[1]);
+
+            let site = Url::parse(&link)
+                .ok()
+                .filter(|u| matches!(u.scheme(), "http" | "https"))
+                .and_then(|u| {
+                    u.host_str()
+                        .map(|host| (u.scheme().to_string(), host.to_string()))
+                });
+            let Some((scheme, host)) = site else {
+                eprintln!(
+                    "Import warn: skipping non-web bookmark {:?} ({})",
+                    title, link
+                );
+                continue;
+            };
+
+            let name = if title.is_empty() {
+                host.clone()
+            } else {
+                title
+            };
+            let mut value = json!({
+                "name": name,
+                "url": format!("{}://{}/", scheme, host),
+            });
+            if let Some(category) = &options.category {
+                value["category"] = json!(category);
+            }
+
+            entries.push(Entry { key: host, value });
+        }
+    }
+
+    Ok(entries)
+}
+
+// Google Takeout subscriptions.csv (Channel Id,Channel Url,Channel Title) -> youtube entries
+fn subscription_channels(text: &str, options: &ImportOptions) -> Vec<Entry> {
+    let mut entries = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields = csv_fields(line);
+        if index == 0
+            && fields
+                .first()
+                .is_some_and(|f| f.eq_ignore_ascii_case("channel id"))
+        {
+            continue;
+        }
+
+        let (Some(channel_id), Some(title)) = (fields.first(), fields.get(2)) else {
+            eprintln!(
+                "Import warn: line {}: expected 3 columns, got {}",
+                index + 1,
+                fields.len()
+            );
+            continue;
+        };
+        if !channel_id.starts_with("UC") || channel_id.len() != 24 {
+            eprintln!(
+                "Import warn: line {}: invalid channel id {:?}",
+                index + 1,
+                channel_id
+            );
+            continue;
+        }
+
+        let name = if title.is_empty() { channel_id } else { title };
+        let mut value = json!({
+            "channel_id": channel_id,
+            "name": name,
+        });
+        if let Some(category) = &options.category {
+            value["category"] = json!(category);
+        }
+
+        entries.push(Entry {
+            key: channel_id.clone(),
+            value,
+        });
+    }
+
+    entries
+}
+
+// Split one CSV line; double quotes wrap fields containing commas ("" is a literal quote)
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(ch),
+        }
+    }
+    fields.push(field.trim().to_string());
+
+    fields
+}
+
+// Bookmark titles and URLs are HTML-escaped
+fn inner_text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    fragment
+        .root_element()
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Match key of an existing config entry (blog host incl. alt_urls, or channel id)
+fn existing_keys(section: &str, config: &Value) -> HashSet<String> {
+    let mut keys = HashSet::new();
+
+    for item in config[section].as_array().into_iter().flatten() {
+        if section == "youtube" {
+            if let Some(id) = item["channel_id"].as_str() {
+                keys.insert(id.to_string());
+            }
+            continue;
+        }
+
+        let urls =
+            std::iter::once(&item["url"]).chain(item["alt_urls"].as_array().into_iter().flatten());
+        for url in urls {
+            if let Some(host) = url
+                .as_str()
+                .and_then(|u| Url::parse(u).ok())
+                .and_then(|u| u.host_str().map(|h| h.to_string()))
+            {
+                keys.insert(host);
+            }
+        }
+    }
+
+    keys
+}
+
+// Print a config fragment, or append the new entries to an existing config file
+fn write_entries(section: &str, entries: Vec<Entry>, options: &ImportOptions) -> Result<()> {
+    let mut config = match &options.merge {
+        Some(path) => {
+            let text =
+                fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+            serde_json::from_str(&text).with_context(|| format!("Invalid config {}", path))?
+        }
+        None => json!({}),
+    };
+
+    let mut known = existing_keys(section, &config);
+    let mut added = Vec::new();
+    let mut skipped = 0;
+
+    for entry in entries {
+        if known.insert(entry.key.clone()) {
+            added.push(entry.value);
+        } else {
+            eprintln!("Import: skipping {} (already present)", entry.key);
+            skipped += 1;
+        }
+    }
+
+    let count = added.len();
+    match &options.merge {
+        Some(path) => {
+            if !config[section].is_array() {
+                config[section] = json!([]);
+            }
+            if let Some(list) = config[section].as_array_mut() {
+                list.extend(added);
+            }
+            fs::write(path, serde_json::to_string_pretty(&config)? + "\n")?;
+        }
+        None => println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({ section: added }))?
+        ),
+    }
+
+    eprintln!("Import: {} added, {} skipped", count, skipped);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+
+use crate::blog;
+use crate::config;
+
+// Entry point for: crawler inspect <url> [--config config.json]
+// Fetches and extracts one page like a crawl would; nothing is stored.
+pub async fn run(args: &[String]) -> Result<()> {
+    let mut args = args.iter();
+    let url = args.next().context("Missing URL to inspect")?;
+    let mut config = None;
+    let mut client = Client::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().context("--config needs a value")?;
+                let loaded = config::load(path)?;
+                // The crawl's User-Agent, timeouts and proxy
+                client = blog::shared_client(&loaded)?;
+                config = Some(loaded);
+            }
+            other => anyhow::bail!("Unknown inspect option: {}", other),
+        }
+    }
+
+    let article = blog::crawl_single(&client, url, config.as_ref()).await?;
+    println!("{}", serde_json::to_string_pretty(&article)?);
+
+    Ok(())
+}
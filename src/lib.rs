@@ -0,0 +1,666 @@
+// Crawler library: configuration, storage, crawling and export. The binary in
+// main.rs parses arguments and calls into these modules; `run` is the default
+// crawl-then-export invocation.
+pub mod blog;
+pub mod classify;
+pub mod config;
+pub mod db;
+pub mod export;
+pub mod fulltext;
+pub mod import;
+pub mod inspect;
+pub mod links;
+pub mod logging;
+pub mod prune;
+pub mod rescore;
+pub mod robots;
+pub mod search;
+pub mod stats;
+pub mod thumbnails;
+pub mod top;
+pub mod trace;
+pub mod webhook;
+pub mod youtube;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::{JoinHandle, LocalSet};
+use tracing::{Instrument, error, error_span, info, warn};
+
+use crate::config::{Config, ExportFormat};
+
+// Outcome of one `run`
+#[derive(Debug)]
+pub struct RunReport {
+    pub started_at: DateTime<Utc>,
+    // One per source, in config order
+    pub sources: Vec<blog::SourceReport>,
+    // Items left out of the JSON export
+    pub withheld: export::Withheld,
+    // Sources whose crawl errored or panicked
+    pub failed: usize,
+    pub quality: db::QualityReport,
+    // config.quality_thresholds exceeded by `quality`
+    pub quality_breaches: Vec<String>,
+}
+
+// Crawl every source, then write the configured exports and record the run (the
+// cron job's invocation). Sources are crawled on their own connections to
+// config.db_path; `conn` should be a connection to the same database. db_path may
+// also be a SQLite URI: file:name?mode=memory&cache=shared is an in-memory database
+// shared by those connections while `conn` keeps it open (the tests use one).
+pub async fn run(config: Config, conn: &Connection) -> Result<RunReport> {
+    run_inner(config, conn).instrument(run_span()).await
+}
+
+// Span of one crawl run; its run_id tells the log lines of overlapping runs apart
+pub fn run_span() -> tracing::Span {
+    let run_id = format!(
+        "{}-{}",
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        std::process::id()
+    );
+    error_span!("run", run_id = %run_id)
+}
+
+async fn run_inner(config: Config, conn: &Connection) -> Result<RunReport> {
+    let config = Rc::new(config);
+    info!("Crawler started");
+
+    let (started_at, sources) = crawl(conn, Rc::clone(&config)).await?;
+
+    // === Export ===
+    if let Some(check) = &config.thumbnail_check {
+        thumbnails::verify(conn, &config, check).await?;
+    }
+    let export_options = export_options(&config, started_at);
+    let withheld = write_export(conn, &config, &export_options)?;
+    if let Some(path) = &config.archive_path {
+        export::export_archive(conn, path, &export_options)?;
+    }
+    if let Some(path) = &config.feed_path {
+        export::export_feed(conn, path, &export_options, config.feed_items)?;
+    }
+    let quality = quality_report(conn, started_at)?;
+    let quality_breaches = quality_breaches(&quality, &config.quality_thresholds);
+    export::export_summary(
+        &config.summary_path,
+        started_at,
+        &sources,
+        &withheld,
+        &quality,
+        &quality_breaches,
+    )?;
+
+    notify_new_items(conn, &config, started_at).await?;
+    finish_run(conn, started_at, &sources)?;
+
+    Ok(RunReport {
+        started_at,
+        failed: failed_sources(&sources),
+        sources,
+        withheld,
+        quality,
+        quality_breaches,
+    })
+}
+
+// Sources whose crawl errored or panicked
+pub fn failed_sources(reports: &[blog::SourceReport]) -> usize {
+    reports.iter().filter(|r| r.failure.is_some()).count()
+}
+
+// Err when more sources failed than max_failed_sources allows, for main to exit
+// non-zero on once the run is exported and recorded
+pub fn check_failed_sources(
+    reports: &[blog::SourceReport],
+    max_failed_sources: usize,
+) -> Result<()> {
+    let failed = failed_sources(reports);
+    if failed > max_failed_sources {
+        anyhow::bail!(
+            "{} of {} sources failed (max_failed_sources: {})",
+            failed,
+            reports.len(),
+            max_failed_sources
+        );
+    }
+    Ok(())
+}
+
+// Crawl against a throwaway copy of the database, so queue, cursors and validators
+// move on there and a real run afterwards picks up the same articles. Nothing is
+// exported.
+pub async fn dry_run(mut config: Config) -> Result<()> {
+    info!("Crawler started (dry run)");
+
+    let snapshot_path = format!("{}.dry-run", config.db_path);
+    let snapshot_files = [
+        snapshot_path.clone(),
+        format!("{}-wal", snapshot_path),
+        format!("{}-shm", snapshot_path),
+    ];
+    for file in &snapshot_files {
+        let _ = std::fs::remove_file(file);
+    }
+
+    let conn = db::open(&config.db_path)?;
+    db::init(&conn)?;
+    db::snapshot(&conn, &snapshot_path)?;
+    drop(conn);
+
+    config.db_path = snapshot_path;
+    let result = async {
+        let conn = db::open(&config.db_path)?;
+        crawl(&conn, Rc::new(config)).await
+    }
+    .instrument(run_span())
+    .await;
+
+    for file in &snapshot_files {
+        let _ = std::fs::remove_file(file);
+    }
+
+    let (_, reports) = result?;
+    for report in &reports {
+        info!(
+            "{}: {} items would have been inserted",
+            report.name, report.inserted
+        );
+    }
+    info!("Crawler finished (dry run)");
+
+    Ok(())
+}
+
+// Crawl every configured source and cross-link the results. Returns the run's start
+// time and one report per source, in config order.
+pub async fn crawl(
+    conn: &Connection,
+    config: Rc<Config>,
+) -> Result<(DateTime<Utc>, Vec<blog::SourceReport>)> {
+    let run_started_at = Utc::now();
+
+    // === Blogs and YouTube channels ===
+    // Each source runs in its own task on its own connection, so a panic inside
+    // one (e.g. in HTML parsing) is contained and any open transaction rolls back
+    // with that connection. Up to `concurrency` tasks run at once on this thread.
+    let cache = Rc::new(db::RunCache::load(conn)?);
+    let youtube_client = blog::shared_client(&config)?;
+    let permits = Rc::new(Semaphore::new(config.concurrency.max(1)));
+    let local = LocalSet::new();
+    let mut tasks = Vec::new();
+
+    for index in 0..config.blogs.len() {
+        let task_config = Rc::clone(&config);
+        let task_cache = Rc::clone(&cache);
+        let task_permits = Rc::clone(&permits);
+        let blog_cfg = &config.blogs[index];
+        // Error level, so warnings keep the source context under -q
+        let span = error_span!("blog", source = %blog_cfg.name, url = %blog_cfg.url);
+        let handle = local.spawn_local(
+            async move {
+                let _permit = task_permits.acquire().await;
+                let started = Instant::now();
+                let result = async {
+                    let conn = db::open(&task_config.db_path)?;
+                    let blog_cfg = &task_config.blogs[index];
+                    blog::fetch_and_store(&conn, &task_config, blog_cfg, task_cache).await
+                }
+                .await;
+                (started.elapsed().as_millis(), result)
+            }
+            .instrument(span),
+        );
+        tasks.push(("blog", config.blogs[index].name.clone(), handle));
+    }
+
+    for index in 0..config.youtube.len() {
+        let task_config = Rc::clone(&config);
+        let task_cache = Rc::clone(&cache);
+        let task_permits = Rc::clone(&permits);
+        let client = youtube_client.clone();
+        let handle = local.spawn_local(
+            async move {
+                let _permit = task_permits.acquire().await;
+                let started = Instant::now();
+                let result = async {
+                    let conn = db::open(&task_config.db_path)?;
+                    let channel = &task_config.youtube[index];
+                    youtube::fetch_and_store(
+                        &conn,
+                        &client,
+                        channel,
+                        task_cache,
+                        task_config.dry_run,
+                    )
+                    .await
+                }
+                .await;
+                (started.elapsed().as_millis(), result)
+            }
+            .instrument(error_span!("youtube", source = %config.youtube[index].name)),
+        );
+        tasks.push(("youtube", config.youtube[index].name.clone(), handle));
+    }
+
+    // Reports keep config order; every task finishes before the export
+    let reports = collect_reports(&local, tasks).await;
+    let failed = failed_sources(&reports);
+    if failed > 0 {
+        warn!("{} of {} sources failed", failed, reports.len());
+    }
+
+    // === Cross-link related contents ===
+    let linked = db::link_related(conn, &config.related_tags)?;
+    if linked > 0 {
+        info!("Linked {} related contents", linked);
+    }
+
+    Ok((run_started_at, reports))
+}
+
+pub fn export_options(config: &Config, run_started_at: DateTime<Utc>) -> export::ExportOptions {
+    export::ExportOptions {
+        collapse_syndicated: config.collapse_syndicated,
+        syndication_window: Duration::hours(config.syndication_window_hours),
+        run_started_at,
+        new_window: config.new_window_hours.map(Duration::hours),
+        include_discovered_from: config.export_discovered_from,
+        include_previous_title: config.export_previous_title,
+        scoring: config.scoring.clone(),
+        takedowns: config.takedowns.clone(),
+        limits: config.export.clone(),
+        dedupe_titles: config.duplicate_titles != config::DuplicateTitles::Keep,
+        drop_broken_thumbnails: config.thumbnail_check.is_some(),
+        default_thumbnails: config
+            .blogs
+            .iter()
+            .filter_map(|blog| Some((blog.name.clone(), blog.default_thumbnail.clone()?)))
+            .collect(),
+    }
+}
+
+// The full export to output_path, or in incremental mode the delta file (and the
+// full export too with also_full), then the export_targets. Returns the rows withheld
+// from the first two.
+pub fn write_export(
+    conn: &Connection,
+    config: &Config,
+    options: &export::ExportOptions,
+) -> Result<export::Withheld> {
+    let export = &config.export;
+
+    if export.incremental {
+        let path = export
+            .delta_path
+            .as_deref()
+            .unwrap_or(export::DEFAULT_DELTA_PATH);
+        let format = ExportFormat::resolve(export.format, path);
+        let withheld = export::export_delta(conn, path, format, options)?;
+        if !export.also_full {
+            write_export_targets(conn, config, options)?;
+            return Ok(withheld);
+        }
+    }
+
+    let format = ExportFormat::resolve(export.format, &config.output_path);
+    let withheld = export::export(conn, &config.output_path, format, options)?;
+    write_export_targets(conn, config, options)?;
+    Ok(withheld)
+}
+
+// config export_targets, e.g. archive.json of the dead and superseded items
+fn write_export_targets(
+    conn: &Connection,
+    config: &Config,
+    options: &export::ExportOptions,
+) -> Result<()> {
+    for target in &config.export_targets {
+        let mut target_options = export_options(config, options.run_started_at);
+        target_options.new_window = options.new_window;
+        target_options.limits = config::ExportConfig {
+            max_items: target.max_items,
+            min_score: target.min_score,
+            format: target.format,
+            include: target.include,
+            extra_fields: target.extra_fields.clone(),
+            sort: target.sort,
+            direction: target.direction,
+            ..Default::default()
+        };
+        let format = ExportFormat::resolve(target.format, &target.path);
+        export::export(conn, &target.path, format, &target_options)?;
+    }
+    Ok(())
+}
+
+// Queue the items first stored since run_started_at for the configured webhook, then
+// deliver the queue (see deliver_notifications)
+pub async fn notify_new_items(
+    conn: &Connection,
+    config: &Config,
+    run_started_at: DateTime<Utc>,
+) -> Result<()> {
+    let Some(hook) = &config.webhook else {
+        return Ok(());
+    };
+
+    // This run only, whatever new_window_hours says
+    let mut options = export_options(config, run_started_at);
+    options.new_window = None;
+    let new: Vec<String> = export::build_items(conn, &options)?
+        .into_iter()
+        .filter(|item| item.is_new)
+        .map(|item| item.id)
+        .collect();
+    db::enqueue_notifications(conn, &new, &Utc::now().to_rfc3339())?;
+
+    deliver_notifications(conn, config, hook).await
+}
+
+// Post the queued items not sent yet, leftovers of earlier runs included, in export
+// order. An entry is marked sent once the webhook accepted its batch; the rest wait
+// for the next run. Entries queued more than max_age_hours ago, or whose item is no
+// longer exported, are dropped. Delivery problems are only logged.
+pub async fn deliver_notifications(
+    conn: &Connection,
+    config: &Config,
+    hook: &config::WebhookConfig,
+) -> Result<()> {
+    let pending = db::pending_notifications(conn)?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let cutoff = now - Duration::hours(hook.max_age_hours);
+    let (expired, pending): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(_, queued_at)| {
+        DateTime::parse_from_rfc3339(queued_at).is_ok_and(|queued_at| queued_at < cutoff)
+    });
+    let expired: Vec<String> = expired.into_iter().map(|(id, _)| id).collect();
+    if !expired.is_empty() {
+        warn!(
+            "Dropping {} webhook notifications not delivered within {} hours",
+            expired.len(),
+            hook.max_age_hours
+        );
+        db::drop_notifications(conn, &expired, &now.to_rfc3339())?;
+    }
+
+    let mut pending: HashSet<String> = pending.into_iter().map(|(id, _)| id).collect();
+    let items: Vec<export::ExportItem> = export::build_items(conn, &export_options(config, now))?
+        .into_iter()
+        .filter(|item| pending.remove(&item.id))
+        .collect();
+    // Deleted, taken down or otherwise out of the export since they were queued
+    let gone: Vec<String> = pending.into_iter().collect();
+    db::drop_notifications(conn, &gone, &now.to_rfc3339())?;
+
+    if items.is_empty() {
+        return Ok(());
+    }
+    let delivered = webhook::notify(&blog::shared_client(config)?, hook, &items).await;
+    let sent: Vec<String> = items[..delivered]
+        .iter()
+        .map(|item| item.id.clone())
+        .collect();
+    db::mark_notified(conn, &sent, &Utc::now().to_rfc3339())?;
+    if delivered < items.len() {
+        warn!(
+            "{} webhook notifications left for the next run",
+            items.len() - delivered
+        );
+    }
+
+    Ok(())
+}
+
+// Run summary and stats sample at the end of a crawl run
+pub fn finish_run(
+    conn: &Connection,
+    run_started_at: DateTime<Utc>,
+    reports: &[blog::SourceReport],
+) -> Result<()> {
+    // === Run summary ===
+    let mut run = db::CrawlRun {
+        started_at: run_started_at.to_rfc3339(),
+        finished_at: Utc::now().to_rfc3339(),
+        pages_fetched: 0,
+        articles_inserted: 0,
+        articles_skipped: 0,
+        errors: 0,
+        sources: Default::default(),
+    };
+    for report in reports {
+        let source = db::RunSource {
+            fetched: report.attempted,
+            inserted: report.inserted,
+            skipped: report.skipped(),
+            errors: report.error_count(),
+            failure: report.failure.clone(),
+            host_delays_ms: report.host_delays_ms.clone(),
+            extraction: report.extraction,
+        };
+        match &source.failure {
+            Some(failure) => error!("{}: failed: {}", report.name, failure),
+            None => info!(
+                "{}: {} new, {} fetched, {} {}",
+                report.name,
+                source.inserted,
+                source.fetched,
+                source.errors,
+                if source.errors == 1 {
+                    "error"
+                } else {
+                    "errors"
+                }
+            ),
+        }
+
+        run.pages_fetched += source.fetched;
+        run.articles_inserted += source.inserted;
+        run.articles_skipped += source.skipped;
+        run.errors += source.errors;
+        run.sources.insert(report.name.clone(), source);
+    }
+    // The whole run as one event, for dashboards reading the JSON log
+    info!(
+        inserted = run.articles_inserted,
+        fetched = run.pages_fetched,
+        skipped = run.articles_skipped,
+        errors = run.errors,
+        sources = reports.len(),
+        failed = failed_sources(reports),
+        "Run summary"
+    );
+    db::record_run(conn, &run)?;
+
+    db::record_stats_sample(conn)?;
+
+    info!("Crawler finished");
+
+    Ok(())
+}
+
+// Data quality of the database after a crawl, logged. Measured before the summary
+// is written, which includes it.
+pub fn quality_report(
+    conn: &Connection,
+    run_started_at: DateTime<Utc>,
+) -> Result<db::QualityReport> {
+    let report = db::quality_report(conn, &run_started_at.to_rfc3339())?;
+    info!(
+        "Quality: {} duplicate rows, {} placeholder titles, {} without published_at, {} without thumbnail, {} invalid URLs",
+        report.duplicate_rows,
+        report.placeholder_titles,
+        report.missing_published_at,
+        report.missing_thumbnail,
+        report.invalid_urls
+    );
+    for source in &report.identical_title_sources {
+        warn!(
+            "all new items from {} share one title; check its selectors",
+            source
+        );
+    }
+    for (source, site_name) in &report.renamed_sources {
+        info!(
+            "Quality hint: pages of {} call the site {:?}; the configured name may be outdated",
+            source, site_name
+        );
+    }
+
+    Ok(report)
+}
+
+// One line per quality_thresholds bound the report exceeds
+pub fn quality_breaches(
+    report: &db::QualityReport,
+    thresholds: &config::QualityThresholds,
+) -> Vec<String> {
+    let counts = [
+        (
+            "duplicate_rows",
+            report.duplicate_rows,
+            thresholds.max_duplicate_rows,
+        ),
+        (
+            "placeholder_titles",
+            report.placeholder_titles,
+            thresholds.max_placeholder_titles,
+        ),
+        (
+            "missing_published_at",
+            report.missing_published_at,
+            thresholds.max_missing_published_at,
+        ),
+        (
+            "missing_thumbnail",
+            report.missing_thumbnail,
+            thresholds.max_missing_thumbnail,
+        ),
+        (
+            "invalid_urls",
+            report.invalid_urls,
+            thresholds.max_invalid_urls,
+        ),
+        (
+            "identical_title_sources",
+            report.identical_title_sources.len() as i64,
+            thresholds.max_identical_title_sources.map(|max| max as i64),
+        ),
+    ];
+    counts
+        .into_iter()
+        .filter_map(|(name, count, max)| {
+            let max = max?;
+            (count > max).then(|| format!("{}: {} (max {})", name, count, max))
+        })
+        .collect()
+}
+
+// Err for quality_breaches, for main to exit non-zero on after the run
+pub fn check_quality(breaches: &[String]) -> Result<()> {
+    if !breaches.is_empty() {
+        anyhow::bail!("quality thresholds exceeded: {}", breaches.join(", "));
+    }
+    Ok(())
+}
+
+type SourceTask = (
+    &'static str,
+    String,
+    JoinHandle<(u128, Result<blog::SourceReport>)>,
+);
+
+// Wait for every source task, in order: (kind, name, handle)
+async fn collect_reports(local: &LocalSet, tasks: Vec<SourceTask>) -> Vec<blog::SourceReport> {
+    let mut reports = Vec::new();
+    for (kind, name, handle) in tasks {
+        reports.push(local.run_until(collect_report(kind, &name, handle)).await);
+    }
+    reports
+}
+
+// One source's report, or a failure report when its task errored or panicked
+async fn collect_report(
+    kind: &str,
+    name: &str,
+    handle: JoinHandle<(u128, Result<blog::SourceReport>)>,
+) -> blog::SourceReport {
+    let (elapsed_ms, failure) = match handle.await {
+        Ok((elapsed_ms, Ok(mut report))) => {
+            report.elapsed_ms = elapsed_ms;
+            return report;
+        }
+        Ok((elapsed_ms, Err(e))) => (elapsed_ms, e.to_string()),
+        Err(e) if e.is_panic() => (0, "crawl panicked".to_string()),
+        Err(e) => (0, e.to_string()),
+    };
+
+    error!("Source error: {}: {}", name, failure);
+
+    let mut report = blog::SourceReport::new(name);
+    report.kind = kind.to_string();
+    report.failure = Some(failure);
+    report.elapsed_ms = elapsed_ms;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_source(name: &str) -> (u128, Result<blog::SourceReport>) {
+        let mut report = blog::SourceReport::new(name);
+        report.inserted = 1;
+        (5, Ok(report))
+    }
+
+    fn panicking_source() -> (u128, Result<blog::SourceReport>) {
+        panic!("fixture source panics")
+    }
+
+    // A panicking source task becomes a failure report of its own: the sources
+    // around it keep their reports, in order
+    #[tokio::test]
+    async fn panicking_source_is_reported_as_failed() {
+        let local = LocalSet::new();
+        let tasks: Vec<SourceTask> = vec![
+            (
+                "blog",
+                "first".to_string(),
+                local.spawn_local(async { fixture_source("first") }),
+            ),
+            (
+                "blog",
+                "panics".to_string(),
+                local.spawn_local(async { panicking_source() }),
+            ),
+            (
+                "youtube",
+                "last".to_string(),
+                local.spawn_local(async { fixture_source("last") }),
+            ),
+        ];
+
+        let reports = collect_reports(&local, tasks).await;
+        let names: Vec<&str> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["first", "panics", "last"]);
+        assert_eq!(reports[0].inserted, 1);
+        assert_eq!(reports[1].failure.as_deref(), Some("crawl panicked"));
+        assert_eq!(reports[1].kind, "blog");
+        assert_eq!(reports[2].inserted, 1);
+        assert_eq!(reports[2].elapsed_ms, 5);
+
+        assert_eq!(failed_sources(&reports), 1);
+        assert!(check_failed_sources(&reports, 0).is_err());
+        assert!(check_failed_sources(&reports, 1).is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use rusqlite::Connection;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blog::{self, Throttle};
+use crate::config::Config;
+use crate::db::{self, LinkCheck};
+
+// Options for: crawler check-links <config.json> [--limit N]
+struct CheckOptions {
+    limit: usize,
+}
+
+fn parse_options(args: &[String], config: &Config) -> Result<CheckOptions> {
+    let mut options = CheckOptions {
+        limit: config.link_check_batch,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                let value = args.next().context("--limit needs a value")?;
+                options.limit = value
+                    .parse()
+                    .with_context(|| format!("Invalid --limit: {}", value))?;
+            }
+            other => anyhow::bail!("Unknown check-links option: {}", other),
+        }
+    }
+
+    Ok(options)
+}
+
+// Entry point. Requests the stored article URLs checked longest ago; rows that keep
+// answering 404/410 are marked dead and left out of the export.
+pub async fn run(conn: &Connection, config: &Config, args: &[String]) -> Result<()> {
+    let options = parse_options(args, config)?;
+    let client = blog::shared_client(config)?;
+    let throttle = Throttle::with_delay(Duration::from_millis(config.link_check_delay_ms));
+
+    let (mut ok, mut missing, mut dead, mut transient, mut rate_limited) = (0, 0, 0, 0, 0);
+    // Links that redirect elsewhere; `crawler refresh` records the move
+    let mut moved = Vec::new();
+
+    for (id, url) in db::link_check_batch(conn, options.limit)? {
+        // The host answered 429 earlier in this run
+        if throttle.wait(&url).await.is_err() {
+            rate_limited += 1;
+            continue;
+        }
+
+        let check = match check_link(&client, &url).await {
+            Ok((StatusCode::TOO_MANY_REQUESTS, _)) => {
+                throttle.limit(&url);
+                rate_limited += 1;
+                continue;
+            }
+            Ok((status, final_url)) => {
+                if status.is_success() && db::canonical_url(&final_url) != db::canonical_url(&url) {
+                    moved.push((url.clone(), final_url));
+                }
+                classify(status)
+            }
+            Err(e) => {
+                warn!("{}: {}", url, e);
+                LinkCheck::Transient
+            }
+        };
+
+        let status = db::record_link_check(conn, &id, check, &Utc::now().to_rfc3339())?;
+        match (check, status.as_deref()) {
+            (LinkCheck::Ok, _) => ok += 1,
+            (LinkCheck::Missing, Some("dead")) => {
+                info!("Dead link: {}", url);
+                dead += 1;
+            }
+            (LinkCheck::Missing, _) => missing += 1,
+            (LinkCheck::Transient, _) => transient += 1,
+        }
+    }
+
+    println!(
+        "Checked links: {} ok, {} missing (dead after another check), {} dead, {} transient failures, {} rate limited",
+        ok, missing, dead, transient, rate_limited
+    );
+    if !moved.is_empty() {
+        println!("Moved ({}, recorded by the next refresh):", moved.len());
+        for (from, to) in &moved {
+            println!("  {} -> {}", from, to);
+        }
+    }
+
+    Ok(())
+}
+
+// HEAD, or GET for servers that refuse HEAD. Returns the status and the URL the
+// redirects ended at.
+async fn check_link(client: &Client, url: &str) -> reqwest::Result<(StatusCode, String)> {
+    let mut response = client.head(url).send().await?;
+
+    if matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED | StatusCode::FORBIDDEN
+    ) {
+        response = client.get(url).send().await?;
+    }
+
+    Ok((response.status(), response.url().to_string()))
+}
+
+// Only 404 and 410 count towards dead; 401/403, 5xx and the like may be temporary
+fn classify(status: StatusCode) -> LinkCheck {
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => LinkCheck::Missing,
+        status if status.is_success() || status.is_redirection() => LinkCheck::Ok,
+        _ => LinkCheck::Transient,
+    }
+}
@@ -1,43 +1,394 @@
-mod blog;
-mod config;
-mod db;
-mod export;
-
 use anyhow::Result;
-use rusqlite::Connection;
+use chrono::{Duration, Utc};
+use std::io::IsTerminal;
+use std::rc::Rc;
+use tracing::{Instrument, error, error_span, info};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use michi_matome_crawler::{
+    blog, check_failed_sources, check_quality,
+    config::{self, ExportFormat},
+    crawl, db, deliver_notifications, dry_run, export, export_options, finish_run, import, inspect,
+    links, logging, notify_new_items, prune, quality_breaches, quality_report, rescore, run_span,
+    search, stats, thumbnails, top, trace, write_export,
+};
+
+const EXPORT_USAGE: &str =
+    "export <config.json> [--out path] [--format json|ndjson|csv] [--since-last [--also-full]]";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    init_logging(&mut args)?;
 
     if args.len() < 2 {
-        eprintln!("Usage: crawler <config.json>");
+        eprintln!("Usage: crawler [-v | -vv | -q] [--log-format text|json] <command> ...");
+        eprintln!("       crawler <config.json> [--dry-run]");
+        eprintln!("       crawler crawl <config.json> [--dry-run]");
+        eprintln!("       crawler {}", EXPORT_USAGE);
+        eprintln!("       crawler recrawl <config.json> <url>");
+        eprintln!("       crawler refresh <config.json> [--older-than days]");
+        eprintln!("       crawler check-links <config.json> [--limit N]");
+        eprintln!("       crawler notify <config.json> [--dry-run]");
+        eprintln!(
+            "       crawler prune --older-than <age> [--max-score N] [--source name] [--dry-run] [--hard]"
+        );
+        eprintln!("       crawler prune --queue --older-than <age>");
+        eprintln!("       crawler top [--limit N] [--type blog|youtube] [--since <age>] [--json]");
+        eprintln!("       crawler search <query> [--limit N] [--json]");
+        eprintln!("       crawler stats [--history] [--days N] [--runs N] [--json]");
+        eprintln!(
+            "       (prune, top, search and stats take --config config.json for its database)"
+        );
+        eprintln!("       crawler inspect <url> [--config config.json]");
+        eprintln!("       crawler trace <url> --config config.json");
+        eprintln!(
+            "       crawler rescore --compare <config.json> [--current config.json] [--top N] [--json out.json]"
+        );
+        eprintln!(
+            "       crawler config import-bookmarks <bookmarks.html> [--folder name] [--category c] [--merge config.json]"
+        );
+        eprintln!(
+            "       crawler config import-subscriptions <subscriptions.csv> [--category c] [--merge config.json]"
+        );
         std::process::exit(1);
     }
 
-    let config_path = &args[1];
+    // Maintenance subcommands only need the database: the one of --config, if given
+    if args[1] == "prune" {
+        let config = take_config(&mut args, "--config")?;
+        let mut conn = db::open(db_path(config.as_ref()))?;
+        db::init(&conn)?;
+        return prune::run(&mut conn, config.as_ref(), &args[2..]);
+    }
+
+    // top is a report: it never creates, migrates or writes the database
+    if args[1] == "top" {
+        let config = take_config(&mut args, "--config")?;
+        let conn = db::open_read_only(db_path(config.as_ref()))?;
+        return top::run(&conn, config.as_ref(), &args[2..]);
+    }
 
-    let config = config::load(config_path)?;
+    // Config helpers don't touch the database
+    if args[1] == "config" {
+        return import::run(&args[2..]);
+    }
 
-    println!("Crawler started");
+    if args[1] == "inspect" {
+        return inspect::run(&args[2..]).await;
+    }
+
+    if args[1] == "trace" {
+        let config = take_config(&mut args, "--config")?
+            .ok_or_else(|| anyhow::anyhow!("trace needs --config <config.json>"))?;
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+        return trace::run(&conn, &config, &args[2..]).await;
+    }
+
+    // The scores are compared on the database of the --current config
+    if args[1] == "rescore" {
+        let current = take_config(&mut args, "--current")?;
+        let conn = db::open(db_path(current.as_ref()))?;
+        db::init(&conn)?;
+        return rescore::run(&conn, current.as_ref(), &args[2..]);
+    }
+
+    if args[1] == "search" {
+        let config = take_config(&mut args, "--config")?;
+        let conn = db::open(db_path(config.as_ref()))?;
+        db::init(&conn)?;
+        return search::run(&conn, config.as_ref(), &args[2..]);
+    }
 
-    // Open SQLite database
-    let conn = Connection::open("crawler.db")?;
+    if args[1] == "stats" {
+        let config = take_config(&mut args, "--config")?;
+        let conn = db::open(db_path(config.as_ref()))?;
+        db::init(&conn)?;
+        return stats::run(&conn, &args[2..]);
+    }
+
+    if args[1] == "crawl" {
+        let mut config = load_config(&args, "crawl <config.json> [--dry-run]")?;
+        if dry_run_flag(&args[3..])? {
+            config.dry_run = true;
+            return dry_run(config).await;
+        }
+        let config = Rc::new(config);
+        info!("Crawler started");
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+
+        return async {
+            let (run_started_at, reports) = crawl(&conn, Rc::clone(&config)).await?;
+            let quality = quality_report(&conn, run_started_at)?;
+            let quality_breaches = quality_breaches(&quality, &config.quality_thresholds);
+            export::export_summary(
+                &config.summary_path,
+                run_started_at,
+                &reports,
+                &export::Withheld::default(),
+                &quality,
+                &quality_breaches,
+            )?;
+            notify_new_items(&conn, &config, run_started_at).await?;
+            finish_run(&conn, run_started_at, &reports)?;
+            check_failed_sources(&reports, config.max_failed_sources)?;
+            check_quality(&quality_breaches)
+        }
+        .instrument(run_span())
+        .await;
+    }
+
+    // Regenerate the export from the database, e.g. after changing scoring
+    if args[1] == "export" {
+        let mut config = load_config(&args, EXPORT_USAGE)?;
+        let mut options = args[3..].iter();
+        while let Some(arg) = options.next() {
+            match arg.as_str() {
+                "--out" => {
+                    config.output_path = options
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("--out needs a value"))?
+                        .clone()
+                }
+                "--format" => {
+                    let value = options
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("--format needs a value"))?;
+                    config.export.format = Some(
+                        ExportFormat::parse(value)
+                            .ok_or_else(|| anyhow::anyhow!("Invalid --format: {}", value))?,
+                    );
+                }
+                "--since-last" => config.export.incremental = true,
+                "--also-full" => config.export.also_full = true,
+                other => anyhow::bail!("Unknown export option: {}", other),
+            }
+        }
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+
+        if let Some(check) = &config.thumbnail_check {
+            thumbnails::verify(&conn, &config, check).await?;
+        }
+        write_export(&conn, &config, &export_options(&config, Utc::now()))?;
+        return Ok(());
+    }
+
+    // Retry the webhook notifications still queued; --dry-run lists them instead
+    if args[1] == "notify" {
+        let config = load_config(&args, "notify <config.json> [--dry-run]")?;
+        let dry_run = match args.get(3).map(String::as_str) {
+            Some("--dry-run") => true,
+            Some(other) => anyhow::bail!("Unknown notify option: {}", other),
+            None => false,
+        };
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+
+        if dry_run {
+            let pending = db::pending_notifications(&conn)?;
+            if pending.is_empty() {
+                println!("No queued notifications");
+            }
+            for (id, queued_at) in &pending {
+                println!("{}  {}", queued_at, id);
+            }
+            return Ok(());
+        }
 
-    // Initialize tables
+        let hook = config
+            .webhook
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No webhook configured"))?;
+        return deliver_notifications(&conn, &config, hook).await;
+    }
+
+    if args[1] == "recrawl" {
+        let config = load_config(&args, "recrawl <config.json> <url>")?;
+        let url = args
+            .get(3)
+            .ok_or_else(|| anyhow::anyhow!("Usage: crawler recrawl <config.json> <url>"))?;
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+
+        if !blog::recrawl(&conn, &config, url).await? {
+            info!("Nothing stored for {}", url);
+        }
+        return Ok(());
+    }
+
+    if args[1] == "check-links" {
+        let config = load_config(&args, "check-links <config.json> [--limit N]")?;
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+        return links::run(&conn, &config, &args[3..]).await;
+    }
+
+    if args[1] == "refresh" {
+        let config = load_config(&args, "refresh <config.json> [--older-than days]")?;
+        let days = match args.get(3).map(String::as_str) {
+            Some("--older-than") => {
+                let value = args
+                    .get(4)
+                    .ok_or_else(|| anyhow::anyhow!("--older-than needs a value"))?;
+                value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid --older-than: {}", value))?
+            }
+            Some(other) => anyhow::bail!("Unknown refresh option: {}", other),
+            None => config.refresh_after_days,
+        };
+        let conn = db::open(&config.db_path)?;
+        db::init(&conn)?;
+
+        let cache = Rc::new(db::RunCache::load(&conn)?);
+        let stale_before = Utc::now() - Duration::days(days);
+        async {
+            let (mut refreshed, mut inserted, mut moved) = (0, 0, Vec::new());
+            for blog_cfg in &config.blogs {
+                let refresh =
+                    blog::refresh(&conn, &config, blog_cfg, Rc::clone(&cache), stale_before)
+                        .instrument(
+                            error_span!("blog", source = %blog_cfg.name, url = %blog_cfg.url),
+                        );
+                match refresh.await {
+                    Ok(report) => {
+                        refreshed += report.refreshed;
+                        inserted += report.inserted;
+                        moved.extend(report.moved);
+                    }
+                    Err(e) => error!("Source error: {}: {}", blog_cfg.name, e),
+                }
+            }
+            info!(
+                "Refreshed {} articles, {} new, {} moved",
+                refreshed,
+                inserted,
+                moved.len()
+            );
+            for article in &moved {
+                info!("Moved: {} -> {}", article.from, article.to);
+            }
+        }
+        .instrument(run_span())
+        .await;
+        return Ok(());
+    }
+
+    // No subcommand: crawl, then export (the cron job's invocation)
+    let config_path = &args[1];
+
+    let mut config = config::load(config_path)?;
+    if dry_run_flag(&args[2..])? {
+        config.dry_run = true;
+        return dry_run(config).await;
+    }
+
+    let conn = db::open(&config.db_path)?;
     db::init(&conn)?;
 
-    // === Blogs ===
-    for blog_cfg in config.blogs {
-        if let Err(e) = blog::fetch_and_store(&conn, &blog_cfg.url).await {
-            eprintln!("Blog error: {e}");
+    let max_failed_sources = config.max_failed_sources;
+    let report = michi_matome_crawler::run(config, &conn).await?;
+    check_failed_sources(&report.sources, max_failed_sources)?;
+    check_quality(&report.quality_breaches)
+}
+
+// Global flags, removed from args: -v / -vv for debug / trace output, -q for
+// warnings and errors only, --log-format json (or --log-json) for one JSON object per
+// line, e.g. under systemd; without the flag the config's log_format applies. RUST_LOG
+// overrides the level. Logs go to stderr, command output (top, stats, ...) to stdout.
+fn init_logging(args: &mut Vec<String>) -> Result<()> {
+    let mut verbosity = 0;
+    let mut format = None;
+    let mut rest = Vec::new();
+    let mut flags = std::mem::take(args).into_iter();
+    while let Some(arg) = flags.next() {
+        match arg.as_str() {
+            "-v" => verbosity += 1,
+            "-vv" => verbosity += 2,
+            "-q" => verbosity -= 1,
+            "--log-json" => format = Some(config::LogFormat::Json),
+            "--log-format" => {
+                format = Some(match flags.next().as_deref() {
+                    Some("json") => config::LogFormat::Json,
+                    Some("text") => config::LogFormat::Text,
+                    other => anyhow::bail!("Invalid --log-format: {:?} (json or text)", other),
+                })
+            }
+            _ => rest.push(arg),
         }
     }
+    *args = rest;
 
-    // === Export JSON ===
-    export::export_json(&conn, "index.json")?;
+    // The config path is args[1] without a subcommand, args[2] with one
+    let format = format
+        .or_else(|| {
+            args.iter()
+                .skip(1)
+                .take(2)
+                .find_map(|path| config::log_format(path))
+        })
+        .unwrap_or_default();
 
-    println!("Crawler finished");
+    let level = match verbosity {
+        ..=-1 => "warn",
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,michi_matome_crawler={}", level)));
 
+    match format {
+        config::LogFormat::Json => tracing_subscriber::registry()
+            .with(filter)
+            .with(logging::JsonLayer::new(std::io::stderr))
+            .init(),
+        config::LogFormat::Text => tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_target(false)
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_writer(std::io::stderr)
+            .init(),
+    }
     Ok(())
 }
+
+// args[2] as the config path of a subcommand
+fn load_config(args: &[String], usage: &str) -> Result<config::Config> {
+    let path = args
+        .get(2)
+        .ok_or_else(|| anyhow::anyhow!("Usage: crawler {}", usage))?;
+    config::load(path)
+}
+
+// Removes `<flag> <config.json>` from a subcommand's options and loads that config
+fn take_config(args: &mut Vec<String>, flag: &str) -> Result<Option<config::Config>> {
+    let Some(index) = args.iter().skip(2).position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let index = index + 2;
+    let path = args
+        .get(index + 1)
+        .ok_or_else(|| anyhow::anyhow!("{} needs a value", flag))?
+        .clone();
+    args.drain(index..=index + 1);
+    config::load(&path).map(Some)
+}
+
+fn db_path(config: Option<&config::Config>) -> &str {
+    config.map_or(config::DEFAULT_DB_PATH, |config| &config.db_path)
+}
+
+// The options after a crawl's config path: nothing, or --dry-run
+fn dry_run_flag(options: &[String]) -> Result<bool> {
+    match options {
+        [] => Ok(false),
+        [flag] if flag == "--dry-run" => Ok(true),
+        [other, ..] => anyhow::bail!("Unknown crawl option: {}", other),
+    }
+}
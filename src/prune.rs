@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+use crate::classify::TermMatcher;
+use crate::config::{Config, ScoringConfig};
+use crate::db;
+use crate::export::calculate_score;
+
+// Options for: crawler prune --older-than 365d [--max-score 0] [--source name] [--dry-run] [--hard] [--config c.json]
+//              crawler prune --queue --older-than 90d
+struct PruneOptions {
+    older_than: Duration,
+    max_score: i32,
+    source: Option<String>,
+    dry_run: bool,
+    hard: bool,
+    // Prune the crawl queue and error backoffs instead of contents
+    queue: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<PruneOptions> {
+    let mut older_than = None;
+    let mut max_score = 0;
+    let mut source = None;
+    let mut dry_run = false;
+    let mut hard = false;
+    let mut queue = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--older-than" => {
+                let value = args.next().context("--older-than needs a value")?;
+                older_than = Some(parse_age(value)?);
+            }
+            "--max-score" => {
+                let value = args.next().context("--max-score needs a value")?;
+                max_score = value
+                    .parse()
+                    .with_context(|| format!("Invalid --max-score: {}", value))?;
+            }
+            "--source" => {
+                source = Some(args.next().context("--source needs a value")?.clone());
+            }
+            "--dry-run" => dry_run = true,
+            "--hard" => hard = true,
+            "--queue" => queue = true,
+            other => anyhow::bail!("Unknown prune option: {}", other),
+        }
+    }
+
+    if queue && (source.is_some() || dry_run || hard) {
+        anyhow::bail!("--source, --dry-run and --hard don't apply to prune --queue");
+    }
+
+    Ok(PruneOptions {
+        older_than: older_than.context("prune requires --older-than (e.g. 365d)")?,
+        max_score,
+        source,
+        dry_run,
+        hard,
+        queue,
+    })
+}
+
+// Accepts "365d" or "48h"
+pub fn parse_age(value: &str) -> Result<Duration> {
+    let (number, to_duration): (&str, fn(i64) -> Option<Duration>) =
+        if let Some(number) = value.strip_suffix('d') {
+            (number, Duration::try_days)
+        } else if let Some(number) = value.strip_suffix('h') {
+            (number, Duration::try_hours)
+        } else {
+            anyhow::bail!("Invalid age unit in {} (use d or h)", value);
+        };
+
+    let number: i64 = number
+        .parse()
+        .with_context(|| format!("Invalid age: {}", value))?;
+
+    to_duration(number).with_context(|| format!("Age out of range: {}", value))
+}
+
+// The moment `age` ago; Err instead of an overflow for absurd ages
+pub fn age_cutoff(age: Duration) -> Result<DateTime<Utc>> {
+    Utc::now()
+        .checked_sub_signed(age)
+        .context("Age out of range")
+}
+
+// Entry point
+pub fn run(conn: &mut Connection, config: Option<&Config>, args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+    if options.queue {
+        return prune_queue(conn, &options);
+    }
+    let cutoff = age_cutoff(options.older_than)?;
+
+    // Without --config the default scoring rules apply
+    let scoring = config.map_or_else(ScoringConfig::default, |config| config.scoring.clone());
+    let terms = TermMatcher::new(&scoring)?;
+
+    let mut matched = Vec::new();
+
+    for item in db::fetch_all(conn, true)? {
+        if let Some(ref name) = options.source
+            && item.source.as_ref() != Some(name)
+        {
+            continue;
+        }
+
+        // Items we can't date are never pruned
+        let dated = item.published_at.as_deref().unwrap_or(&item.fetched_at);
+        let Ok(date) = DateTime::parse_from_rfc3339(dated) else {
+            continue;
+        };
+        let date = date.with_timezone(&Utc);
+
+        if date > cutoff {
+            continue;
+        }
+
+        let score = calculate_score(&item, &scoring, &terms);
+        if score > options.max_score {
+            continue;
+        }
+
+        matched.push((item, score, Utc::now() - date));
+    }
+
+    let mut per_source: BTreeMap<String, usize> = BTreeMap::new();
+
+    for (item, score, age) in &matched {
+        let source = item
+            .source
+            .clone()
+            .unwrap_or_else(|| "(unknown)".to_string());
+        *per_source.entry(source).or_default() += 1;
+
+        if options.dry_run {
+            println!(
+                "{:>4}  {:>5}d  {}  {}",
+                score,
+                age.num_days(),
+                item.title.trim(),
+                item.url
+            );
+        }
+    }
+
+    let ids: Vec<String> = matched.into_iter().map(|(item, _, _)| item.id).collect();
+
+    let verb = if options.dry_run {
+        "Would remove"
+    } else {
+        let removed = if options.hard {
+            db::hard_delete(conn, &ids)?
+        } else {
+            db::soft_delete(conn, &ids)?
+        };
+        println!("Removed {} contents", removed);
+        "Removed"
+    };
+
+    for (source, count) in per_source {
+        println!("{} {} from {}", verb, count, source);
+    }
+
+    Ok(())
+}
+
+fn prune_queue(conn: &mut Connection, options: &PruneOptions) -> Result<()> {
+    // db::prune computes its cutoff the same way
+    age_cutoff(options.older_than)?;
+    let before = db::size_bytes(conn)?;
+    let removed = db::prune(conn, options.older_than.num_days())?;
+    let after = db::size_bytes(conn)?;
+
+    println!("Removed {} done queue rows", removed.queue_done);
+    println!("Removed {} expired error entries", removed.error_sites);
+    println!(
+        "Database size: {:.1} MiB -> {:.1} MiB",
+        before as f64 / (1024.0 * 1024.0),
+        after as f64 / (1024.0 * 1024.0)
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_age_units() {
+        assert_eq!(parse_age("365d").unwrap(), Duration::days(365));
+        assert_eq!(parse_age("48h").unwrap(), Duration::hours(48));
+    }
+
+    #[test]
+    fn parse_age_rejects_bad_input_without_panicking() {
+        for value in [
+            "365日",
+            "日",
+            "",
+            "d",
+            "12",
+            "10w",
+            "-",
+            "99999999999999999d",
+        ] {
+            assert!(parse_age(value).is_err(), "{:?} should be rejected", value);
+        }
+        assert!(age_cutoff(Duration::MAX).is_err());
+    }
+}
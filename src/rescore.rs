@@ -0,0 +1,253 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use crate::classify::TermMatcher;
+use crate::config::{self, Config, ScoringConfig};
+use crate::db;
+use crate::export::score_breakdown;
+
+// Largest score changes listed in the report
+const MOVERS: usize = 20;
+
+// Options for: crawler rescore --compare new.json [--current config.json] [--top 50] [--json out.json]
+// (--current is loaded by the caller, which also opens its database)
+struct RescoreOptions {
+    compare: String,
+    top: usize,
+    json: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<RescoreOptions> {
+    let mut compare = None;
+    let mut options = RescoreOptions {
+        compare: String::new(),
+        top: 50,
+        json: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compare" => compare = Some(args.next().context("--compare needs a value")?.clone()),
+            "--top" => {
+                let value = args.next().context("--top needs a value")?;
+                options.top = value
+                    .parse()
+                    .with_context(|| format!("Invalid --top: {}", value))?;
+            }
+            "--json" => options.json = Some(args.next().context("--json needs a value")?.clone()),
+            other => anyhow::bail!("Unknown rescore option: {}", other),
+        }
+    }
+
+    options.compare = compare.context("rescore needs --compare <config.json>")?;
+    Ok(options)
+}
+
+#[derive(Debug, Serialize)]
+struct RescoreReport {
+    top: usize,
+    items: usize,
+    // Items crossing the top-N boundary, in their new / old rank order
+    entering: Vec<Mover>,
+    leaving: Vec<Mover>,
+    // Largest absolute score changes
+    movers: Vec<Mover>,
+    current: Distribution,
+    candidate: Distribution,
+}
+
+#[derive(Debug, Serialize)]
+struct Mover {
+    id: String,
+    title: String,
+    old_score: i32,
+    new_score: i32,
+    old_rank: usize,
+    new_rank: usize,
+    // Rule -> change in its contribution, only for rules that differ
+    components: BTreeMap<String, i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct Distribution {
+    min: i32,
+    max: i32,
+    mean: f64,
+    median: i32,
+    p90: i32,
+    positive: usize,
+}
+
+// One item scored under one rule set
+struct Scored {
+    score: i32,
+    parts: Vec<(&'static str, i32)>,
+}
+
+// Entry point. Read-only: nothing is written to the database.
+pub fn run(conn: &Connection, current: Option<&Config>, args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+
+    // Without --current the rules are the app defaults, as used by prune and top
+    let current = current.map_or_else(ScoringConfig::default, |config| config.scoring.clone());
+    let candidate = config::load(&options.compare)?.scoring;
+
+    let items = db::fetch_all(conn, false)?;
+    let old = score_all(&items, &current)?;
+    let new = score_all(&items, &candidate)?;
+
+    let old_ranks = ranks(&old);
+    let new_ranks = ranks(&new);
+
+    let mover = |index: usize| {
+        let mut components: BTreeMap<String, i32> = BTreeMap::new();
+        for (rule, points) in &new[index].parts {
+            *components.entry(rule.to_string()).or_default() += points;
+        }
+        for (rule, points) in &old[index].parts {
+            *components.entry(rule.to_string()).or_default() -= points;
+        }
+        components.retain(|_, delta| *delta != 0);
+
+        Mover {
+            id: items[index].id.clone(),
+            title: items[index].title.clone(),
+            old_score: old[index].score,
+            new_score: new[index].score,
+            old_rank: old_ranks[index],
+            new_rank: new_ranks[index],
+            components,
+        }
+    };
+
+    let mut entering: Vec<usize> = (0..items.len())
+        .filter(|&i| new_ranks[i] <= options.top && old_ranks[i] > options.top)
+        .collect();
+    entering.sort_by_key(|&i| new_ranks[i]);
+
+    let mut leaving: Vec<usize> = (0..items.len())
+        .filter(|&i| old_ranks[i] <= options.top && new_ranks[i] > options.top)
+        .collect();
+    leaving.sort_by_key(|&i| old_ranks[i]);
+
+    let mut changed: Vec<usize> = (0..items.len())
+        .filter(|&i| old[i].score != new[i].score)
+        .collect();
+    changed.sort_by_key(|&i| std::cmp::Reverse((new[i].score - old[i].score).abs()));
+    changed.truncate(MOVERS);
+
+    let report = RescoreReport {
+        top: options.top,
+        items: items.len(),
+        entering: entering.into_iter().map(mover).collect(),
+        leaving: leaving.into_iter().map(mover).collect(),
+        movers: changed.into_iter().map(mover).collect(),
+        current: distribution(&old),
+        candidate: distribution(&new),
+    };
+
+    if let Some(path) = &options.json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("Wrote rescore report to {}", path);
+        return Ok(());
+    }
+
+    print_report(&report);
+    Ok(())
+}
+
+fn score_all(items: &[db::Content], scoring: &ScoringConfig) -> Result<Vec<Scored>> {
+    let terms = TermMatcher::new(scoring)?;
+
+    Ok(items
+        .iter()
+        .map(|item| {
+            let parts = score_breakdown(item, scoring, &terms);
+            Scored {
+                score: parts.iter().map(|(_, points)| points).sum(),
+                parts,
+            }
+        })
+        .collect())
+}
+
+// 1-based rank of each item, ordered like the export (score desc, ties keep row order)
+fn ranks(scored: &[Scored]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..scored.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(scored[i].score));
+
+    let positions: HashMap<usize, usize> = order
+        .into_iter()
+        .enumerate()
+        .map(|(rank, index)| (index, rank + 1))
+        .collect();
+
+    (0..scored.len()).map(|i| positions[&i]).collect()
+}
+
+fn distribution(scored: &[Scored]) -> Distribution {
+    let mut scores: Vec<i32> = scored.iter().map(|s| s.score).collect();
+    scores.sort_unstable();
+
+    let at = |fraction: f64| {
+        let index = ((scores.len() as f64 - 1.0) * fraction).round() as usize;
+        scores.get(index).copied().unwrap_or(0)
+    };
+
+    Distribution {
+        min: scores.first().copied().unwrap_or(0),
+        max: scores.last().copied().unwrap_or(0),
+        mean: if scores.is_empty() {
+            0.0
+        } else {
+            scores.iter().map(|&s| s as f64).sum::<f64>() / scores.len() as f64
+        },
+        median: at(0.5),
+        p90: at(0.9),
+        positive: scores.iter().filter(|&&s| s > 0).count(),
+    }
+}
+
+fn print_report(report: &RescoreReport) {
+    println!("Rescored {} items; top {}", report.items, report.top);
+
+    let print_movers = |heading: &str, movers: &[Mover]| {
+        println!();
+        println!("{} ({})", heading, movers.len());
+        for m in movers {
+            let components: Vec<String> = m
+                .components
+                .iter()
+                .map(|(rule, delta)| format!("{} {:+}", rule, delta))
+                .collect();
+            println!(
+                "  #{:<4} -> #{:<4} {:>4} -> {:<4} {}  [{}]",
+                m.old_rank,
+                m.new_rank,
+                m.old_score,
+                m.new_score,
+                m.title.trim(),
+                components.join(", ")
+            );
+        }
+    };
+
+    print_movers("Entering top", &report.entering);
+    print_movers("Leaving top", &report.leaving);
+    print_movers("Largest score changes", &report.movers);
+
+    println!();
+    for (label, d) in [
+        ("current", &report.current),
+        ("candidate", &report.candidate),
+    ] {
+        println!(
+            "{:<9}  min {:>3}  median {:>3}  p90 {:>3}  max {:>3}  mean {:>6.2}  positive {}",
+            label, d.min, d.median, d.p90, d.max, d.mean, d.positive
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use reqwest::Client;
+use std::time::Duration;
+use url::Url;
+
+// Product token matched against robots.txt User-agent lines
+pub const AGENT: &str = "michi_matome_crawler";
+// Crawl-delay values above this are clamped; some sites declare hours
+const MAX_CRAWL_DELAY_SECS: f64 = 60.0;
+
+// Allow/Disallow rules of the groups that apply to us
+#[derive(Debug, Default)]
+pub struct Robots {
+    // (allow, path pattern)
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+    // Sitemap: lines, which belong to no group
+    pub sitemaps: Vec<String>,
+}
+
+impl Robots {
+    pub fn allow_all() -> Self {
+        Robots::default()
+    }
+
+    // Groups naming our agent win over `*`; several matching groups are merged
+    pub fn parse(text: &str) -> Self {
+        let mut ours = Robots::default();
+        let mut wildcard = Robots::default();
+        let mut matched_ours = false;
+
+        // Agents of the group being read, and whether a rule line closed its header
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        let mut sitemaps: Vec<String> = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            if key == "sitemap" {
+                if !value.is_empty() && !sitemaps.iter().any(|s| s == value) {
+                    sitemaps.push(value.to_string());
+                }
+                continue;
+            }
+
+            in_rules = true;
+            let for_us = agents.iter().any(|a| a.split('/').next() == Some(AGENT));
+            let for_all = agents.iter().any(|a| a == "*");
+            if !for_us && !for_all {
+                continue;
+            }
+            matched_ours |= for_us;
+            let target = if for_us { &mut ours } else { &mut wildcard };
+
+            match key.as_str() {
+                // An empty Disallow allows everything
+                "disallow" if !value.is_empty() => target.rules.push((false, value.to_string())),
+                "allow" if !value.is_empty() => target.rules.push((true, value.to_string())),
+                "crawl-delay" => {
+                    if let Ok(secs) = value.parse::<f64>()
+                        && secs > 0.0
+                    {
+                        target.crawl_delay =
+                            Some(Duration::from_secs_f64(secs.min(MAX_CRAWL_DELAY_SECS)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut robots = if matched_ours { ours } else { wildcard };
+        robots.sitemaps = sitemaps;
+        robots
+    }
+
+    // Longest matching pattern decides; Allow wins a tie
+    pub fn allows(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return true;
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if !pattern_matches(pattern, &path) {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some((len, best_allow)) => {
+                    pattern.len() > len || (pattern.len() == len && *allow && !best_allow)
+                }
+            };
+            if better {
+                best = Some((pattern.len(), *allow));
+            }
+        }
+
+        best.is_none_or(|(_, allow)| allow)
+    }
+}
+
+// Err only for a 5xx: the host is not crawled this run. A missing or unreachable
+// robots.txt allows everything.
+pub async fn fetch(client: &Client, base_url: &str) -> Result<Robots> {
+    let robots_url = format!("{}/robots.txt", base_url.trim_end_matches('/'));
+
+    let response = match client.get(&robots_url).send().await {
+        Ok(response) => response,
+        Err(_) => return Ok(Robots::allow_all()),
+    };
+
+    if response.status().is_server_error() {
+        anyhow::bail!("robots.txt returned {}", response.status());
+    }
+    if !response.status().is_success() {
+        return Ok(Robots::allow_all());
+    }
+
+    Ok(Robots::parse(&response.text().await.unwrap_or_default()))
+}
+
+// Prefix match with `*` wildcards and an optional `$` end anchor
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let Some(mut rest) = path.strip_prefix(parts[0]) else {
+        return false;
+    };
+
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        // The wildcard before an anchored last part can absorb anything
+        if anchored && index == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
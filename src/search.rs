@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::classify::TermMatcher;
+use crate::config::{Config, ScoringConfig};
+use crate::db;
+use crate::export::calculate_score;
+use crate::top::{TITLE_WIDTH, pad};
+
+// Options for: crawler search <query> [--limit 20] [--json] [--config c.json]
+struct SearchOptions {
+    query: String,
+    limit: usize,
+    json: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<SearchOptions> {
+    let mut args = args.iter();
+    let mut options = SearchOptions {
+        query: args.next().context("search needs a query")?.clone(),
+        limit: 20,
+        json: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                let value = args.next().context("--limit needs a value")?;
+                options.limit = value
+                    .parse()
+                    .with_context(|| format!("Invalid --limit: {}", value))?;
+            }
+            "--json" => options.json = true,
+            other => anyhow::bail!("Unknown search option: {}", other),
+        }
+    }
+
+    Ok(options)
+}
+
+#[derive(Debug, Serialize)]
+struct Hit {
+    title: String,
+    url: String,
+    score: i32,
+    #[serde(rename = "type")]
+    content_type: String,
+    source: Option<String>,
+}
+
+// Entry point. Read-only: full-text matches in rank order, with their export score
+// under the --config rules, or the default ones.
+pub fn run(conn: &Connection, config: Option<&Config>, args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+
+    let scoring = config.map_or_else(ScoringConfig::default, |config| config.scoring.clone());
+    let terms = TermMatcher::new(&scoring)?;
+
+    let hits: Vec<Hit> = db::search(conn, &options.query, options.limit)?
+        .into_iter()
+        .map(|item| Hit {
+            score: calculate_score(&item, &scoring, &terms),
+            title: item.title,
+            url: item.url,
+            content_type: item.content_type,
+            source: item.source,
+        })
+        .collect();
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+
+    if hits.is_empty() {
+        println!("No matches for {:?}", options.query);
+        return Ok(());
+    }
+
+    println!(
+        "{:>4} {:>5}  {}  URL",
+        "#",
+        "SCORE",
+        pad("TITLE", TITLE_WIDTH)
+    );
+    for (rank, hit) in hits.iter().enumerate() {
+        println!(
+            "{:>4} {:>5}  {}  {}",
+            rank + 1,
+            hit.score,
+            pad(hit.title.trim(), TITLE_WIDTH),
+            hit.url
+        );
+    }
+
+    Ok(())
+}
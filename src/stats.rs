@@ -0,0 +1,502 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::{self, CrawlRun, ExtractionRates, HttpsProbe, StatsSample};
+
+const SPARK: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// Recorded runs the per-source extraction rates are averaged over
+const RATE_RUNS: usize = 10;
+
+// Options for: crawler stats [--history] [--days 30] [--runs N] [--json]
+struct StatsOptions {
+    history: bool,
+    days: i64,
+    // Show the last N crawl runs instead of the counts
+    runs: Option<usize>,
+    json: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<StatsOptions> {
+    let mut options = StatsOptions {
+        history: false,
+        days: 30,
+        runs: None,
+        json: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => options.history = true,
+            "--days" => {
+                let value = args.next().context("--days needs a value")?;
+                options.days = value
+                    .parse()
+                    .with_context(|| format!("Invalid --days: {}", value))?;
+            }
+            "--runs" => {
+                let value = args.next().context("--runs needs a value")?;
+                options.runs = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid --runs: {}", value))?,
+                );
+            }
+            "--json" => options.json = true,
+            other => anyhow::bail!("Unknown stats option: {}", other),
+        }
+    }
+
+    Ok(options)
+}
+
+// Entry point. Read-only: samples are recorded by crawl runs.
+pub fn run(conn: &Connection, args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+
+    if let Some(limit) = options.runs {
+        return print_runs(conn, limit, options.json);
+    }
+
+    if !options.history {
+        let sample = db::current_stats(conn)?;
+        let probes = db::https_probes(conn)?;
+        let rates = source_rates(&db::recent_runs(conn, RATE_RUNS)?);
+        if options.json {
+            let current = Current {
+                sample: &sample,
+                https_probes: probes
+                    .iter()
+                    .map(|(source, probe)| SourceProbe::new(source, probe))
+                    .collect(),
+                extraction_rates: &rates,
+            };
+            println!("{}", serde_json::to_string_pretty(&current)?);
+        } else {
+            println!(
+                "Queue: {} pending, {} done, {} error",
+                sample.queue_pending, sample.queue_done, sample.queue_error
+            );
+            println!(
+                "Contents: {} blog, {} youtube",
+                sample.contents_blog, sample.contents_youtube
+            );
+            println!("Error sites: {}", sample.error_sites);
+            println!(
+                "Full text: {} pages, {} KiB compressed",
+                sample.fulltext_rows,
+                sample.fulltext_bytes / 1024
+            );
+            println!("Revisions: {}", sample.revisions);
+            if !probes.is_empty() {
+                println!("HTTPS probes:");
+                for (source, probe) in &probes {
+                    println!("  {}", probe_line(source, probe));
+                }
+            }
+            if !rates.is_empty() {
+                println!("Extraction rates (last {} runs):", RATE_RUNS);
+                for (source, rates) in &rates {
+                    println!("  {}", rates_line(source, rates));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let since = Utc::now() - Duration::days(options.days);
+    let samples = db::stats_history(conn, &since.to_rfc3339())?;
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&samples)?);
+        return Ok(());
+    }
+
+    if samples.is_empty() {
+        println!("No samples in the last {} days", options.days);
+        return Ok(());
+    }
+
+    let spark = sparkline(&samples);
+    println!(
+        "{:<16}  {:>7} {:>7} {:>6}  {:>7} {:>7}  {:>7}  CONTENTS",
+        "SAMPLED", "PENDING", "DONE", "ERROR", "BLOG", "YOUTUBE", "ERRSITE"
+    );
+
+    for (sample, bar) in samples.iter().zip(spark) {
+        let sampled_at = sample.sampled_at.replace('T', " ");
+        println!(
+            "{:<16}  {:>7} {:>7} {:>6}  {:>7} {:>7}  {:>7}  {}",
+            sampled_at.chars().take(16).collect::<String>(),
+            sample.queue_pending,
+            sample.queue_done,
+            sample.queue_error,
+            sample.contents_blog,
+            sample.contents_youtube,
+            sample.error_sites,
+            bar
+        );
+    }
+
+    Ok(())
+}
+
+// stats --json without --history: the counts, plus each http source's probe result
+#[derive(Serialize)]
+struct Current<'a> {
+    #[serde(flatten)]
+    sample: &'a StatsSample,
+    https_probes: Vec<SourceProbe<'a>>,
+    extraction_rates: &'a BTreeMap<String, SourceRates>,
+}
+
+#[derive(Serialize)]
+struct SourceProbe<'a> {
+    source: &'a str,
+    status: &'a str,
+    ok: bool,
+    checked_at: String,
+    note: Option<&'a str>,
+}
+
+impl<'a> SourceProbe<'a> {
+    fn new(source: &'a str, probe: &'a HttpsProbe) -> Self {
+        SourceProbe {
+            source,
+            status: &probe.status,
+            ok: probe.ok,
+            checked_at: probe.checked_at.to_rfc3339(),
+            note: probe.note.as_deref(),
+        }
+    }
+}
+
+fn probe_line(source: &str, probe: &HttpsProbe) -> String {
+    let result = match probe.status.as_str() {
+        "upgradeable" => "upgradeable, crawled over https".to_string(),
+        "http_only" => "http only".to_string(),
+        _ => "https broken, kept http".to_string(),
+    };
+    let checked = probe.checked_at.format("%Y-%m-%d");
+    match &probe.note {
+        Some(note) => format!("{}: {} ({}; checked {})", source, result, note, checked),
+        None => format!("{}: {} (checked {})", source, result, checked),
+    }
+}
+
+// A source's extraction rates averaged over the runs that measured them
+#[derive(Debug, PartialEq, Serialize)]
+struct SourceRates {
+    runs: usize,
+    #[serde(flatten)]
+    rates: ExtractionRates,
+}
+
+fn source_rates(runs: &[CrawlRun]) -> BTreeMap<String, SourceRates> {
+    let mut measured: BTreeMap<&str, Vec<ExtractionRates>> = BTreeMap::new();
+    for run in runs {
+        for (name, source) in &run.sources {
+            if let Some(rates) = source.extraction {
+                measured.entry(name).or_default().push(rates);
+            }
+        }
+    }
+
+    measured
+        .into_iter()
+        .map(|(name, rates)| {
+            let mean = |metric: fn(&ExtractionRates) -> f64| {
+                rates.iter().map(metric).sum::<f64>() / rates.len() as f64
+            };
+            let averaged = SourceRates {
+                runs: rates.len(),
+                rates: ExtractionRates {
+                    title: mean(|r| r.title),
+                    description: mean(|r| r.description),
+                    date: mean(|r| r.date),
+                    thumbnail: mean(|r| r.thumbnail),
+                },
+            };
+            (name.to_string(), averaged)
+        })
+        .collect()
+}
+
+fn rates_line(source: &str, rates: &SourceRates) -> String {
+    let percent = |rate: f64| (rate * 100.0).round() as i64;
+    format!(
+        "{}: title {}%, description {}%, date {}%, thumbnail {}% ({} {})",
+        source,
+        percent(rates.rates.title),
+        percent(rates.rates.description),
+        percent(rates.rates.date),
+        percent(rates.rates.thumbnail),
+        rates.runs,
+        if rates.runs == 1 { "run" } else { "runs" }
+    )
+}
+
+fn print_runs(conn: &Connection, limit: usize, json: bool) -> Result<()> {
+    let runs = db::recent_runs(conn, limit)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&runs)?);
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        println!("No crawl runs recorded");
+        return Ok(());
+    }
+
+    println!(
+        "{:<16}  {:>7} {:>7} {:>7} {:>6}  FAILED",
+        "STARTED", "FETCHED", "NEW", "SKIPPED", "ERRORS"
+    );
+    for run in &runs {
+        let failed: Vec<&str> = run
+            .sources
+            .iter()
+            .filter(|(_, source)| source.failure.is_some())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        println!(
+            "{:<16}  {:>7} {:>7} {:>7} {:>6}  {}",
+            run.started_at
+                .replace('T', " ")
+                .chars()
+                .take(16)
+                .collect::<String>(),
+            run.pages_fetched,
+            run.articles_inserted,
+            run.articles_skipped,
+            run.errors,
+            failed.join(", ")
+        );
+    }
+
+    // Where the latest run left each host's adapted request delay
+    let delays: Vec<String> = runs
+        .last()
+        .into_iter()
+        .flat_map(|run| run.sources.values())
+        .flat_map(|source| &source.host_delays_ms)
+        .map(|(host, delay)| format!("{} {} ms", host, delay))
+        .collect();
+    if !delays.is_empty() {
+        println!();
+        println!("Request delays: {}", delays.join(", "));
+    }
+
+    // A blog that still answers but whose new articles are no longer found
+    let idle: Vec<&str> = runs
+        .last()
+        .into_iter()
+        .flat_map(|run| run.sources.keys())
+        .filter(|name| {
+            runs.iter().all(|run| {
+                run.sources
+                    .get(name.as_str())
+                    .is_none_or(|source| source.inserted == 0)
+            })
+        })
+        .map(String::as_str)
+        .collect();
+    if runs.len() > 1 && !idle.is_empty() {
+        println!();
+        println!(
+            "No new articles in these {} runs: {}",
+            runs.len(),
+            idle.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+// One bar per sample for the total contents count, scaled to the window's range
+fn sparkline(samples: &[StatsSample]) -> Vec<char> {
+    let totals: Vec<i64> = samples
+        .iter()
+        .map(|s| s.contents_blog + s.contents_youtube)
+        .collect();
+    let min = totals.iter().copied().min().unwrap_or(0);
+    let max = totals.iter().copied().max().unwrap_or(0);
+    let range = (max - min).max(1) as f64;
+
+    totals
+        .iter()
+        .map(|total| {
+            let level = ((total - min) as f64 / range * (SPARK.len() - 1) as f64).round();
+            SPARK[level as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn https_probe_results_per_source() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        db::record_https_probe(&conn, "upgraded", "http://a.example/", "upgradeable", None)
+            .unwrap();
+        db::record_https_probe(
+            &conn,
+            "broken",
+            "http://b.example/",
+            "https_broken",
+            Some("https fetch failed: TLS error"),
+        )
+        .unwrap();
+        db::record_https_probe(
+            &conn,
+            "plain",
+            "http://c.example/",
+            "http_only",
+            Some("https fetch failed: connection refused"),
+        )
+        .unwrap();
+        // Registered but never probed (an https source): not listed
+        db::register_source(&conn, "secure", "https://d.example/", None).unwrap();
+
+        let probes = db::https_probes(&conn).unwrap();
+        let today = Utc::now().format("%Y-%m-%d");
+        let lines: Vec<String> = probes
+            .iter()
+            .map(|(source, probe)| probe_line(source, probe))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "broken: https broken, kept http (https fetch failed: TLS error; checked {})",
+                    today
+                ),
+                format!(
+                    "plain: http only (https fetch failed: connection refused; checked {})",
+                    today
+                ),
+                format!(
+                    "upgraded: upgradeable, crawled over https (checked {})",
+                    today
+                ),
+            ]
+        );
+
+        let sample = db::current_stats(&conn).unwrap();
+        let current = Current {
+            sample: &sample,
+            https_probes: probes
+                .iter()
+                .map(|(source, probe)| SourceProbe::new(source, probe))
+                .collect(),
+            extraction_rates: &BTreeMap::new(),
+        };
+        let json = serde_json::to_value(&current).unwrap();
+        assert_eq!(json["queue_pending"], 0);
+        let statuses: Vec<(&str, &str, bool)> = json["https_probes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|probe| {
+                (
+                    probe["source"].as_str().unwrap(),
+                    probe["status"].as_str().unwrap(),
+                    probe["ok"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("broken", "https_broken", false),
+                ("plain", "http_only", false),
+                ("upgraded", "upgradeable", true),
+            ]
+        );
+        assert_eq!(
+            json["https_probes"][1]["note"],
+            "https fetch failed: connection refused"
+        );
+    }
+
+    fn run(started_at: &str, sources: &[(&str, Option<ExtractionRates>)]) -> CrawlRun {
+        CrawlRun {
+            started_at: started_at.to_string(),
+            finished_at: started_at.to_string(),
+            pages_fetched: 0,
+            articles_inserted: 0,
+            articles_skipped: 0,
+            errors: 0,
+            sources: sources
+                .iter()
+                .map(|(name, extraction)| {
+                    let source = db::RunSource {
+                        extraction: *extraction,
+                        ..Default::default()
+                    };
+                    (name.to_string(), source)
+                })
+                .collect(),
+        }
+    }
+
+    fn rates(title: f64, description: f64, date: f64, thumbnail: f64) -> ExtractionRates {
+        ExtractionRates {
+            title,
+            description,
+            date,
+            thumbnail,
+        }
+    }
+
+    // Averaged per source over the recorded runs that measured it; a run without
+    // rates (too few new pages) doesn't pull the average down
+    #[test]
+    fn extraction_rates_per_source_from_recorded_runs() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let runs = [
+            run(
+                "2024-05-01T00:00:00+00:00",
+                &[
+                    ("a", Some(rates(1.0, 0.8, 1.0, 0.5))),
+                    ("b", Some(rates(1.0, 1.0, 1.0, 1.0))),
+                ],
+            ),
+            run(
+                "2024-05-02T00:00:00+00:00",
+                &[("a", Some(rates(1.0, 0.4, 0.0, 0.5))), ("b", None)],
+            ),
+            run("2024-05-03T00:00:00+00:00", &[("c", None)]),
+        ];
+        for run in &runs {
+            db::record_run(&conn, run).unwrap();
+        }
+
+        let by_source = source_rates(&db::recent_runs(&conn, RATE_RUNS).unwrap());
+        assert_eq!(by_source.keys().collect::<Vec<_>>(), ["a", "b"]);
+        let a = &by_source["a"];
+        assert_eq!(a.runs, 2);
+        assert!((a.rates.description - 0.6).abs() < 1e-9);
+        assert!((a.rates.date - 0.5).abs() < 1e-9);
+        assert_eq!(
+            rates_line("a", a),
+            "a: title 100%, description 60%, date 50%, thumbnail 50% (2 runs)"
+        );
+        assert_eq!(
+            rates_line("b", &by_source["b"]),
+            "b: title 100%, description 100%, date 100%, thumbnail 100% (1 run)"
+        );
+
+        let json = serde_json::to_value(&by_source).unwrap();
+        assert_eq!(json["a"]["runs"], 2);
+        assert_eq!(json["a"]["thumbnail"], 0.5);
+    }
+}
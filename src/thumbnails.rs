@@ -0,0 +1,150 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, StatusCode};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::blog;
+use crate::config::{Config, ThumbnailCheckConfig};
+use crate::db;
+
+#[derive(Debug)]
+enum Outcome {
+    Ok(StatusCode),
+    Broken(StatusCode),
+    // 5xx, 429 or no answer: neither cached nor held against the thumbnail
+    Unknown,
+}
+
+// Export-time pass (config thumbnail_check): requests the thumbnails of exportable
+// rows not checked within ttl_hours and records the outcomes in thumbnail_checks,
+// which the export reads. Hosts are checked `concurrency` at a time, each host's
+// images one after another `delay_ms` apart.
+pub async fn verify(
+    conn: &Connection,
+    config: &Config,
+    check: &ThumbnailCheckConfig,
+) -> Result<()> {
+    let checked_before = Utc::now() - Duration::hours(check.ttl_hours);
+    let urls = db::thumbnails_to_check(conn, &checked_before.to_rfc3339())?;
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    let mut by_host: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for url in urls {
+        match Url::parse(&url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+        {
+            Some(host) => by_host.entry(host).or_default().push(url),
+            None => warn!("Invalid thumbnail URL: {}", url),
+        }
+    }
+
+    let client = blog::shared_client(config)?;
+    let permits = Arc::new(Semaphore::new(check.concurrency.max(1)));
+    let delay = std::time::Duration::from_millis(check.delay_ms);
+    let mut tasks = Vec::new();
+    for urls in by_host.into_values() {
+        let client = client.clone();
+        let permits = Arc::clone(&permits);
+        tasks.push(tokio::spawn(async move {
+            let _permit = permits.acquire().await;
+            let mut outcomes = Vec::new();
+            for (index, url) in urls.into_iter().enumerate() {
+                if index > 0 {
+                    tokio::time::sleep(delay).await;
+                }
+                let outcome = check_thumbnail(&client, &url).await;
+                outcomes.push((url, outcome));
+            }
+            outcomes
+        }));
+    }
+
+    let (mut ok, mut broken, mut unknown) = (0, 0, 0);
+    let checked_at = Utc::now().to_rfc3339();
+    for task in tasks {
+        for (url, outcome) in task.await? {
+            match outcome {
+                Outcome::Ok(status) => {
+                    ok += 1;
+                    db::record_thumbnail_check(
+                        conn,
+                        &url,
+                        true,
+                        Some(status.as_u16()),
+                        &checked_at,
+                    )?;
+                }
+                Outcome::Broken(status) => {
+                    info!("Broken thumbnail: {} {}", status, url);
+                    broken += 1;
+                    db::record_thumbnail_check(
+                        conn,
+                        &url,
+                        false,
+                        Some(status.as_u16()),
+                        &checked_at,
+                    )?;
+                }
+                Outcome::Unknown => unknown += 1,
+            }
+        }
+    }
+
+    info!(
+        "Checked thumbnails: {} ok, {} broken, {} unanswered",
+        ok, broken, unknown
+    );
+    Ok(())
+}
+
+// HEAD, or GET for servers that refuse HEAD. An image URL that answers with a text
+// page (a hotlink placeholder, a login) counts as broken.
+async fn check_thumbnail(client: &Client, url: &str) -> Outcome {
+    let mut response = match client.head(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Thumbnail check failed: {}: {}", url, e);
+            return Outcome::Unknown;
+        }
+    };
+    if matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        response = match client.get(url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Thumbnail check failed: {}: {}", url, e);
+                return Outcome::Unknown;
+            }
+        };
+    }
+
+    let status = response.status();
+    if status.is_success() {
+        let not_an_image = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().to_ascii_lowercase().starts_with("text/"));
+        if not_an_image {
+            return Outcome::Broken(status);
+        }
+        return Outcome::Ok(status);
+    }
+
+    if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+        Outcome::Broken(status)
+    } else {
+        Outcome::Unknown
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use url::Url;
+
+use crate::config::{Config, ExportConfig, ScoringConfig};
+use crate::export::{self, ExportItem, ExportOptions};
+use crate::prune::{age_cutoff, parse_age};
+
+pub(crate) const TITLE_WIDTH: usize = 48;
+const SOURCE_WIDTH: usize = 20;
+
+// Options for: crawler top [--limit 20] [--type blog|youtube] [--since 7d] [--json] [--config c.json]
+struct TopOptions {
+    limit: usize,
+    content_type: Option<String>,
+    since: Option<DateTime<Utc>>,
+    json: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<TopOptions> {
+    let mut options = TopOptions {
+        limit: 20,
+        content_type: None,
+        since: None,
+        json: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                let value = args.next().context("--limit needs a value")?;
+                options.limit = value
+                    .parse()
+                    .with_context(|| format!("Invalid --limit: {}", value))?;
+            }
+            "--type" => {
+                options.content_type = Some(args.next().context("--type needs a value")?.clone());
+            }
+            "--since" => {
+                let value = args.next().context("--since needs a value")?;
+                options.since = Some(age_cutoff(parse_age(value)?)?);
+            }
+            "--json" => options.json = true,
+            other => anyhow::bail!("Unknown top option: {}", other),
+        }
+    }
+
+    Ok(options)
+}
+
+// Entry point. Read-only: scores items the same way as the export and prints them.
+// With --config its export settings apply (scoring, collapse_syndicated, takedowns,
+// ...), without one the defaults of a config that sets none of them.
+pub fn run(conn: &Connection, config: Option<&Config>, args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+
+    let export_options = match config {
+        Some(config) => crate::export_options(config, Utc::now()),
+        None => ExportOptions {
+            collapse_syndicated: false,
+            syndication_window: Duration::hours(72),
+            run_started_at: Utc::now(),
+            new_window: None,
+            include_discovered_from: false,
+            include_previous_title: false,
+            scoring: ScoringConfig::default(),
+            takedowns: Vec::new(),
+            limits: ExportConfig::default(),
+            dedupe_titles: true,
+            drop_broken_thumbnails: false,
+            default_thumbnails: HashMap::new(),
+        },
+    };
+
+    let items: Vec<ExportItem> = export::build_items(conn, &export_options)?
+        .into_iter()
+        .filter(|item| {
+            options
+                .content_type
+                .as_ref()
+                .is_none_or(|t| &item.r#type == t)
+        })
+        .filter(|item| {
+            options
+                .since
+                .is_none_or(|since| item_date(item) >= Some(since))
+        })
+        .take(options.limit)
+        .collect();
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&items)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:>4} {:>5}  {:<10}  {}  {}  URL",
+        "#",
+        "SCORE",
+        "DATE",
+        pad("SOURCE", SOURCE_WIDTH),
+        pad("TITLE", TITLE_WIDTH)
+    );
+
+    for (rank, item) in items.iter().enumerate() {
+        let date = item
+            .published_at
+            .as_deref()
+            .unwrap_or(&item.fetched_at)
+            .chars()
+            .take(10)
+            .collect::<String>();
+
+        let source = item.source.clone().unwrap_or_else(|| {
+            Url::parse(&item.url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()))
+                .unwrap_or_default()
+        });
+
+        println!(
+            "{:>4} {:>5}  {:<10}  {}  {}  {}",
+            rank + 1,
+            item.score,
+            date,
+            pad(&source, SOURCE_WIDTH),
+            pad(item.title.trim(), TITLE_WIDTH),
+            item.url
+        );
+    }
+
+    Ok(())
+}
+
+fn item_date(item: &ExportItem) -> Option<DateTime<Utc>> {
+    let date = item.published_at.as_deref().unwrap_or(&item.fetched_at);
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+// Truncate/pad to a terminal column width; full-width characters count as two
+pub(crate) fn pad(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+
+    if text.width() > width {
+        for ch in text.chars() {
+            let w = ch.width().unwrap_or(0);
+            if used + w + 1 > width {
+                break;
+            }
+            out.push(ch);
+            used += w;
+        }
+        out.push('…');
+        used += 1;
+    } else {
+        out.push_str(text);
+        used = text.width();
+    }
+
+    out.push_str(&" ".repeat(width.saturating_sub(used)));
+    out
+}
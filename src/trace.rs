@@ -0,0 +1,26 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+
+use crate::blog::{self, Verdict};
+use crate::config::Config;
+
+// Entry point for: crawler trace <url> --config config.json (the config is loaded by
+// the caller, which also opens its database)
+pub async fn run(conn: &Connection, config: &Config, args: &[String]) -> Result<()> {
+    let mut args = args.iter();
+    let url = args.next().context("Missing URL to trace")?;
+    if let Some(other) = args.next() {
+        anyhow::bail!("Unknown trace option: {}", other);
+    }
+
+    for step in blog::trace(conn, config, url).await? {
+        let mark = match step.verdict {
+            Verdict::Pass => "ok",
+            Verdict::Block => "BLOCKED",
+            Verdict::Note => "note",
+        };
+        println!("{:<8} {:<18} {}", mark, step.gate, step.detail);
+    }
+
+    Ok(())
+}
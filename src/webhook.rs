@@ -0,0 +1,100 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{WebhookConfig, WebhookFormat};
+use crate::export::ExportItem;
+
+// Items per POST; Discord accepts at most 10 embeds per message
+const ITEMS_PER_MESSAGE: usize = 10;
+// Before the one retry of a 5xx answer, and between batches
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const BATCH_DELAY: Duration = Duration::from_secs(1);
+// Discord rejects longer embed titles
+const MAX_TITLE_CHARS: usize = 256;
+
+// POST the items in batches, stopping at the first batch the webhook doesn't accept.
+// Returns how many of the items, from the front, were delivered. Failures are logged,
+// never returned: a webhook that is down must not fail the crawl run.
+pub async fn notify(client: &Client, hook: &WebhookConfig, items: &[ExportItem]) -> usize {
+    let mut delivered = 0;
+    for (index, batch) in items.chunks(ITEMS_PER_MESSAGE).enumerate() {
+        if index > 0 {
+            tokio::time::sleep(BATCH_DELAY).await;
+        }
+
+        let payload = match hook.format {
+            WebhookFormat::Discord => discord_payload(batch),
+            WebhookFormat::Generic => generic_payload(batch),
+        };
+
+        // The URL is not logged: Discord's includes the webhook token
+        if let Err(e) = post(client, &hook.url, &payload).await {
+            warn!("Webhook not delivered: {}", e);
+            return delivered;
+        }
+        delivered += batch.len();
+    }
+
+    info!("Posted {} new items to the webhook", items.len());
+    delivered
+}
+
+async fn post(client: &Client, url: &str, payload: &Value) -> Result<()> {
+    let mut retried = false;
+
+    loop {
+        let status = client
+            .post(url)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("request failed: {}", e.without_url()))?
+            .status();
+
+        if status.is_success() {
+            return Ok(());
+        }
+        if status.is_server_error() && !retried {
+            retried = true;
+            tokio::time::sleep(RETRY_DELAY).await;
+            continue;
+        }
+        anyhow::bail!("webhook returned {}", status);
+    }
+}
+
+fn discord_payload(items: &[ExportItem]) -> Value {
+    let embeds: Vec<Value> = items
+        .iter()
+        .map(|item| {
+            json!({
+                "title": item.title.trim().chars().take(MAX_TITLE_CHARS).collect::<String>(),
+                "url": item.url,
+                "description": format!(
+                    "{} · score {}",
+                    item.source.as_deref().unwrap_or("unknown source"),
+                    item.score
+                ),
+            })
+        })
+        .collect();
+
+    json!({ "embeds": embeds })
+}
+
+fn generic_payload(items: &[ExportItem]) -> Value {
+    items
+        .iter()
+        .map(|item| {
+            json!({
+                "title": item.title.trim(),
+                "url": item.url,
+                "source": item.source,
+                "score": item.score,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use reqwest::{Client, StatusCode};
+use rusqlite::Connection;
+use std::rc::Rc;
+use tracing::info;
+
+use crate::blog::{self, CrawlError, SourceReport};
+use crate::config::YouTubeConfig;
+use crate::db::{self, RunCache};
+
+const FEED_URL: &str = "https://www.youtube.com/feeds/videos.xml?channel_id=";
+// Backoff for a channel whose feed is gone (deleted or renamed channel)
+const DEAD_CHANNEL_RETRY_DAYS: i64 = 7;
+
+// One <entry> of a channel feed
+#[derive(Debug, Default)]
+struct Video {
+    id: String,
+    title: String,
+    url: Option<String>,
+    description: Option<String>,
+    thumbnail: Option<String>,
+    published_at: Option<String>,
+}
+
+pub async fn fetch_and_store(
+    conn: &Connection,
+    client: &Client,
+    channel: &YouTubeConfig,
+    cache: Rc<RunCache>,
+    dry_run: bool,
+) -> Result<SourceReport> {
+    let feed_url = format!("{}{}", FEED_URL, channel.channel_id);
+
+    // Same error_sites backoff as blog pages, keyed by the feed URL
+    if let Some(retry_after) = cache.retry_after(&feed_url) {
+        anyhow::bail!("skipped: feed failed recently, retry after {}", retry_after);
+    }
+
+    let mut report = SourceReport::new(&channel.name);
+    report.kind = "youtube".to_string();
+    report.path = Some("feed".to_string());
+
+    db::register_source(conn, &channel.name, &feed_url, channel.category.as_deref())?;
+    info!(
+        "Crawl youtube; channel: {} ({})",
+        channel.name, channel.channel_id
+    );
+
+    report.attempted += 1;
+    let body = match blog::fetch_html(client, &feed_url).await {
+        Ok(body) => body,
+        Err(e) => {
+            if let Some(CrawlError::HttpStatus { status, .. }) = e.downcast_ref::<CrawlError>()
+                && matches!(*status, StatusCode::NOT_FOUND | StatusCode::GONE)
+            {
+                cache.register_error(conn, &feed_url, "404", DEAD_CHANNEL_RETRY_DAYS)?;
+            }
+            return Err(e);
+        }
+    };
+
+    let fetched_at = Utc::now().to_rfc3339();
+
+    for video in parse_feed(&body) {
+        // Video ids are the content ids, so reruns skip what is already stored
+        if cache.fetched_at(&video.id).is_some() {
+            continue;
+        }
+
+        let url = video
+            .url
+            .clone()
+            .unwrap_or_else(|| format!("https://www.youtube.com/watch?v={}", video.id));
+
+        if dry_run {
+            info!("Would insert video: {} ({})", video.title, url);
+            cache.record_content(&video.id, &fetched_at);
+            report.inserted += 1;
+            continue;
+        }
+
+        let inserted = db::insert(
+            conn,
+            &db::NewContent {
+                id: &video.id,
+                content_type: "youtube",
+                title: &video.title,
+                url: &url,
+                description: video.description.as_deref(),
+                thumbnail: video.thumbnail.as_deref(),
+                published_at: video.published_at.as_deref(),
+                fetched_at: &fetched_at,
+                source: Some(&channel.name),
+                discovered_from: Some(&feed_url),
+            },
+        )?;
+
+        if inserted {
+            cache.record_content(&video.id, &fetched_at);
+            report.inserted += 1;
+            info!("Inserted video: {}", video.title);
+        }
+    }
+
+    Ok(report)
+}
+
+// Atom feed entries; the watch link, thumbnail and description live in
+// <link rel="alternate"> and <media:group>.
+fn parse_feed(body: &str) -> Vec<Video> {
+    // Text is trimmed per element, not per event: spaces around entities matter in titles
+    let mut reader = Reader::from_str(body);
+
+    let mut buf = Vec::new();
+    let mut videos = Vec::new();
+    let mut text = String::new();
+    let mut current: Option<Video> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                text.clear();
+                if e.local_name().as_ref() == b"entry" {
+                    current = Some(Video::default());
+                } else if let Some(video) = current.as_mut() {
+                    read_attributes(video, &e);
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                if let Some(video) = current.as_mut() {
+                    read_attributes(video, &e);
+                }
+            }
+            Ok(Event::Text(e)) => {
+                text.push_str(&String::from_utf8_lossy(e.as_ref()));
+            }
+            Ok(Event::GeneralRef(e)) => {
+                if let Ok(Some(ch)) = e.resolve_char_ref() {
+                    text.push(ch);
+                } else if let Some(resolved) =
+                    resolve_predefined_entity(&String::from_utf8_lossy(e.as_ref()))
+                {
+                    text.push_str(resolved);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let value = text.trim().to_string();
+                text.clear();
+
+                let Some(video) = current.as_mut() else {
+                    buf.clear();
+                    continue;
+                };
+
+                // Qualified names: the feed's <title> and <media:title> both exist
+                match e.name().as_ref() {
+                    b"yt:videoId" => video.id = value,
+                    b"title" => video.title = value,
+                    b"media:description" if !value.is_empty() => {
+                        video.description = Some(value);
+                    }
+                    b"published" => {
+                        video.published_at = DateTime::parse_from_rfc3339(&value)
+                            .ok()
+                            .map(|d| d.with_timezone(&Utc).to_rfc3339());
+                    }
+                    b"entry" => {
+                        if let Some(video) = current.take()
+                            && !video.id.is_empty()
+                        {
+                            videos.push(video);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    videos
+}
+
+fn read_attributes(video: &mut Video, element: &BytesStart) {
+    let attr = |name: &[u8]| {
+        element
+            .try_get_attribute(name)
+            .ok()
+            .flatten()
+            .and_then(|a| a.unescape_value().ok())
+            .map(|v| v.into_owned())
+    };
+
+    match element.name().as_ref() {
+        b"link" if attr(b"rel").as_deref() == Some("alternate") => video.url = attr(b"href"),
+        b"media:thumbnail" => video.thumbnail = attr(b"url"),
+        _ => {}
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::blog;
+use serde_json::json;
+
+#[tokio::test]
+async fn sends_the_configured_user_agent() {
+    let server = MockServer::start();
+    server.route("/", Response::html("ok"));
+    let dir = temp_dir("client-agent");
+
+    let config = load_config(
+        &dir,
+        json!({ "youtube": [], "blogs": [], "user_agent": "road-test/1.0" }),
+    );
+    blog::shared_client(&config)
+        .unwrap()
+        .get(server.url("/"))
+        .send()
+        .await
+        .unwrap();
+
+    let default = load_config(&dir, json!({ "youtube": [], "blogs": [] }));
+    blog::shared_client(&default)
+        .unwrap()
+        .get(server.url("/"))
+        .send()
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("user-agent"), Some("road-test/1.0"));
+    assert!(
+        requests[1]
+            .header("user-agent")
+            .is_some_and(|agent| agent.starts_with("michi_matome_crawler/"))
+    );
+}
+
+#[tokio::test]
+async fn goes_through_the_proxy() {
+    let proxy = MockServer::start();
+    proxy.route("http://blog.invalid/page.html", Response::html("via proxy"));
+    let dir = temp_dir("client-proxy");
+
+    let config = load_config(
+        &dir,
+        json!({ "youtube": [], "blogs": [], "http": { "proxy": proxy.url("") } }),
+    );
+    let body = blog::shared_client(&config)
+        .unwrap()
+        .get("http://blog.invalid/page.html")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body, "via proxy");
+    let requests = proxy.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].target, "http://blog.invalid/page.html");
+}
+
+#[tokio::test]
+async fn no_proxy_hosts_are_fetched_directly() {
+    let proxy = MockServer::start();
+    let server = MockServer::start();
+    server.route("/", Response::html("direct"));
+    let dir = temp_dir("client-no-proxy");
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [],
+            "http": { "proxy": proxy.url(""), "no_proxy": "127.0.0.1,.lan" },
+        }),
+    );
+    let body = blog::shared_client(&config)
+        .unwrap()
+        .get(server.url("/"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body, "direct");
+    assert!(proxy.requests().is_empty());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn a_silent_server_times_out() {
+    let server = MockServer::start();
+    server.route(
+        "/slow",
+        Response::html("late").delayed(std::time::Duration::from_secs(3)),
+    );
+    let dir = temp_dir("client-timeout");
+
+    let config = load_config(
+        &dir,
+        json!({ "youtube": [], "blogs": [], "request_timeout_secs": 1, "connect_timeout_secs": 1 }),
+    );
+    let started = std::time::Instant::now();
+    let error = blog::shared_client(&config)
+        .unwrap()
+        .get(server.url("/slow"))
+        .send()
+        .await
+        .unwrap_err();
+
+    assert!(error.is_timeout());
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+}
@@ -0,0 +1,255 @@
+// Minimal HTTP/1.1 server for the integration tests: canned responses per path,
+// every request recorded. One thread per connection, `Connection: close` on every
+// response, so the clients never reuse a socket between two scripted answers.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use michi_matome_crawler::config::{self, Config};
+
+// A fresh, empty directory for one test's database and export files
+pub fn temp_dir(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "michi_matome_crawler-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Loads `json` the way the binary loads a config file, validation included
+pub fn load_config(dir: &std::path::Path, json: serde_json::Value) -> Config {
+    let path = dir.join("config.json");
+    std::fs::write(&path, json.to_string()).unwrap();
+    config::load(path.to_str().unwrap()).unwrap()
+}
+
+#[derive(Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // Wait this long before answering
+    pub delay: Option<Duration>,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: None,
+        }
+    }
+
+    pub fn html(body: &str) -> Self {
+        Response::new(200, body).header("Content-Type", "text/html; charset=utf-8")
+    }
+
+    pub fn xml(body: &str) -> Self {
+        Response::new(200, body).header("Content-Type", "application/xml")
+    }
+
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Response::new(status, "").header("Location", location)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    // As sent: a path, or an absolute URL when the server acts as a proxy
+    pub target: String,
+    // Names in lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // The target without its query string
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Answers per path (request target without the query), used in order; the last
+    // one repeats
+    routes: HashMap<String, VecDeque<Response>>,
+    requests: Vec<Request>,
+}
+
+pub struct MockServer {
+    port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let accept_state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&accept_state);
+                std::thread::spawn(move || handle(stream, &state));
+            }
+        });
+
+        MockServer { port, state }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // http://127.0.0.1:<port><path>
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    // The same server under the name "localhost": a different host to the crawler
+    pub fn other_host_url(&self, path: &str) -> String {
+        format!("http://localhost:{}{}", self.port, path)
+    }
+
+    pub fn route(&self, path: &str, response: Response) -> &Self {
+        self.sequence(path, vec![response])
+    }
+
+    pub fn sequence(&self, path: &str, responses: Vec<Response>) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .routes
+            .insert(path.to_string(), responses.into());
+        self
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.path() == path)
+            .collect()
+    }
+}
+
+fn handle(mut stream: TcpStream, state: &Mutex<State>) {
+    // A TLS ClientHello (an https probe): not served here
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).is_err() || first[0] == 0x16 {
+        return;
+    }
+
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        let key = if request.target.starts_with("http") {
+            request.target.clone()
+        } else {
+            request.path().to_string()
+        };
+        match state.routes.get_mut(&key) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        }
+        .unwrap_or_else(|| Response::new(404, "not found"))
+    };
+
+    if let Some(delay) = response.delay {
+        std::thread::sleep(delay);
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    if request.method != "HEAD" {
+        let _ = stream.write_all(&response.body);
+    }
+    let _ = stream.flush();
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use chrono::Utc;
+use common::{load_config, temp_dir};
+use michi_matome_crawler::{db, export_options, write_export};
+use serde_json::json;
+use std::path::Path;
+
+fn store(conn: &rusqlite::Connection, url: &str, title: &str) {
+    db::insert(
+        conn,
+        &db::NewContent {
+            id: url,
+            content_type: "blog",
+            title,
+            url,
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            fetched_at: "2024-05-01T00:00:00+00:00",
+            source: Some("blog"),
+            discovered_from: None,
+        },
+    )
+    .unwrap();
+}
+
+fn exported(path: &Path) -> Vec<serde_json::Value> {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn ids(items: &[serde_json::Value]) -> Vec<&str> {
+    let mut ids: Vec<&str> = items.iter().map(|i| i["id"].as_str().unwrap()).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn dead_and_superseded_items_go_to_their_own_target() {
+    let (live, dead, old, replacement) = (
+        "https://example.com/live.html",
+        "https://example.com/dead.html",
+        "https://example.com/old.html",
+        "https://example.com/new.html",
+    );
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+    store(&conn, live, "峠の旧道");
+    store(&conn, dead, "消えた隧道");
+    store(&conn, old, "沈下橋");
+    store(&conn, replacement, "沈下橋の再訪");
+    for checked_at in ["2024-06-01T00:00:00+00:00", "2024-06-08T00:00:00+00:00"] {
+        db::record_link_check(&conn, dead, db::LinkCheck::Missing, checked_at).unwrap();
+    }
+    let wayback = "https://web.archive.org/web/2024/https://example.com/dead.html";
+    db::set_archived_url(&conn, dead, wayback).unwrap();
+    db::supersede(&conn, old, replacement).unwrap();
+
+    // Without export_targets
+    let plain_dir = temp_dir("export-plain");
+    let plain = load_config(
+        &plain_dir,
+        json!({
+            "youtube": [],
+            "blogs": [],
+            "output_path": plain_dir.join("index.json"),
+        }),
+    );
+    let run_started_at = Utc::now();
+    write_export(&conn, &plain, &export_options(&plain, run_started_at)).unwrap();
+
+    let dir = temp_dir("export-targets");
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [],
+            "output_path": dir.join("index.json"),
+            "export_targets": [
+                { "path": dir.join("archive.json"), "include": "dead" },
+                { "path": dir.join("all.json"), "include": "all" },
+                { "path": dir.join("archive.csv"), "include": "dead" },
+            ],
+        }),
+    );
+    write_export(&conn, &config, &export_options(&config, run_started_at)).unwrap();
+
+    let index = std::fs::read(dir.join("index.json")).unwrap();
+    assert_eq!(index, std::fs::read(plain_dir.join("index.json")).unwrap());
+    assert_eq!(ids(&exported(&dir.join("index.json"))), [live, replacement]);
+
+    let archive = exported(&dir.join("archive.json"));
+    assert_eq!(ids(&archive), [dead, old]);
+    let item = |id: &str| archive.iter().find(|i| i["id"] == id).unwrap().clone();
+    assert_eq!(item(dead)["dead_at"], "2024-06-08T00:00:00+00:00");
+    assert_eq!(item(dead)["archived_url"], wayback);
+    assert!(item(old)["dead_at"].is_string());
+    assert_eq!(item(old)["superseded_by"], replacement);
+    assert!(item(old).get("archived_url").is_none());
+
+    assert_eq!(
+        ids(&exported(&dir.join("all.json"))),
+        [dead, live, replacement, old]
+    );
+
+    let csv = std::fs::read_to_string(dir.join("archive.csv")).unwrap();
+    let header = csv.lines().next().unwrap();
+    assert!(header.ends_with(",source,dead_at,archived_url,superseded_by"));
+    assert!(csv.contains(wayback));
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use serde_json::json;
+
+fn article(title: &str) -> Response {
+    Response::html(&format!(
+        "<html><head><title>{}</title></head><body><p>旧道の記録。</p></body></html>",
+        title
+    ))
+}
+
+// The blog has no sitemap; its feed links through shorteners (on the mock server's
+// other host name) and the platform's own /outbound wrapper
+#[tokio::test]
+async fn feed_links_through_redirectors_are_stored_under_their_destination() {
+    let server = MockServer::start();
+    let dir = temp_dir("feed-redirectors");
+    let tunnel = server.url("/2024/05/tunnel.html");
+    let outbound = server.url(&format!(
+        "/outbound?url={}",
+        url::form_urlencoded::byte_serialize(tunnel.as_bytes()).collect::<String>()
+    ));
+    let links = [
+        server.other_host_url("/s/pass"),
+        server.other_host_url("/m/bridge"),
+        outbound,
+        server.other_host_url("/s/off"),
+        server.other_host_url("/s/gone"),
+    ];
+    let items: String = links
+        .iter()
+        .map(|link| format!("<item><link>{}</link></item>", link.replace('&', "&amp;")))
+        .collect();
+
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/feed",
+            Response::xml(&format!(
+                r#"<rss version="2.0"><channel>{}</channel></rss>"#,
+                items
+            )),
+        )
+        .route(
+            "/s/pass",
+            Response::redirect(301, &server.url("/2024/05/pass.html")),
+        )
+        .route(
+            "/m/bridge",
+            Response::html(&format!(
+                r#"<html><head><meta http-equiv="refresh" content="0;url={}"></head></html>"#,
+                server.url("/2024/05/bridge.html")
+            )),
+        )
+        .route(
+            "/s/off",
+            Response::redirect(301, "http://elsewhere.invalid/2024/05/off.html"),
+        )
+        .route("/2024/05/pass.html", article("峠の旧道"))
+        .route("/2024/05/bridge.html", article("沈下橋"))
+        .route("/2024/05/tunnel.html", article("素掘り隧道"));
+
+    let settings = json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/") }],
+            "request_delay_ms": 0,
+            "redirectors": ["localhost", "/outbound?url="],
+            "db_path": dir.join("crawler.db"),
+            "output_path": dir.join("index.json"),
+            "summary_path": dir.join("summary.json"),
+    });
+    let config = load_config(&dir, settings.clone());
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+    michi_matome_crawler::run(config, &conn).await.unwrap();
+
+    let mut stored: Vec<String> = conn
+        .prepare("SELECT url FROM contents")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    stored.sort();
+    assert_eq!(
+        stored,
+        [
+            server.url("/2024/05/bridge.html"),
+            server.url("/2024/05/pass.html"),
+            tunnel,
+        ]
+    );
+    // The wrapper's own query says where it goes
+    assert!(server.requests_to("/outbound").is_empty());
+    assert_eq!(server.requests_to("/s/off").len(), 1);
+    assert_eq!(server.requests_to("/s/gone").len(), 1);
+
+    // Resolved wrappers are not asked again
+    let config = load_config(&dir, settings);
+    michi_matome_crawler::run(config, &conn).await.unwrap();
+    assert_eq!(server.requests_to("/s/pass").len(), 1);
+    assert_eq!(server.requests_to("/m/bridge").len(), 1);
+    assert_eq!(server.requests_to("/s/gone").len(), 2);
+}
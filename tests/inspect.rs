@@ -0,0 +1,139 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::blog::{self, ExtractedArticle};
+use serde_json::json;
+
+const ARTICLE: &str = include_str!("fixtures/article.html");
+const STUB: &str = include_str!("fixtures/stub.html");
+const OFFSITE_STUB: &str = include_str!("fixtures/offsite_stub.html");
+
+fn server() -> MockServer {
+    let server = MockServer::start();
+    server
+        .route("/posts/pass.html", Response::html(ARTICLE))
+        .route("/old.html", Response::html(STUB))
+        .route("/moved.html", Response::html(OFFSITE_STUB))
+        .route(
+            "/out.html",
+            Response::redirect(301, &server.other_host_url("/elsewhere.html")),
+        )
+        .route("/elsewhere.html", Response::html(ARTICLE));
+    server
+}
+
+fn assert_fixture(article: &ExtractedArticle, server: &MockServer) {
+    assert_eq!(article.url, server.url("/posts/pass.html"));
+    assert_eq!(article.title, "国道999号 旧道探索");
+    assert_eq!(article.description.as_deref(), Some("峠の旧道を歩いた記録"));
+    assert_eq!(
+        article.published_at.as_deref(),
+        Some("2024-05-01T01:00:00+00:00")
+    );
+    assert_eq!(
+        article.thumbnail.as_deref(),
+        Some(server.url("/img/pass.jpg").as_str())
+    );
+    assert_eq!(article.language.as_deref(), Some("ja"));
+    assert_eq!(article.site_name.as_deref(), Some("道の記録"));
+    assert_eq!(
+        article.unavailable_after.as_deref(),
+        Some("2030-01-01T00:00:00+00:00")
+    );
+    assert_eq!(article.tags, ["旧道", "隧道", "国道999号", "峠"]);
+    assert_eq!(article.embedded_videos, ["abcDEF12345"]);
+    assert_eq!(article.text_length, 57);
+    assert_eq!(article.image_count, 2);
+    assert_eq!(
+        article.excerpt.as_deref(),
+        Some(
+            "国道999号 旧道探索 峠の手前で旧道が分岐している。素掘りの隧道を抜けると、崩れかけた路肩が続く。 峠"
+        )
+    );
+    assert!(
+        article
+            .main_text
+            .as_deref()
+            .is_some_and(|text| text.contains("素掘りの隧道"))
+    );
+    assert_eq!(article.score, Some(9));
+}
+
+#[tokio::test]
+async fn extracts_every_field() {
+    let server = server();
+    let client = reqwest::Client::new();
+
+    let article = blog::crawl_single(&client, &server.url("/posts/pass.html"), None)
+        .await
+        .unwrap();
+
+    assert_fixture(&article, &server);
+    assert_eq!(article.redirected_from, None);
+}
+
+#[tokio::test]
+async fn follows_a_same_site_meta_refresh() {
+    let server = server();
+    let client = reqwest::Client::new();
+
+    let article = blog::crawl_single(&client, &server.url("/old.html"), None)
+        .await
+        .unwrap();
+
+    assert_fixture(&article, &server);
+    assert_eq!(
+        article.redirected_from.as_deref(),
+        Some(server.url("/old.html").as_str())
+    );
+}
+
+#[tokio::test]
+async fn stops_at_the_edge_of_the_site() {
+    let server = server();
+    let client = reqwest::Client::new();
+
+    let error = blog::crawl_single(&client, &server.url("/moved.html"), None)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("not followed"), "{}", error);
+
+    // The client follows the 301; its destination is outside the site
+    let error = blog::crawl_single(&client, &server.url("/out.html"), None)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("off the site"), "{}", error);
+}
+
+// A configured blog's alternate host maps onto its primary one, as in a crawl
+#[tokio::test]
+async fn uses_the_configured_blog() {
+    let server = server();
+    let dir = temp_dir("inspect-config");
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{
+                "name": "道の記録",
+                "url": server.url("/"),
+                "alt_urls": [server.other_host_url("/")],
+            }],
+        }),
+    );
+    let client = blog::shared_client(&config).unwrap();
+
+    let article = blog::crawl_single(
+        &client,
+        &server.other_host_url("/posts/pass.html"),
+        Some(&config),
+    )
+    .await
+    .unwrap();
+
+    assert_fixture(&article, &server);
+    assert_eq!(
+        server.requests_to("/posts/pass.html")[0].header("host"),
+        Some(format!("127.0.0.1:{}", server.port()).as_str())
+    );
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::logging::JsonLayer;
+use serde_json::{Value, json};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+
+const ARTICLE: &str = r#"<html lang="ja"><head>
+<title>国道999号 旧道レポート</title>
+<meta property="article:published_time" content="2024-05-01T10:00:00+09:00">
+</head><body><article><p>隧道と旧道の踏査。</p></article></body></html>"#;
+
+// Log lines written to memory instead of stderr
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Capture {
+    fn lines(&self) -> Vec<Value> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect(line))
+            .collect()
+    }
+}
+
+// Every line of a run carries its run_id, the lines of a source its source and url,
+// and the run ends with one summary event with the totals as fields
+#[tokio::test]
+async fn json_lines_carry_run_source_and_url() {
+    let server = MockServer::start();
+    let dir = temp_dir("logging");
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/sitemap.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc><lastmod>2024-05-01</lastmod></url>
+                </urlset>"#,
+                server.url("/posts/1.html"),
+            )),
+        )
+        .route("/posts/1.html", Response::html(ARTICLE));
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/"), "category": "roads" }],
+            "request_delay_ms": 0,
+            "db_path": "file:logging_test?mode=memory&cache=shared",
+            "output_path": dir.join("index.json"),
+            "summary_path": dir.join("summary.json"),
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+
+    let capture = Capture::default();
+    let writer = capture.clone();
+    // Only the crate's own events, as with the binary's default filter (hyper logs
+    // from its connection tasks, outside any span)
+    let subscriber = tracing_subscriber::registry()
+        .with(Targets::new().with_target("michi_matome_crawler", Level::TRACE))
+        .with(JsonLayer::new(move || writer.clone()));
+    // The test runtime is single-threaded, so the spawned source tasks log here too
+    let guard = tracing::subscriber::set_default(subscriber);
+    michi_matome_crawler::run(config, &conn).await.unwrap();
+    drop(guard);
+
+    let lines = capture.lines();
+    assert!(!lines.is_empty());
+    for line in &lines {
+        assert!(line["timestamp"].as_str().is_some(), "{}", line);
+        assert!(line["level"].as_str().is_some(), "{}", line);
+        assert!(line["target"].as_str().is_some(), "{}", line);
+        assert!(line["message"].as_str().is_some(), "{}", line);
+    }
+
+    let run_id = lines[0]["run_id"].as_str().unwrap();
+    assert!(lines.iter().all(|line| line["run_id"] == run_id));
+
+    let source_lines: Vec<&Value> = lines
+        .iter()
+        .filter(|line| line["source"] == "blog")
+        .collect();
+    assert!(!source_lines.is_empty());
+    // The source's url, or the article's inside an article span
+    assert!(
+        source_lines
+            .iter()
+            .any(|line| line["url"] == server.url("/"))
+    );
+    assert!(
+        source_lines
+            .iter()
+            .any(|line| line["url"] == server.url("/posts/1.html"))
+    );
+
+    let summaries: Vec<&Value> = lines
+        .iter()
+        .filter(|line| line["message"] == "Run summary")
+        .collect();
+    assert_eq!(summaries.len(), 1);
+    let summary = summaries[0];
+    assert_eq!(summary["level"], "INFO");
+    assert_eq!(summary["inserted"], 1);
+    assert_eq!(summary["sources"], 1);
+    assert_eq!(summary["failed"], 0);
+    assert_eq!(summary["errors"], 0);
+    assert!(summary["fetched"].as_u64().unwrap() >= 1);
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::{db, deliver_notifications, notify_new_items};
+use serde_json::json;
+
+fn store(conn: &rusqlite::Connection, url: &str, title: &str) {
+    db::insert(
+        conn,
+        &db::NewContent {
+            id: url,
+            content_type: "blog",
+            title,
+            url,
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            fetched_at: &Utc::now().to_rfc3339(),
+            source: Some("blog"),
+            discovered_from: None,
+        },
+    )
+    .unwrap();
+}
+
+// Posts to the webhook that announced `url`
+fn posts_of(server: &MockServer, url: &str) -> usize {
+    server
+        .requests_to("/hook")
+        .iter()
+        .filter(|request| {
+            serde_json::from_slice::<serde_json::Value>(&request.body)
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|item| item["url"] == url)
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn a_failed_notification_is_delivered_exactly_once_on_the_next_run() {
+    let server = MockServer::start();
+    let dir = temp_dir("notify");
+    // The first run's post and its one retry fail
+    server.sequence(
+        "/hook",
+        vec![
+            Response::new(503, ""),
+            Response::new(503, ""),
+            Response::new(204, ""),
+        ],
+    );
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [],
+            "webhook": { "url": server.url("/hook") },
+        }),
+    );
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+
+    let first_run = Utc::now() - Duration::minutes(1);
+    let url = "https://example.com/a.html";
+    store(&conn, url, "国道999号 旧道");
+    notify_new_items(&conn, &config, first_run).await.unwrap();
+    assert_eq!(server.requests_to("/hook").len(), 2);
+    assert_eq!(db::pending_notifications(&conn).unwrap().len(), 1);
+
+    // The next run has nothing new of its own, but delivers the leftover
+    notify_new_items(&conn, &config, Utc::now()).await.unwrap();
+    assert_eq!(server.requests_to("/hook").len(), 3);
+    assert!(db::pending_notifications(&conn).unwrap().is_empty());
+
+    // Seen as new again (a run overlapping the first), it is not announced again
+    notify_new_items(&conn, &config, first_run).await.unwrap();
+    assert_eq!(server.requests_to("/hook").len(), 3);
+    // Two refused posts and the accepted one
+    assert_eq!(posts_of(&server, url), 3);
+}
+
+#[tokio::test]
+async fn old_notifications_are_dropped() {
+    let server = MockServer::start();
+    let dir = temp_dir("notify-age");
+    server.route("/hook", Response::new(204, ""));
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [],
+            "webhook": { "url": server.url("/hook"), "max_age_hours": 24 },
+        }),
+    );
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+
+    let url = "https://example.com/a.html";
+    store(&conn, url, "国道999号 旧道");
+    let queued_at = (Utc::now() - Duration::hours(25)).to_rfc3339();
+    db::enqueue_notifications(&conn, &[url.to_string()], &queued_at).unwrap();
+
+    deliver_notifications(&conn, &config, config.webhook.as_ref().unwrap())
+        .await
+        .unwrap();
+    assert!(server.requests_to("/hook").is_empty());
+    assert!(db::pending_notifications(&conn).unwrap().is_empty());
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use chrono::Utc;
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::{blog, db};
+use serde_json::json;
+use std::rc::Rc;
+
+const OLD_SEEN: &str = "2020-01-01T00:00:00+00:00";
+
+fn article(title: &str) -> String {
+    format!(
+        r#"<html lang="ja"><head><title>{}</title>
+        <meta name="description" content="移転後の記事">
+        </head><body><article><p>旧道の記録。</p></article></body></html>"#,
+        title
+    )
+}
+
+fn store(conn: &rusqlite::Connection, url: &str, title: &str) {
+    db::insert(
+        conn,
+        &db::NewContent {
+            id: url,
+            content_type: "blog",
+            title,
+            url,
+            description: None,
+            thumbnail: None,
+            published_at: None,
+            fetched_at: OLD_SEEN,
+            source: Some("blog"),
+            discovered_from: None,
+        },
+    )
+    .unwrap();
+}
+
+// The blog moved from /YYYY/MM/slug.html permalinks to /posts/slug; one new URL
+// was already stored from the new platform's sitemap
+#[tokio::test]
+async fn refresh_records_moved_permalinks() {
+    let server = MockServer::start();
+    let dir = temp_dir("refresh");
+    server
+        .route(
+            "/2019/01/pass.html",
+            Response::redirect(301, &server.url("/posts/pass")),
+        )
+        .route(
+            "/2019/02/bridge.html",
+            Response::redirect(301, &server.url("/posts/bridge")),
+        )
+        .route("/posts/pass", Response::html(&article("峠の旧道")))
+        .route("/posts/bridge", Response::html(&article("沈下橋")));
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/") }],
+            "request_delay_ms": 0,
+        }),
+    );
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+
+    let (old_pass, old_bridge) = (
+        server.url("/2019/01/pass.html"),
+        server.url("/2019/02/bridge.html"),
+    );
+    let (new_pass, new_bridge) = (server.url("/posts/pass"), server.url("/posts/bridge"));
+    store(&conn, &old_pass, "峠の旧道");
+    store(&conn, &old_bridge, "沈下橋");
+    store(&conn, &new_bridge, "沈下橋");
+
+    let cache = Rc::new(db::RunCache::load(&conn).unwrap());
+    let report = blog::refresh(&conn, &config, &config.blogs[0], cache, Utc::now())
+        .await
+        .unwrap();
+
+    let mut moves: Vec<(&str, &str)> = report
+        .moved
+        .iter()
+        .map(|m| (m.from.as_str(), m.to.as_str()))
+        .collect();
+    moves.sort();
+    assert_eq!(
+        moves,
+        [
+            (old_pass.as_str(), new_pass.as_str()),
+            (old_bridge.as_str(), new_bridge.as_str()),
+        ]
+    );
+    assert_eq!(report.inserted, 0);
+
+    // Only the destinations are live; the new row keeps the old first-seen time
+    let live = db::fetch_all(&conn, true).unwrap();
+    let mut ids: Vec<&str> = live.iter().map(|c| c.id.as_str()).collect();
+    ids.sort();
+    assert_eq!(ids, [new_bridge.as_str(), new_pass.as_str()]);
+    assert!(live.iter().all(|c| c.fetched_at == OLD_SEEN));
+
+    let redirected_to: Option<String> = conn
+        .query_row(
+            "SELECT redirected_to FROM contents WHERE id = ?1 AND deleted_at IS NOT NULL",
+            [&old_pass],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(redirected_to.as_deref(), Some(new_pass.as_str()));
+
+    // The old ids stay reachable through the destination's aliases
+    let options = michi_matome_crawler::export_options(&config, Utc::now());
+    let items = michi_matome_crawler::export::build_items(&conn, &options).unwrap();
+    let aliases = |id: &str| {
+        items
+            .iter()
+            .find(|item| item.id == id)
+            .map(|item| (item.aliases.clone(), item.is_new))
+            .unwrap()
+    };
+    assert_eq!(aliases(&new_pass), (vec![old_pass.clone()], false));
+    assert_eq!(aliases(&new_bridge), (vec![old_bridge.clone()], false));
+}
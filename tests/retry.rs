@@ -0,0 +1,43 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::blog;
+use serde_json::json;
+
+fn client(name: &str) -> reqwest::Client {
+    let dir = temp_dir(name);
+    blog::shared_client(&load_config(&dir, json!({ "youtube": [], "blogs": [] }))).unwrap()
+}
+
+#[tokio::test]
+async fn retries_503_until_the_page_answers() {
+    let server = MockServer::start();
+    server.sequence(
+        "/page.html",
+        vec![
+            Response::new(503, "busy"),
+            Response::new(503, "busy"),
+            Response::html("<title>ok</title>"),
+        ],
+    );
+
+    let body = blog::fetch_html(&client("retry-503"), &server.url("/page.html"))
+        .await
+        .unwrap();
+
+    assert_eq!(body, "<title>ok</title>");
+    assert_eq!(server.requests_to("/page.html").len(), 3);
+}
+
+#[tokio::test]
+async fn other_errors_are_not_retried() {
+    let server = MockServer::start();
+    server.route("/gone.html", Response::new(404, "gone"));
+
+    let error = blog::fetch_html(&client("retry-404"), &server.url("/gone.html"))
+        .await
+        .unwrap_err();
+
+    assert!(error.to_string().contains("404"));
+    assert_eq!(server.requests_to("/gone.html").len(), 1);
+}
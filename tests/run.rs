@@ -0,0 +1,267 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use serde_json::json;
+
+const ARTICLE_1: &str = r#"<html lang="ja"><head>
+<title>国道999号 旧道レポート</title>
+<meta name="description" content="廃道区間を歩いた記録">
+<meta property="og:image" content="/img/999.jpg">
+<meta property="article:published_time" content="2024-05-01T10:00:00+09:00">
+</head><body><article><p>隧道と旧道の踏査。</p></article></body></html>"#;
+
+const ARTICLE_2: &str = r#"<html lang="ja"><head>
+<title>林道の橋梁</title>
+<meta name="description" content="沈下橋">
+<meta property="article:published_time" content="2024-04-01T10:00:00+09:00">
+</head><body><article><p>橋の記録。</p></article></body></html>"#;
+
+// url, title, description, thumbnail, source
+type Row = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+// Crawl a two-article sitemap into an in-memory database and export it
+#[tokio::test]
+async fn crawls_a_sitemap_and_exports_it() {
+    let server = MockServer::start();
+    let dir = temp_dir("run");
+
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/sitemap.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc><lastmod>2024-05-01</lastmod></url>
+                    <url><loc>{}</loc><lastmod>2024-04-01</lastmod></url>
+                </urlset>"#,
+                server.url("/posts/1.html"),
+                server.url("/posts/2.html"),
+            )),
+        )
+        .route("/posts/1.html", Response::html(ARTICLE_1))
+        .route("/posts/2.html", Response::html(ARTICLE_2));
+
+    let output = dir.join("index.json");
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/"), "category": "roads" }],
+            "request_delay_ms": 0,
+            // Every source task opens its own connection: a named shared-cache
+            // database is the same one for all of them
+            "db_path": "file:run_test?mode=memory&cache=shared",
+            "output_path": output,
+            "summary_path": dir.join("summary.json"),
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+
+    let report = michi_matome_crawler::run(config, &conn).await.unwrap();
+    assert_eq!(report.sources.len(), 1);
+    assert_eq!(report.sources[0].inserted, 2);
+    // Fast, clean answers leave the zero delay where it started
+    assert_eq!(report.sources[0].host_delays_ms.get("127.0.0.1"), Some(&0));
+
+    let mut rows: Vec<Row> = conn
+        .prepare(
+            "SELECT url, title, description, thumbnail, source FROM contents
+             WHERE type = 'blog' ORDER BY url",
+        )
+        .unwrap()
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    rows.sort();
+    assert_eq!(
+        rows,
+        [
+            (
+                server.url("/posts/1.html"),
+                "国道999号 旧道レポート".to_string(),
+                Some("廃道区間を歩いた記録".to_string()),
+                Some(server.url("/img/999.jpg")),
+                Some("blog".to_string()),
+            ),
+            (
+                server.url("/posts/2.html"),
+                "林道の橋梁".to_string(),
+                Some("沈下橋".to_string()),
+                None,
+                Some("blog".to_string()),
+            ),
+        ]
+    );
+
+    let export: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+    let items = export.as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    // Sorted by score: the road number and 旧道 outrank the bridge
+    assert_eq!(items[0]["url"], server.url("/posts/1.html"));
+    assert_eq!(items[0]["title"], "国道999号 旧道レポート");
+    assert_eq!(items[0]["type"], "blog");
+    assert_eq!(items[0]["source"], "blog");
+    assert_eq!(items[0]["thumbnail"], server.url("/img/999.jpg"));
+    assert_eq!(items[0]["published_at"], "2024-05-01T01:00:00+00:00");
+    assert_eq!(items[1]["url"], server.url("/posts/2.html"));
+    assert!(items[0]["score"].as_i64() > items[1]["score"].as_i64());
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("summary.json")).unwrap()).unwrap();
+    assert!(summary.to_string().contains("\"inserted\":2"));
+}
+
+// A source that fails doesn't stop the others: they are still crawled and
+// exported, and the run reports the failure for main's exit status
+#[tokio::test]
+async fn a_failed_source_is_reported_and_the_rest_exported() {
+    let server = MockServer::start();
+    let dir = temp_dir("run-failed");
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/sitemap.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc><lastmod>2024-05-01</lastmod></url>
+                </urlset>"#,
+                server.url("/posts/1.html"),
+            )),
+        )
+        .route("/posts/1.html", Response::html(ARTICLE_1));
+
+    let output = dir.join("index.json");
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [
+                // Fails before any request: its cookie variable is never set
+                {
+                    "name": "broken",
+                    "url": server.url("/broken/"),
+                    "auth": { "type": "cookie", "cookie_env": "MMC_TEST_RUN_UNSET_COOKIE" },
+                },
+                { "name": "blog", "url": server.url("/") },
+            ],
+            "request_delay_ms": 0,
+            "db_path": "file:run_failed_test?mode=memory&cache=shared",
+            "output_path": output,
+            "summary_path": dir.join("summary.json"),
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+
+    let report = michi_matome_crawler::run(config, &conn).await.unwrap();
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.sources[0].name, "broken");
+    assert!(
+        report.sources[0].failure.is_some(),
+        "{:?}",
+        report.sources[0]
+    );
+    assert_eq!(report.sources[1].failure, None);
+    assert_eq!(report.sources[1].inserted, 1);
+
+    let export: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+    let items = export.as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["url"], server.url("/posts/1.html"));
+
+    // Exit status: a failure over the allowed count is an error
+    let error = michi_matome_crawler::check_failed_sources(&report.sources, 0).unwrap_err();
+    assert!(
+        error.to_string().contains("1 of 2 sources failed"),
+        "{}",
+        error
+    );
+    assert!(michi_matome_crawler::check_failed_sources(&report.sources, 1).is_ok());
+}
+
+// summary.json carries the run's quality report; a count over its threshold is a
+// breach, listed there and turned into the exit status
+#[tokio::test]
+async fn quality_report_goes_into_the_summary_and_thresholds_breach() {
+    let server = MockServer::start();
+    let dir = temp_dir("run-quality");
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/sitemap.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc><lastmod>2024-05-01</lastmod></url>
+                    <url><loc>{}</loc><lastmod>2024-04-01</lastmod></url>
+                </urlset>"#,
+                server.url("/posts/1.html"),
+                server.url("/posts/2.html"),
+            )),
+        )
+        .route("/posts/1.html", Response::html(ARTICLE_1))
+        // No og:image: one item without a thumbnail
+        .route("/posts/2.html", Response::html(ARTICLE_2));
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/") }],
+            "request_delay_ms": 0,
+            "db_path": "file:run_quality_test?mode=memory&cache=shared",
+            "output_path": dir.join("index.json"),
+            "summary_path": dir.join("summary.json"),
+            "quality_thresholds": { "max_missing_thumbnail": 0, "max_invalid_urls": 0 },
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+
+    let report = michi_matome_crawler::run(config, &conn).await.unwrap();
+    assert_eq!(report.quality.missing_thumbnail, 1);
+    assert_eq!(report.quality_breaches, ["missing_thumbnail: 1 (max 0)"]);
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("summary.json")).unwrap()).unwrap();
+    let quality = &summary["quality"];
+    assert_eq!(quality["duplicate_rows"], 0);
+    assert_eq!(quality["placeholder_titles"], 0);
+    assert_eq!(quality["missing_published_at"], 0);
+    assert_eq!(quality["missing_thumbnail"], 1);
+    assert_eq!(quality["invalid_urls"], 0);
+    assert_eq!(quality["identical_title_sources"], json!([]));
+    assert_eq!(
+        summary["quality_breaches"],
+        json!(["missing_thumbnail: 1 (max 0)"])
+    );
+
+    let error = michi_matome_crawler::check_quality(&report.quality_breaches).unwrap_err();
+    assert!(error.to_string().contains("missing_thumbnail"), "{}", error);
+}
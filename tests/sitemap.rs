@@ -0,0 +1,251 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use serde_json::json;
+
+// Off-site child sitemaps and entries are never requested, so the blog's credentials
+// only ever reach its own host
+#[tokio::test]
+async fn credentials_stay_on_the_blog_host() {
+    let server = MockServer::start();
+    let dir = temp_dir("sitemap-credentials");
+    // SAFETY: no other test reads this variable
+    unsafe { std::env::set_var("MMC_TEST_SITEMAP_COOKIE", "session=secret") };
+
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/sitemap.xml",
+            Response::xml(&format!(
+                r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <sitemap><loc>{}</loc></sitemap>
+                    <sitemap><loc>{}</loc></sitemap>
+                </sitemapindex>"#,
+                server.url("/sitemap-posts.xml"),
+                server.other_host_url("/sitemap-elsewhere.xml"),
+            )),
+        )
+        .route(
+            "/sitemap-posts.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc></url>
+                    <url><loc>{}</loc></url>
+                </urlset>"#,
+                server.other_host_url("/posts/elsewhere.html"),
+                server.url("/posts/1.html"),
+            )),
+        )
+        .route(
+            "/posts/1.html",
+            Response::html(
+                "<html><head><title>隧道レポート</title></head><body><p>本文</p></body></html>",
+            ),
+        );
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{
+                "name": "blog",
+                "url": server.url("/"),
+                "auth": { "type": "cookie", "cookie_env": "MMC_TEST_SITEMAP_COOKIE" },
+            }],
+            "request_delay_ms": 0,
+            "db_path": dir.join("crawler.db"),
+            "output_path": dir.join("index.json"),
+            "summary_path": dir.join("summary.json"),
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+    michi_matome_crawler::run(config, &conn).await.unwrap();
+
+    let requests = server.requests();
+    let host = format!("127.0.0.1:{}", server.port());
+    assert!(
+        requests
+            .iter()
+            .all(|r| r.header("host") == Some(host.as_str())),
+        "requests left the blog host: {:?}",
+        requests
+            .iter()
+            .map(|r| (r.header("host"), &r.target))
+            .collect::<Vec<_>>()
+    );
+    assert!(server.requests_to("/sitemap-elsewhere.xml").is_empty());
+    assert!(server.requests_to("/posts/elsewhere.html").is_empty());
+
+    let article = server.requests_to("/posts/1.html");
+    assert_eq!(article.len(), 1);
+    assert_eq!(article[0].header("cookie"), Some("session=secret"));
+
+    let stored: Vec<String> = conn
+        .prepare("SELECT url FROM contents")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(stored, [server.url("/posts/1.html")]);
+}
+
+// A Sitemap: line in robots.txt pointing at another host is not followed
+#[tokio::test]
+async fn robots_sitemaps_outside_the_site_are_skipped() {
+    let server = MockServer::start();
+    let dir = temp_dir("sitemap-robots");
+
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route(
+            "/robots.txt",
+            Response::new(
+                200,
+                format!(
+                    "User-agent: *\nAllow: /\nSitemap: {}\nSitemap: {}\n",
+                    server.other_host_url("/sitemap-elsewhere.xml"),
+                    server.url("/sitemap-posts.xml"),
+                ),
+            )
+            .header("Content-Type", "text/plain"),
+        )
+        .route(
+            "/sitemap-posts.xml",
+            Response::xml(&format!(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                    <url><loc>{}</loc></url>
+                </urlset>"#,
+                server.url("/posts/1.html"),
+            )),
+        )
+        .route(
+            "/posts/1.html",
+            Response::html("<html><head><title>林道レポート</title></head></html>"),
+        );
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "blog", "url": server.url("/") }],
+            "request_delay_ms": 0,
+            "db_path": dir.join("crawler.db"),
+            "output_path": dir.join("index.json"),
+            "summary_path": dir.join("summary.json"),
+        }),
+    );
+    let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+    michi_matome_crawler::run(config, &conn).await.unwrap();
+
+    let host = format!("localhost:{}", server.port());
+    assert!(
+        server
+            .requests()
+            .iter()
+            .all(|r| r.header("host") != Some(host.as_str()))
+    );
+    assert_eq!(server.requests_to("/sitemap-posts.xml").len(), 1);
+    assert_eq!(server.requests_to("/posts/1.html").len(), 1);
+}
+
+fn article(title: &str, description: &str) -> Response {
+    Response::html(&format!(
+        r#"<html><head><title>{}</title><meta name="description" content="{}"></head>
+        <body><article><p>本文</p></article></body></html>"#,
+        title, description
+    ))
+}
+
+fn urlset(url: &str, lastmod: &str) -> Response {
+    Response::xml(&format!(
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <url><loc>{}</loc><lastmod>{}</lastmod></url>
+        </urlset>"#,
+        url, lastmod
+    ))
+}
+
+// A stored entry whose lastmod is newer than its last fetch is fetched again and
+// its row updated; once refetched it is left alone until lastmod moves again
+#[tokio::test]
+async fn newer_lastmod_updates_the_stored_row() {
+    let server = MockServer::start();
+    let dir = temp_dir("sitemap-lastmod");
+    let post = server.url("/posts/1.html");
+    server
+        .route(
+            "/",
+            Response::html("<html><head><title>Blog</title></head></html>"),
+        )
+        .route("/sitemap.xml", urlset(&post, "2024-05-01"))
+        .route("/posts/1.html", article("旧道レポート", "初版"));
+
+    let run = |dir: &std::path::Path| {
+        let config = load_config(
+            dir,
+            json!({
+                "youtube": [],
+                "blogs": [{ "name": "blog", "url": server.url("/") }],
+                "request_delay_ms": 0,
+                "db_path": dir.join("crawler.db"),
+                "output_path": dir.join("index.json"),
+                "summary_path": dir.join("summary.json"),
+            }),
+        );
+        async move {
+            let conn = michi_matome_crawler::db::open(&config.db_path).unwrap();
+            michi_matome_crawler::db::init(&conn).unwrap();
+            michi_matome_crawler::run(config, &conn).await.unwrap()
+        }
+    };
+    let conn = michi_matome_crawler::db::open(dir.join("crawler.db").to_str().unwrap()).unwrap();
+    michi_matome_crawler::db::init(&conn).unwrap();
+    let stored = |conn: &rusqlite::Connection| -> (String, String, Option<String>) {
+        conn.query_row(
+            "SELECT title, description, refreshed_at FROM contents WHERE type = 'blog'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap()
+    };
+
+    let report = run(&dir).await;
+    assert_eq!(report.sources[0].inserted, 1);
+    assert_eq!(stored(&conn).0, "旧道レポート");
+    // First seen before the next lastmod
+    conn.execute(
+        "UPDATE contents SET fetched_at = '2024-05-02T00:00:00+00:00'",
+        [],
+    )
+    .unwrap();
+
+    server
+        .route("/sitemap.xml", urlset(&post, "2024-06-01"))
+        .route(
+            "/posts/1.html",
+            article("旧道レポート（追記あり）", "改訂版"),
+        );
+    let report = run(&dir).await;
+    assert_eq!(report.sources[0].inserted, 0);
+    assert_eq!(report.sources[0].refreshed, 1);
+    let (title, description, refreshed_at) = stored(&conn);
+    assert_eq!(title, "旧道レポート（追記あり）");
+    assert_eq!(description, "改訂版");
+    assert!(refreshed_at.is_some());
+    assert_eq!(server.requests_to("/posts/1.html").len(), 2);
+
+    // The same lastmod again: older than the refetch, so no request
+    let report = run(&dir).await;
+    assert_eq!(report.sources[0].refreshed, 0);
+    assert_eq!(report.sources[0].skipped_known, 1);
+    assert_eq!(server.requests_to("/posts/1.html").len(), 2);
+}
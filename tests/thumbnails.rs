@@ -0,0 +1,161 @@
+mod common;
+
+use chrono::Utc;
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::{db, export, thumbnails};
+use serde_json::json;
+
+fn store(conn: &rusqlite::Connection, url: &str, source: &str, thumbnail: &str) {
+    db::insert(
+        conn,
+        &db::NewContent {
+            id: url,
+            content_type: "blog",
+            title: url,
+            url,
+            description: None,
+            thumbnail: Some(thumbnail),
+            published_at: None,
+            fetched_at: "2024-05-01T00:00:00+00:00",
+            source: Some(source),
+            discovered_from: None,
+        },
+    )
+    .unwrap();
+}
+
+fn exported_thumbnail(items: &[export::ExportItem], id: &str) -> Option<String> {
+    items
+        .iter()
+        .find(|item| item.id == id)
+        .unwrap()
+        .thumbnail
+        .clone()
+}
+
+#[tokio::test]
+async fn broken_thumbnails_are_left_out_of_the_export() {
+    let server = MockServer::start();
+    let dir = temp_dir("thumbnails");
+    server
+        .route(
+            "/img/ok.jpg",
+            Response::new(200, "").header("Content-Type", "image/jpeg"),
+        )
+        .route("/img/gone.jpg", Response::new(404, ""))
+        .route("/img/hotlink.jpg", Response::new(403, ""))
+        .route("/img/flaky.jpg", Response::new(500, ""));
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [
+                { "name": "a", "url": "https://a.example.com/" },
+                {
+                    "name": "b",
+                    "url": "https://b.example.com/",
+                    "default_thumbnail": "https://b.example.com/logo.png",
+                },
+            ],
+            "thumbnail_check": { "delay_ms": 0 },
+        }),
+    );
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+
+    let (ok, gone, hotlink, flaky) = (
+        "https://a.example.com/ok.html",
+        "https://a.example.com/gone.html",
+        "https://b.example.com/hotlink.html",
+        "https://a.example.com/flaky.html",
+    );
+    store(&conn, ok, "a", &server.url("/img/ok.jpg"));
+    store(&conn, gone, "a", &server.url("/img/gone.jpg"));
+    store(&conn, hotlink, "b", &server.url("/img/hotlink.jpg"));
+    store(&conn, flaky, "a", &server.url("/img/flaky.jpg"));
+
+    let check = config.thumbnail_check.as_ref().unwrap();
+    thumbnails::verify(&conn, &config, check).await.unwrap();
+    assert!(server.requests().iter().all(|r| r.method == "HEAD"));
+
+    let options = michi_matome_crawler::export_options(&config, Utc::now());
+    let items = export::build_items(&conn, &options).unwrap();
+    assert_eq!(
+        exported_thumbnail(&items, ok),
+        Some(server.url("/img/ok.jpg"))
+    );
+    assert_eq!(exported_thumbnail(&items, gone), None);
+    assert_eq!(
+        exported_thumbnail(&items, hotlink).as_deref(),
+        Some("https://b.example.com/logo.png")
+    );
+    // A server error is no verdict on the image
+    assert_eq!(
+        exported_thumbnail(&items, flaky),
+        Some(server.url("/img/flaky.jpg"))
+    );
+
+    // The stored values are untouched and the export reports what it dropped
+    let stored = db::fetch_all(&conn, true).unwrap();
+    assert!(stored.iter().all(|c| c.thumbnail.is_some()));
+    let output = dir.join("index.json");
+    let mut withheld = export::export(
+        &conn,
+        output.to_str().unwrap(),
+        michi_matome_crawler::config::ExportFormat::Json,
+        &options,
+    )
+    .unwrap()
+    .thumbnails_suppressed;
+    withheld.sort();
+    assert_eq!(withheld, [gone, hotlink]);
+}
+
+#[tokio::test]
+async fn checks_are_cached_for_the_ttl() {
+    let server = MockServer::start();
+    let dir = temp_dir("thumbnail-ttl");
+    server.sequence(
+        "/img/a.jpg",
+        vec![
+            Response::new(404, ""),
+            Response::new(200, "").header("Content-Type", "image/jpeg"),
+        ],
+    );
+
+    let config = load_config(
+        &dir,
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "a", "url": "https://a.example.com/" }],
+            "thumbnail_check": { "delay_ms": 0, "ttl_hours": 24 },
+        }),
+    );
+    let check = config.thumbnail_check.as_ref().unwrap();
+    let conn = db::open(":memory:").unwrap();
+    db::init(&conn).unwrap();
+    let id = "https://a.example.com/a.html";
+    store(&conn, id, "a", &server.url("/img/a.jpg"));
+    let options = michi_matome_crawler::export_options(&config, Utc::now());
+
+    thumbnails::verify(&conn, &config, check).await.unwrap();
+    thumbnails::verify(&conn, &config, check).await.unwrap();
+    assert_eq!(server.requests_to("/img/a.jpg").len(), 1);
+    let items = export::build_items(&conn, &options).unwrap();
+    assert_eq!(exported_thumbnail(&items, id), None);
+
+    // Past the TTL the image is asked for again, and is back
+    conn.execute(
+        "UPDATE thumbnail_checks SET checked_at = '2000-01-01T00:00:00+00:00'",
+        [],
+    )
+    .unwrap();
+    thumbnails::verify(&conn, &config, check).await.unwrap();
+    assert_eq!(server.requests_to("/img/a.jpg").len(), 2);
+    let items = export::build_items(&conn, &options).unwrap();
+    assert_eq!(
+        exported_thumbnail(&items, id),
+        Some(server.url("/img/a.jpg"))
+    );
+}
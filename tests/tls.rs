@@ -0,0 +1,124 @@
+mod common;
+
+use common::{load_config, temp_dir};
+use michi_matome_crawler::blog::{self, CrawlError};
+use native_tls::{Identity, TlsAcceptor};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+
+// Serves TLS with a self-signed certificate for localhost; returns its port. Clients
+// that accept the certificate get a small page.
+fn self_signed_server() -> u16 {
+    let identity =
+        Identity::from_pkcs12(include_bytes!("fixtures/self_signed.p12"), "test").unwrap();
+    let acceptor = TlsAcceptor::new(identity).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Verifying clients give up during the handshake
+            let Ok(mut stream) = acceptor.accept(stream) else {
+                continue;
+            };
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let body = "<html><head><title>self-signed</title></head></html>";
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    port
+}
+
+fn client(name: &str) -> reqwest::Client {
+    let dir = temp_dir(name);
+    blog::shared_client(&load_config(&dir, json!({ "youtube": [], "blogs": [] }))).unwrap()
+}
+
+#[tokio::test]
+async fn an_untrusted_certificate_is_a_tls_error() {
+    let port = self_signed_server();
+    let url = format!("https://127.0.0.1:{}/", port);
+
+    let error = blog::fetch_html(&client("tls-self-signed"), &url)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(
+            error.downcast_ref::<CrawlError>(),
+            Some(CrawlError::Tls { .. })
+        ),
+        "{:?}",
+        error
+    );
+}
+
+// Only the clients of blogs on a listed host skip verification
+#[tokio::test]
+async fn listed_hosts_accept_invalid_certificates() {
+    let port = self_signed_server();
+    let listed = format!("https://127.0.0.1:{}/", port);
+    let unlisted = format!("https://localhost:{}/", port);
+    let config = load_config(
+        &temp_dir("tls-listed"),
+        json!({
+            "youtube": [],
+            "blogs": [
+                { "name": "listed", "url": listed },
+                { "name": "unlisted", "url": unlisted },
+            ],
+            "http": { "danger_accept_invalid_certs": ["127.0.0.1"] },
+        }),
+    );
+
+    let client = blog::build_client(&config, &config.blogs[0]).unwrap();
+    let body = blog::fetch_html(&client, &listed).await.unwrap();
+    assert!(body.contains("self-signed"), "{}", body);
+
+    let client = blog::build_client(&config, &config.blogs[1]).unwrap();
+    let error = blog::fetch_html(&client, &unlisted).await.unwrap_err();
+    assert!(
+        matches!(
+            error.downcast_ref::<CrawlError>(),
+            Some(CrawlError::Tls { .. })
+        ),
+        "{:?}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn a_refused_connection_is_not() {
+    // Bound and dropped: nothing listens there any more
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = format!("https://127.0.0.1:{}/", port);
+
+    let error = blog::fetch_html(&client("tls-refused"), &url)
+        .await
+        .unwrap_err();
+
+    assert!(error.downcast_ref::<CrawlError>().is_none(), "{:?}", error);
+    assert!(
+        error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect())
+    );
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{MockServer, Response, load_config, temp_dir};
+use michi_matome_crawler::blog::{self, TraceStep, Verdict};
+use michi_matome_crawler::config::Config;
+use michi_matome_crawler::db;
+use rusqlite::Connection;
+use serde_json::json;
+
+const ARTICLE: &str = include_str!("fixtures/article.html");
+const STUB: &str = include_str!("fixtures/stub.html");
+// Same target as STUB, but well past meta_refresh_max_secs
+const SLOW_STUB: &str = r#"<html><head><meta http-equiv="refresh" content="30; url=/posts/pass.html"><title>移転しました</title></head><body></body></html>"#;
+
+fn server() -> MockServer {
+    let server = MockServer::start();
+    server
+        .route(
+            "/robots.txt",
+            Response::new(200, "User-agent: *\nDisallow: /private/\n".to_string())
+                .header("Content-Type", "text/plain"),
+        )
+        .route("/posts/pass.html", Response::html(ARTICLE))
+        .route("/private/pass.html", Response::html(ARTICLE))
+        .route("/old.html", Response::html(STUB))
+        .route("/slow.html", Response::html(SLOW_STUB));
+    server
+}
+
+fn config(server: &MockServer, name: &str) -> Config {
+    load_config(
+        &temp_dir(name),
+        json!({
+            "youtube": [],
+            "blogs": [{ "name": "道の記録", "url": server.url("/") }],
+            "request_delay_ms": 0,
+        }),
+    )
+}
+
+async fn trace(config: &Config, url: &str) -> Vec<TraceStep> {
+    let conn = Connection::open_in_memory().unwrap();
+    db::init(&conn).unwrap();
+    blog::trace(&conn, config, url).await.unwrap()
+}
+
+fn step<'a>(steps: &'a [TraceStep], gate: &str) -> &'a TraceStep {
+    steps
+        .iter()
+        .find(|step| step.gate == gate)
+        .unwrap_or_else(|| panic!("no {} step in {:?}", gate, steps))
+}
+
+#[tokio::test]
+async fn scope_blocks_urls_no_blog_covers() {
+    let server = server();
+    let config = config(&server, "trace-scope");
+
+    // Same server, but a host the blog doesn't list
+    let steps = trace(&config, &server.other_host_url("/posts/pass.html")).await;
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].gate, "scope");
+    assert_eq!(steps[0].verdict, Verdict::Block);
+    assert!(server.requests().is_empty());
+
+    let steps = trace(&config, &server.url("/posts/pass.html")).await;
+    assert_eq!(step(&steps, "scope").verdict, Verdict::Pass);
+    assert_eq!(step(&steps, "page_filters").verdict, Verdict::Pass);
+}
+
+#[tokio::test]
+async fn robots_blocks_before_the_page_is_fetched() {
+    let server = server();
+    let config = config(&server, "trace-robots");
+
+    let steps = trace(&config, &server.url("/private/pass.html")).await;
+    assert_eq!(step(&steps, "robots").verdict, Verdict::Block);
+    assert!(steps.iter().all(|step| step.gate != "fetch"));
+    assert!(server.requests_to("/private/pass.html").is_empty());
+
+    let steps = trace(&config, &server.url("/posts/pass.html")).await;
+    assert_eq!(step(&steps, "robots").verdict, Verdict::Pass);
+    assert_eq!(step(&steps, "fetch").verdict, Verdict::Pass);
+}
+
+// The meta-refresh steps come from the crawl's own resolution: a short delay is
+// followed, one over meta_refresh_max_secs stops the URL
+#[tokio::test]
+async fn meta_refresh_is_followed_only_within_the_delay() {
+    let server = server();
+    let config = config(&server, "trace-refresh");
+
+    let steps = trace(&config, &server.url("/old.html")).await;
+    let refresh = step(&steps, "meta_refresh");
+    assert_eq!(refresh.verdict, Verdict::Pass);
+    assert!(
+        refresh.detail.contains(&server.url("/posts/pass.html")),
+        "{}",
+        refresh.detail
+    );
+    assert_eq!(step(&steps, "page_filters").verdict, Verdict::Pass);
+    assert_eq!(server.requests_to("/posts/pass.html").len(), 1);
+
+    let steps = trace(&config, &server.url("/slow.html")).await;
+    let refresh = step(&steps, "meta_refresh");
+    assert_eq!(refresh.verdict, Verdict::Block);
+    assert!(
+        refresh.detail.contains("meta_refresh_max_secs"),
+        "{}",
+        refresh.detail
+    );
+    assert_eq!(steps.last().unwrap().gate, "meta_refresh");
+    // Not followed, so the target isn't fetched again
+    assert_eq!(server.requests_to("/posts/pass.html").len(), 1);
+}